@@ -0,0 +1,133 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use nes::bus::Bus;
+use nes::cartridge::Cartridge;
+use nes::cpu::{CpuBuilder, CPU};
+use nes::mapper;
+use nes::opcodes::{OpCode, OPCODE_TABLE};
+use std::collections::HashMap;
+
+const NOPS: usize = 10_000;
+const NESTEST_LINES: usize = 1000;
+
+/*
+ * Each workload is reported twice, once as instructions and once as cycles per
+ * second. The counts come from a dry run before measuring.
+ */
+fn bench_workload<F>(c: &mut Criterion, name: &str, setup: F, run: fn(&mut CPU) -> u64)
+where
+    F: Fn() -> CPU,
+{
+    let mut cpu = setup();
+    let instructions = run(&mut cpu);
+    let cycles = cpu.elapsed_cycles() - setup().elapsed_cycles();
+
+    let mut group = c.benchmark_group(name);
+    for (unit, count) in [("instructions", instructions), ("cycles", cycles)] {
+        group.throughput(Throughput::Elements(count));
+        group.bench_function(unit, |b| {
+            b.iter_batched(&setup, |mut cpu| run(black_box(&mut cpu)), BatchSize::SmallInput)
+        });
+    }
+    group.finish();
+}
+
+// straight line NOPs in ROM from $8000, ended by a BRK
+fn nop_cpu() -> CPU {
+    let mut rom = vec![0xea; NOPS];
+    rom.resize(0x8000, 0);
+    rom[0x7ffc] = 0x00;
+    rom[0x7ffd] = 0x80;
+    CpuBuilder::new(Bus::new_with_rom(rom)).build()
+}
+
+/*
+ * 256 passes of a loop touching every addressing mode:
+ * zp,X abs,X abs,Y (ind),Y zp,Y imm (ind,X) A zp abs rel (ind)
+ */
+fn addressing_modes_cpu() -> CPU {
+    let mut rom = vec![0; 0x8000];
+    rom[0x7ffc] = 0x00;
+    rom[0x7ffd] = 0x06;
+    let mut cpu = CpuBuilder::new(Bus::new_with_rom(rom)).build();
+    cpu.load(vec![
+        0xa9, 0x12, 0x85, 0x40, 0xa9, 0x06, 0x85, 0x41, // ($40) -> loop at $0612
+        0xa9, 0x00, 0x85, 0x10, 0xa9, 0x02, 0x85, 0x11, // ($10) -> $0200
+        0xa0, 0x00, // LDY #$00
+        0x98, 0xaa, // loop: TYA, TAX
+        0xb5, 0x20, // LDA $20,X
+        0x9d, 0x00, 0x03, // STA $0300,X
+        0xb9, 0x00, 0x03, // LDA $0300,Y
+        0x91, 0x10, // STA ($10),Y
+        0xb6, 0x20, // LDX $20,Y
+        0xa2, 0x00, // LDX #$00
+        0xa1, 0x10, // LDA ($10,X)
+        0x0a, // ASL A
+        0xe6, 0x12, // INC $12
+        0xee, 0x00, 0x04, // INC $0400
+        0x6d, 0x00, 0x04, // ADC $0400
+        0xc8, // INY
+        0xf0, 0x03, // BEQ +3
+        0x6c, 0x40, 0x00, // JMP ($0040)
+        0x00, // BRK
+    ]);
+    cpu
+}
+
+// returns the number of instructions executed before the BRK
+fn run_to_break(cpu: &mut CPU) -> u64 {
+    let mut instructions = 0;
+    while cpu.step().is_ok() {
+        instructions += 1;
+    }
+    instructions
+}
+
+fn nestest_rom() -> Vec<u8> {
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/nestest.nes")).expect("tests/nestest.nes fixture")
+}
+
+fn nestest_cpu(rom: &[u8]) -> CPU {
+    let cartridge = Cartridge::from_bytes(rom).unwrap();
+    CpuBuilder::new(Bus::new(mapper::from_cartridge(cartridge).unwrap()))
+        .with_pc(0xC000)
+        .with_status(0x24)
+        .with_cycle_count(7)
+        .build()
+}
+
+fn run_nestest(cpu: &mut CPU) -> u64 {
+    for _ in 0..NESTEST_LINES {
+        cpu.step().unwrap();
+    }
+    NESTEST_LINES as u64
+}
+
+fn bench_cpu(c: &mut Criterion) {
+    bench_workload(c, "nop", nop_cpu, run_to_break);
+    bench_workload(c, "addressing_modes", addressing_modes_cpu, run_to_break);
+
+    let rom = nestest_rom();
+    bench_workload(c, "nestest", || nestest_cpu(&rom), run_nestest);
+}
+
+fn bench_opcode_lookup(c: &mut Criterion) {
+    // the HashMap the opcode table used to be
+    let map: HashMap<u8, &'static OpCode> = OPCODE_TABLE
+        .iter()
+        .flatten()
+        .map(|opcode| (opcode.code, *opcode))
+        .collect();
+
+    let mut group = c.benchmark_group("opcode_lookup");
+    group.throughput(Throughput::Elements(256));
+    group.bench_function("table", |b| {
+        b.iter(|| (0..=255u8).filter_map(|code| OPCODE_TABLE[black_box(code) as usize]).count())
+    });
+    group.bench_function("hashmap", |b| {
+        b.iter(|| (0..=255u8).filter_map(|code| map.get(&black_box(code))).count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_cpu, bench_opcode_lookup);
+criterion_main!(benches);
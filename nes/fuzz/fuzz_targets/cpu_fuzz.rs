@@ -0,0 +1,32 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nes::bus::Bus;
+use nes::cpu::CpuBuilder;
+
+// the program is copied to $0600, only the RAM up to $07FF is writable
+const MAX_PROGRAM_LEN: usize = 0x0800 - 0x0600;
+const MAX_STEPS: usize = 1000;
+
+/*
+ * Runs arbitrary bytes as a program. Errors such as BRK, KIL or an unknown
+ * opcode end the run normally, any panic is a finding.
+ * Inputs that once crashed are kept as corpus/cpu_fuzz/regression-* seeds.
+ *
+ *     cargo +nightly fuzz run cpu_fuzz
+ */
+fuzz_target!(|data: &[u8]| {
+    let mut rom = vec![0; 0x8000];
+    rom[0x7ffc] = 0x00;
+    rom[0x7ffd] = 0x06;
+    let mut cpu = CpuBuilder::new(Bus::new_with_rom(rom)).build();
+    // unmapped reads and writes are expected from random programs
+    cpu.bus_mut().set_unmapped_handler(None);
+    cpu.load(data[..data.len().min(MAX_PROGRAM_LEN)].to_vec());
+
+    for _ in 0..MAX_STEPS {
+        if cpu.step().is_err() {
+            break;
+        }
+    }
+});
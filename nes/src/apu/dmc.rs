@@ -0,0 +1,298 @@
+use crate::apu::IrqSignal;
+use crate::bus::Mem;
+use crate::region::Region;
+use serde::{Deserialize, Serialize};
+
+// timer periods in CPU cycles (NTSC)
+const RATE_TABLE: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+// PAL, Dendy uses the NTSC ones
+const PAL_RATE_TABLE: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
+
+// the CPU is stalled while the DMC fetches a sample byte
+const FETCH_STALL_CYCLES: u64 = 4;
+
+/*
+ * Delta modulation channel
+ *   $4010 IL-- RRRR   IRQ enable, loop, rate index
+ *   $4011 -DDD DDDD   direct load of the output level
+ *   $4012 AAAA AAAA   sample address, $C000 + A * 64
+ *   $4013 LLLL LLLL   sample length, L * 16 + 1 bytes
+ * Every output clock moves the 7-bit level up or down by 2 depending on the next bit of the sample.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct DmcChannel {
+    pub sample_addr: u16,
+    pub sample_len: u16,
+    pub current_addr: u16,
+    pub bytes_remaining: u16,
+    pub output_level: u8,
+    pub shift_register: u8,
+    pub bits_remaining: u8,
+    pub silence: bool,
+    pub irq_flag: bool,
+    pub loop_flag: bool,
+
+    irq_enabled: bool,
+    rate: u16,
+    timer: u16,
+    sample_buffer: Option<u8>,
+    // cycles the CPU lost to sample fetches, not yet charged to it
+    stall_cycles: u64,
+    region: Region,
+}
+
+fn rate_table(region: Region) -> &'static [u16; 16] {
+    match region {
+        Region::NTSC | Region::Dendy => &RATE_TABLE,
+        Region::PAL => &PAL_RATE_TABLE,
+    }
+}
+
+impl Default for DmcChannel {
+    fn default() -> Self {
+        DmcChannel::new()
+    }
+}
+
+impl DmcChannel {
+    pub fn new() -> Self {
+        DmcChannel::new_with_region(Region::NTSC)
+    }
+
+    pub fn new_with_region(region: Region) -> Self {
+        DmcChannel {
+            sample_addr: 0xC000,
+            sample_len: 1,
+            current_addr: 0xC000,
+            bytes_remaining: 0,
+            output_level: 0,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            irq_flag: false,
+            loop_flag: false,
+            irq_enabled: false,
+            rate: rate_table(region)[0],
+            timer: 0,
+            sample_buffer: None,
+            stall_cycles: 0,
+            region,
+        }
+    }
+
+    // register 0-3 of the channel
+    pub fn write_register(&mut self, register: u16, val: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = val & 0b1000_0000 != 0;
+                self.loop_flag = val & 0b0100_0000 != 0;
+                self.rate = rate_table(self.region)[(val & 0b1111) as usize];
+                if !self.irq_enabled {
+                    self.irq_flag = false;
+                }
+            }
+            1 => self.output_level = val & 0b0111_1111,
+            2 => self.sample_addr = 0xC000 + val as u16 * 64,
+            _ => self.sample_len = val as u16 * 16 + 1,
+        }
+    }
+
+    // $4015 bit 4, restarts the sample if it has finished
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_addr = self.sample_addr;
+        self.bytes_remaining = self.sample_len;
+    }
+
+    pub fn take_stall_cycles(&mut self) -> u64 {
+        core::mem::take(&mut self.stall_cycles)
+    }
+
+    /*
+     * Clocked every CPU cycle. Fetches the next sample byte through mem when the buffer
+     * is empty, returns an IRQ when a non-looping sample has been fully read.
+     */
+    pub fn tick(&mut self, mem: &mut impl Mem) -> Option<IrqSignal> {
+        let irq = self.fill_sample_buffer(mem);
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        } else {
+            self.timer = self.rate - 1;
+            self.clock_output();
+        }
+
+        irq
+    }
+
+    fn fill_sample_buffer(&mut self, mem: &mut impl Mem) -> Option<IrqSignal> {
+        if self.sample_buffer.is_some() || self.bytes_remaining == 0 {
+            return None;
+        }
+
+        self.sample_buffer = Some(mem.mem_read(self.current_addr));
+        self.stall_cycles += FETCH_STALL_CYCLES;
+        // the address wraps around to $8000
+        self.current_addr = self.current_addr.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+                return Some(IrqSignal);
+            }
+        }
+        None
+    }
+
+    fn clock_output(&mut self) {
+        if !self.silence {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    // 0.0 - 127.0
+    pub fn sample(&self) -> f32 {
+        self.output_level as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::bus::Bus;
+
+    fn sample_bus(sample: &[u8]) -> Bus {
+        let mut rom = vec![0; 0x8000];
+        rom[0x4000..0x4000 + sample.len()].copy_from_slice(sample);
+        Bus::new_with_rom(rom)
+    }
+
+    // output levels after every output clock
+    fn output_levels(dmc: &mut DmcChannel, bus: &mut Bus, clocks: usize) -> Vec<u8> {
+        let mut levels = vec![];
+        for _ in 0..clocks {
+            for _ in 0..dmc.rate {
+                dmc.tick(bus);
+            }
+            levels.push(dmc.output_level);
+        }
+        levels
+    }
+
+    #[test]
+    fn test_one_byte_sample() {
+        let mut bus = sample_bus(&[0b0000_1111]);
+        let mut dmc = DmcChannel::new();
+        dmc.write_register(0, 0x0F);
+        dmc.write_register(1, 64);
+        dmc.set_enabled(true);
+
+        let levels = output_levels(&mut dmc, &mut bus, 17);
+        // silent until the first output cycle ends, then 4 steps up and 4 down
+        assert_eq!(levels[..8], [64; 8]);
+        assert_eq!(levels[8..], [66, 68, 70, 72, 70, 68, 66, 64, 64]);
+        assert_eq!(dmc.bytes_remaining, 0);
+        assert_eq!(dmc.silence, true);
+        assert_eq!(dmc.take_stall_cycles(), 4);
+    }
+
+    #[test]
+    fn test_pal_rates() {
+        let mut dmc = DmcChannel::new_with_region(Region::PAL);
+        assert_eq!(dmc.rate, 398);
+        dmc.write_register(0, 0x0F);
+        assert_eq!(dmc.rate, 50);
+
+        let mut dmc = DmcChannel::new_with_region(Region::Dendy);
+        dmc.write_register(0, 0x0F);
+        assert_eq!(dmc.rate, 54);
+    }
+
+    #[test]
+    fn test_output_level_is_clamped() {
+        let mut bus = sample_bus(&[0xFF]);
+        let mut dmc = DmcChannel::new();
+        dmc.write_register(0, 0x0F);
+        dmc.write_register(1, 124);
+        dmc.set_enabled(true);
+        let levels = output_levels(&mut dmc, &mut bus, 16);
+        assert_eq!(levels[8..], [126; 8]);
+
+        let mut bus = sample_bus(&[0x00]);
+        let mut dmc = DmcChannel::new();
+        dmc.write_register(0, 0x0F);
+        dmc.write_register(1, 3);
+        dmc.set_enabled(true);
+        let levels = output_levels(&mut dmc, &mut bus, 16);
+        assert_eq!(levels[8..], [1; 8]);
+    }
+
+    #[test]
+    fn test_irq_at_end_of_sample() {
+        let mut bus = sample_bus(&[0x00]);
+        let mut dmc = DmcChannel::new();
+        dmc.write_register(0, 0x8F);
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.tick(&mut bus), Some(IrqSignal));
+        assert_eq!(dmc.irq_flag, true);
+        assert_eq!(dmc.tick(&mut bus), None);
+
+        dmc.set_enabled(false);
+        assert_eq!(dmc.irq_flag, false);
+    }
+
+    #[test]
+    fn test_loop_restarts_sample() {
+        let mut bus = sample_bus(&[0xFF]);
+        let mut dmc = DmcChannel::new();
+        dmc.write_register(0, 0xCF);
+        dmc.set_enabled(true);
+
+        output_levels(&mut dmc, &mut bus, 20);
+        assert_eq!(dmc.bytes_remaining, 1);
+        assert_eq!(dmc.irq_flag, false);
+        assert_eq!(dmc.take_stall_cycles(), 12);
+    }
+
+    #[test]
+    fn test_sample_address_and_length() {
+        let mut dmc = DmcChannel::new();
+        dmc.write_register(2, 0x10);
+        dmc.write_register(3, 0x02);
+        dmc.set_enabled(true);
+        assert_eq!(dmc.current_addr, 0xC400);
+        assert_eq!(dmc.bytes_remaining, 33);
+    }
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+/*
+ * Volume envelope shared by the pulse and noise channels.
+ * Either outputs a constant volume or decays from 15 to 0, one step every volume + 1 quarter frames.
+ */
+#[derive(Default, Serialize, Deserialize)]
+pub struct Envelope {
+    pub start: bool,
+    pub loop_flag: bool,
+    pub constant_volume: bool,
+    // constant volume, or the divider period of the decay
+    pub volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    pub fn tick(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+            return;
+        }
+
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.volume;
+        if self.decay > 0 {
+            self.decay -= 1;
+        } else if self.loop_flag {
+            self.decay = 15;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant_volume { self.volume } else { self.decay }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_decay() {
+        let mut envelope = Envelope { start: true, volume: 1, ..Default::default() };
+        envelope.tick();
+        assert_eq!(envelope.output(), 15);
+
+        // one step every two quarter frames
+        let levels: Vec<u8> = (0..4).map(|_| { envelope.tick(); envelope.output() }).collect();
+        assert_eq!(levels, vec![15, 14, 14, 13]);
+    }
+
+    #[test]
+    fn test_decay_stops_or_loops_at_zero() {
+        let mut envelope = Envelope { start: true, ..Default::default() };
+        for _ in 0..20 {
+            envelope.tick();
+        }
+        assert_eq!(envelope.output(), 0);
+
+        envelope.loop_flag = true;
+        envelope.tick();
+        assert_eq!(envelope.output(), 15);
+    }
+
+    #[test]
+    fn test_constant_volume() {
+        let mut envelope = Envelope { start: true, constant_volume: true, volume: 7, ..Default::default() };
+        envelope.tick();
+        envelope.tick();
+        assert_eq!(envelope.output(), 7);
+    }
+}
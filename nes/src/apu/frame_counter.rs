@@ -0,0 +1,187 @@
+use crate::region::Region;
+use serde::{Deserialize, Serialize};
+
+// CPU cycles at which each step fires, half APU cycles: 3728.5, 7456.5, 11185.5, 14914.5 and 18640.5
+const FOUR_STEP_SEQUENCE: [u32; 4] = [7457, 14913, 22371, 29829];
+const FIVE_STEP_SEQUENCE: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const FOUR_STEP_PERIOD: u32 = 29830;
+const FIVE_STEP_PERIOD: u32 = 37282;
+
+// PAL, from 4156.5, 8313.5, 12469.5, 16626.5 and 20782.5 APU cycles. Dendy uses the NTSC ones
+const PAL_FOUR_STEP_SEQUENCE: [u32; 4] = [8313, 16627, 24939, 33253];
+const PAL_FIVE_STEP_SEQUENCE: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+const PAL_FOUR_STEP_PERIOD: u32 = 33254;
+const PAL_FIVE_STEP_PERIOD: u32 = 41566;
+
+// the clocks a frame counter step sends to the channels
+#[derive(Debug, PartialEq, Default)]
+pub struct FrameCounterEvent {
+    pub quarter_frame: bool,
+    pub half_frame: bool,
+    pub irq: bool,
+}
+
+/*
+ * Frame counter
+ *   $4017 MI-- ----   mode (0: 4-step, 1: 5-step), IRQ inhibit
+ * Quarter frames clock the envelopes, half frames the length counters and sweep units.
+ * The 4-step sequence raises an IRQ on its last step.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct FrameCounter {
+    pub mode: u8,
+    pub inhibit_irq: bool,
+    pub step: u8,
+    // CPU cycles since the sequence started
+    pub cycles: u32,
+    pub irq_flag: bool,
+    region: Region,
+}
+
+impl Default for FrameCounter {
+    fn default() -> Self {
+        FrameCounter::new()
+    }
+}
+
+impl FrameCounter {
+    pub fn new() -> Self {
+        FrameCounter::new_with_region(Region::NTSC)
+    }
+
+    pub fn new_with_region(region: Region) -> Self {
+        FrameCounter {
+            mode: 0,
+            inhibit_irq: false,
+            step: 0,
+            cycles: 0,
+            irq_flag: false,
+            region,
+        }
+    }
+
+    // $4017, switching to the 5-step mode clocks the channels right away
+    pub fn write(&mut self, val: u8) -> FrameCounterEvent {
+        self.mode = val >> 7;
+        self.inhibit_irq = val & 0b0100_0000 != 0;
+        if self.inhibit_irq {
+            self.irq_flag = false;
+        }
+        self.step = 0;
+        self.cycles = 0;
+
+        let clock = self.mode == 1;
+        FrameCounterEvent { quarter_frame: clock, half_frame: clock, irq: false }
+    }
+
+    // clocked every CPU cycle
+    pub fn tick(&mut self) -> FrameCounterEvent {
+        self.cycles += 1;
+
+        let (sequence, period): (&[u32], u32) = match (self.region, self.mode) {
+            (Region::PAL, 0) => (&PAL_FOUR_STEP_SEQUENCE, PAL_FOUR_STEP_PERIOD),
+            (Region::PAL, _) => (&PAL_FIVE_STEP_SEQUENCE, PAL_FIVE_STEP_PERIOD),
+            (_, 0) => (&FOUR_STEP_SEQUENCE, FOUR_STEP_PERIOD),
+            (_, _) => (&FIVE_STEP_SEQUENCE, FIVE_STEP_PERIOD),
+        };
+        if self.cycles == period {
+            self.cycles = 0;
+        }
+        if self.cycles != sequence[self.step as usize] {
+            return FrameCounterEvent::default();
+        }
+
+        let last = self.step as usize == sequence.len() - 1;
+        let event = FrameCounterEvent {
+            // the fourth step of the 5-step sequence does nothing
+            quarter_frame: !(self.mode == 1 && self.step == 3),
+            half_frame: self.step == 1 || last,
+            irq: self.mode == 0 && last && !self.inhibit_irq,
+        };
+        if event.irq {
+            self.irq_flag = true;
+        }
+
+        self.step = if last { 0 } else { self.step + 1 };
+        event
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // (cycle, quarter, half, irq) for every cycle with a clock
+    fn run(frame_counter: &mut FrameCounter, cycles: u32) -> Vec<(u32, bool, bool, bool)> {
+        let mut events = vec![];
+        for cycle in 1..=cycles {
+            let event = frame_counter.tick();
+            if event != FrameCounterEvent::default() {
+                events.push((cycle, event.quarter_frame, event.half_frame, event.irq));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_four_step_sequence() {
+        let mut frame_counter = FrameCounter::new();
+        let events = run(&mut frame_counter, 2 * 29830);
+        assert_eq!(events, vec![
+            (7457, true, false, false),
+            (14913, true, true, false),
+            (22371, true, false, false),
+            (29829, true, true, true),
+            (29830 + 7457, true, false, false),
+            (29830 + 14913, true, true, false),
+            (29830 + 22371, true, false, false),
+            (29830 + 29829, true, true, true),
+        ]);
+        assert_eq!(frame_counter.irq_flag, true);
+    }
+
+    #[test]
+    fn test_five_step_sequence() {
+        let mut frame_counter = FrameCounter::new();
+        let event = frame_counter.write(0x80);
+        assert_eq!(event, FrameCounterEvent { quarter_frame: true, half_frame: true, irq: false });
+
+        let events = run(&mut frame_counter, 37282 + 7457);
+        assert_eq!(events, vec![
+            (7457, true, false, false),
+            (14913, true, true, false),
+            (22371, true, false, false),
+            (37281, true, true, false),
+            (37282 + 7457, true, false, false),
+        ]);
+        assert_eq!(frame_counter.irq_flag, false);
+    }
+
+    #[test]
+    fn test_pal_four_step_sequence() {
+        let mut frame_counter = FrameCounter::new_with_region(Region::PAL);
+        let events = run(&mut frame_counter, 33254 + 8313);
+        assert_eq!(events, vec![
+            (8313, true, false, false),
+            (16627, true, true, false),
+            (24939, true, false, false),
+            (33253, true, true, true),
+            (33254 + 8313, true, false, false),
+        ]);
+    }
+
+    #[test]
+    fn test_irq_inhibit() {
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.write(0x40);
+        let events = run(&mut frame_counter, 29830);
+        assert_eq!(events.last(), Some(&(29829, true, true, false)));
+        assert_eq!(frame_counter.irq_flag, false);
+
+        // setting the inhibit flag clears a pending IRQ
+        frame_counter.irq_flag = true;
+        frame_counter.write(0x40);
+        assert_eq!(frame_counter.irq_flag, false);
+    }
+}
@@ -0,0 +1,45 @@
+/*
+ * Non-linear mixer of the five channels, every input is the channel's raw level
+ * (pulse, triangle and noise 0-15, DMC 0-127). The output is in 0.0 - 1.0.
+ */
+pub fn mix(pulse1: f32, pulse2: f32, triangle: f32, noise: f32, dmc: f32) -> f32 {
+    let pulse = pulse1 + pulse2;
+    let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+
+    let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+    let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+
+    pulse_out + tnd_out
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.0001, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_silence() {
+        assert_eq!(mix(0.0, 0.0, 0.0, 0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_pulse() {
+        assert_close(mix(15.0, 0.0, 0.0, 0.0, 0.0), 0.14938);
+        assert_close(mix(15.0, 15.0, 0.0, 0.0, 0.0), 0.25848);
+    }
+
+    #[test]
+    fn test_tnd() {
+        assert_close(mix(0.0, 0.0, 0.0, 0.0, 127.0), 0.57426);
+        assert_close(mix(0.0, 0.0, 15.0, 15.0, 127.0), 0.74152);
+    }
+
+    #[test]
+    fn test_full_volume() {
+        assert_close(mix(15.0, 15.0, 15.0, 15.0, 127.0), 0.25848 + 0.74152);
+    }
+}
@@ -0,0 +1,286 @@
+pub mod dmc;
+mod envelope;
+pub mod frame_counter;
+pub mod mixer;
+pub mod pulse;
+
+pub use dmc::DmcChannel;
+pub use frame_counter::{FrameCounter, FrameCounterEvent};
+pub use pulse::PulseChannel;
+
+use crate::bus::Mem;
+use crate::region::Region;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/*
+ * Audio Processing Unit
+ *   $4000-$4003 pulse 1
+ *   $4010-$4013 DMC
+ *   $4015       channel enable (write), length counter and IRQ status (read)
+ *   $4017       frame counter
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Apu {
+    pub pulse1: PulseChannel,
+    pub dmc: DmcChannel,
+    pub frame_counter: FrameCounter,
+    // CPU cycles, the pulse timers run at half the CPU clock
+    cycles: u64,
+
+    // mixed output, sampled every cycles_per_sample CPU cycles.
+    // Holds at most one second, older samples are dropped when nobody drains them
+    #[serde(skip)]
+    samples: VecDeque<f32>,
+    sample_rate: u32,
+    cycles_per_sample: f64,
+    sample_timer: f64,
+    region: Region,
+}
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+// raised by the DMC at the end of a sample and by the 4-step frame counter
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct IrqSignal;
+
+// length counter values, indexed by bits 3-7 of the channel's last register
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+impl Default for Apu {
+    fn default() -> Self {
+        Apu::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu::new_with_region(Region::NTSC)
+    }
+
+    // the region sets the DMC rates, the frame counter timing and the CPU clock the samples are taken against
+    pub fn new_with_region(region: Region) -> Self {
+        Apu {
+            pulse1: PulseChannel::new(),
+            dmc: DmcChannel::new_with_region(region),
+            frame_counter: FrameCounter::new_with_region(region),
+            cycles: 0,
+            samples: VecDeque::new(),
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            cycles_per_sample: region.cpu_clock_hz() / DEFAULT_SAMPLE_RATE as f64,
+            sample_timer: 0.0,
+            region,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.cycles_per_sample = self.region.cpu_clock_hz() / sample_rate as f64;
+    }
+
+    // the samples produced since the last call, for the audio front end
+    pub fn drain_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+
+    // 0.0 - 1.0, the channels that are not emulated yet are silent
+    pub fn output(&self) -> f32 {
+        mixer::mix(self.pulse1.sample(), 0.0, 0.0, 0.0, self.dmc.sample())
+    }
+
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write_register(addr - 0x4000, val),
+            0x4010..=0x4013 => self.dmc.write_register(addr - 0x4010, val),
+            0x4015 => {
+                self.pulse1.set_enabled(val & 0b0000_0001 != 0);
+                self.dmc.set_enabled(val & 0b0001_0000 != 0);
+            }
+            0x4017 => {
+                let event = self.frame_counter.write(val);
+                self.clock_frame(event);
+            }
+            _ => {}
+        }
+    }
+
+    // $4015
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        if self.pulse1.length_counter > 0 {
+            status |= 0b0000_0001;
+        }
+        if self.dmc.bytes_remaining > 0 {
+            status |= 0b0001_0000;
+        }
+        if self.frame_counter.irq_flag {
+            status |= 0b0100_0000;
+        }
+        if self.dmc.irq_flag {
+            status |= 0b1000_0000;
+        }
+        // reading clears the frame IRQ, not the DMC one
+        self.frame_counter.irq_flag = false;
+        status
+    }
+
+    // clocked every CPU cycle, mem is where the DMC fetches its samples from
+    pub fn tick(&mut self, mem: &mut impl Mem) -> Option<IrqSignal> {
+        self.cycles += 1;
+        if self.cycles.is_multiple_of(2) {
+            self.pulse1.tick_timer();
+        }
+
+        let event = self.frame_counter.tick();
+        let frame_irq = event.irq;
+        self.clock_frame(event);
+
+        let dmc_irq = self.dmc.tick(mem);
+
+        self.sample_timer += 1.0;
+        if self.sample_timer >= self.cycles_per_sample {
+            self.sample_timer -= self.cycles_per_sample;
+            self.samples.push_back(self.output());
+            if self.samples.len() > self.sample_rate as usize {
+                self.samples.pop_front();
+            }
+        }
+
+        if frame_irq { Some(IrqSignal) } else { dmc_irq }
+    }
+
+    fn clock_frame(&mut self, event: FrameCounterEvent) {
+        if event.quarter_frame {
+            self.pulse1.tick_quarter_frame();
+        }
+        if event.half_frame {
+            self.pulse1.tick_half_frame();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::bus::Bus;
+
+    fn mem() -> Bus {
+        Bus::new_with_rom(vec![])
+    }
+
+    #[test]
+    fn test_status_reports_length_counter() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.read_status(), 0x00);
+
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.read_status(), 0x01);
+
+        // disabling the channel clears its length counter
+        apu.write_register(0x4015, 0x00);
+        assert_eq!(apu.read_status(), 0x00);
+    }
+
+    #[test]
+    fn test_frame_counter_clocks_length_counter() {
+        let mut apu = Apu::new();
+        let mut mem = mem();
+        apu.write_register(0x4015, 0x01);
+        // length 10, two half frames per 4-step frame
+        apu.write_register(0x4003, 0b0000_0000);
+        for _ in 0..29830 {
+            apu.tick(&mut mem);
+        }
+        assert_eq!(apu.pulse1.length_counter, 8);
+
+        // the 5-step mode clocks a half frame on the write
+        apu.write_register(0x4017, 0xC0);
+        assert_eq!(apu.pulse1.length_counter, 7);
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let mut apu = Apu::new();
+        let mut mem = mem();
+        let irqs = (0..29830).filter(|_| apu.tick(&mut mem).is_some()).count();
+        assert_eq!(irqs, 1);
+        assert_eq!(apu.read_status(), 0x40);
+        assert_eq!(apu.read_status(), 0x00);
+    }
+
+    #[test]
+    fn test_one_second_of_samples() {
+        let mut apu = Apu::new();
+        let mut mem = mem();
+        for _ in 0..Region::NTSC.cpu_clock_hz() as u32 {
+            apu.tick(&mut mem);
+        }
+        let samples = apu.drain_samples();
+        assert!((44_099..=44_101).contains(&samples.len()));
+        assert_eq!(apu.drain_samples().len(), 0);
+    }
+
+    #[test]
+    fn test_one_second_of_samples_pal() {
+        let mut apu = Apu::new_with_region(Region::PAL);
+        let mut mem = mem();
+        for _ in 0..Region::PAL.cpu_clock_hz() as u32 {
+            apu.tick(&mut mem);
+        }
+        assert!((44_099..=44_101).contains(&apu.drain_samples().len()));
+    }
+
+    #[test]
+    fn test_undrained_samples_are_capped() {
+        let mut apu = Apu::new();
+        let mut mem = mem();
+        apu.set_sample_rate(1_000);
+        for _ in 0..Region::NTSC.cpu_clock_hz() as u32 * 2 {
+            apu.tick(&mut mem);
+        }
+        assert_eq!(apu.drain_samples().len(), 1_000);
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut apu = Apu::new();
+        let mut mem = mem();
+        apu.set_sample_rate(48_000);
+        for _ in 0..Region::NTSC.cpu_clock_hz() as u32 / 10 {
+            apu.tick(&mut mem);
+        }
+        assert!((4_799..=4_801).contains(&apu.drain_samples().len()));
+    }
+
+    #[test]
+    fn test_samples_follow_the_channels() {
+        let mut apu = Apu::new();
+        let mut mem = mem();
+        apu.write_register(0x4011, 127);
+        for _ in 0..100 {
+            apu.tick(&mut mem);
+        }
+        let samples = apu.drain_samples();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0], mixer::mix(0.0, 0.0, 0.0, 0.0, 127.0));
+    }
+
+    #[test]
+    fn test_status_reports_dmc() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4010, 0x80);
+        apu.write_register(0x4015, 0x10);
+        assert_eq!(apu.read_status(), 0x10);
+
+        apu.dmc.irq_flag = true;
+        apu.dmc.bytes_remaining = 0;
+        assert_eq!(apu.read_status(), 0x80);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::LENGTH_TABLE;
+use serde::{Deserialize, Serialize};
+
+// waveforms for the 12.5%, 25%, 50% and 25% negated duty cycles
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/*
+ * Sweep unit, bends the timer period up or down every period + 1 half frames
+ */
+#[derive(Default, Serialize, Deserialize)]
+pub struct Sweep {
+    pub enabled: bool,
+    pub period: u8,
+    pub negate: bool,
+    pub shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+impl Sweep {
+    // pulse 1 negates with one's complement, the change is one larger than on pulse 2
+    fn target_period(&self, timer_period: u16) -> u16 {
+        let change = timer_period >> self.shift;
+        if self.negate {
+            timer_period.saturating_sub(change + 1)
+        } else {
+            timer_period + change
+        }
+    }
+
+    fn muting(&self, timer_period: u16) -> bool {
+        timer_period < 8 || self.target_period(timer_period) > 0x7FF
+    }
+}
+
+/*
+ * Pulse channel
+ *   $4000 DDLC VVVV   duty, length counter halt / envelope loop, constant volume, volume / envelope period
+ *   $4001 EPPP NSSS   sweep enable, period, negate, shift
+ *   $4002 TTTT TTTT   timer low
+ *   $4003 LLLL LTTT   length counter load, timer high
+ */
+#[derive(Serialize, Deserialize)]
+pub struct PulseChannel {
+    pub duty: u8,
+    pub length_counter: u8,
+    pub envelope: Envelope,
+    pub sweep: Sweep,
+    pub timer: u16,
+    pub timer_period: u16,
+    pub sequencer: u8,
+    // cleared through $4015, holds the length counter at 0
+    pub enabled: bool,
+}
+
+impl Default for PulseChannel {
+    fn default() -> Self {
+        PulseChannel::new()
+    }
+}
+
+impl PulseChannel {
+    pub fn new() -> Self {
+        PulseChannel {
+            duty: 0,
+            length_counter: 0,
+            envelope: Envelope::default(),
+            sweep: Sweep::default(),
+            timer: 0,
+            timer_period: 0,
+            sequencer: 0,
+            enabled: false,
+        }
+    }
+
+    // register 0-3 of the channel
+    pub fn write_register(&mut self, register: u16, val: u8) {
+        match register {
+            0 => {
+                self.duty = val >> 6;
+                self.envelope.loop_flag = val & 0b0010_0000 != 0;
+                self.envelope.constant_volume = val & 0b0001_0000 != 0;
+                self.envelope.volume = val & 0b0000_1111;
+            }
+            1 => {
+                self.sweep.enabled = val & 0b1000_0000 != 0;
+                self.sweep.period = (val >> 4) & 0b111;
+                self.sweep.negate = val & 0b0000_1000 != 0;
+                self.sweep.shift = val & 0b0000_0111;
+                self.sweep.reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | val as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((val as u16 & 0b111) << 8);
+                if self.enabled {
+                    self.length_counter = LENGTH_TABLE[(val >> 3) as usize];
+                }
+                self.sequencer = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length_counter = 0;
+        }
+    }
+
+    // clocked every APU cycle (every other CPU cycle)
+    pub fn tick_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequencer = (self.sequencer + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn tick_quarter_frame(&mut self) {
+        self.envelope.tick();
+    }
+
+    pub fn tick_half_frame(&mut self) {
+        // the envelope loop flag doubles as the length counter halt flag
+        if self.length_counter > 0 && !self.envelope.loop_flag {
+            self.length_counter -= 1;
+        }
+
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.sweep.muting(self.timer_period) {
+            self.timer_period = self.sweep.target_period(self.timer_period);
+        }
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    // 0.0 - 15.0
+    pub fn sample(&self) -> f32 {
+        if self.length_counter == 0
+            || DUTY_SEQUENCES[self.duty as usize][self.sequencer as usize] == 0
+            || self.sweep.muting(self.timer_period)
+        {
+            return 0.0;
+        }
+        self.envelope.output() as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn channel(duty: u8, timer_period: u16) -> PulseChannel {
+        let mut pulse = PulseChannel::new();
+        pulse.set_enabled(true);
+        // constant volume 15
+        pulse.write_register(0, duty << 6 | 0b0001_1111);
+        pulse.write_register(2, timer_period as u8);
+        pulse.write_register(3, (timer_period >> 8) as u8);
+        pulse
+    }
+
+    #[test]
+    fn test_duty_sequences() {
+        for duty in 0..4 {
+            let mut pulse = channel(duty, 8);
+            let mut output = vec![];
+            for _ in 0..8 {
+                output.push(pulse.sample());
+                // the sequencer steps every timer_period + 1 APU cycles
+                for _ in 0..9 {
+                    pulse.tick_timer();
+                }
+            }
+            let expected: Vec<f32> = DUTY_SEQUENCES[duty as usize].iter().map(|&bit| bit as f32 * 15.0).collect();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_length_counter_silences_channel() {
+        // length index 0 loads 10 half frames
+        let mut pulse = channel(3, 0x100);
+        assert_eq!(pulse.length_counter, 10);
+        for _ in 0..9 {
+            pulse.tick_half_frame();
+        }
+        assert_eq!(pulse.sample(), 15.0);
+        pulse.tick_half_frame();
+        assert_eq!(pulse.length_counter, 0);
+        assert_eq!(pulse.sample(), 0.0);
+    }
+
+    #[test]
+    fn test_length_counter_halt() {
+        let mut pulse = channel(3, 0x100);
+        pulse.write_register(0, 0b1111_1111);
+        for _ in 0..20 {
+            pulse.tick_half_frame();
+        }
+        assert_eq!(pulse.length_counter, 10);
+    }
+
+    #[test]
+    fn test_length_counter_needs_channel_enabled() {
+        let mut pulse = PulseChannel::new();
+        pulse.write_register(3, 0b0000_1000);
+        assert_eq!(pulse.length_counter, 0);
+    }
+
+    #[test]
+    fn test_envelope_decay() {
+        let mut pulse = channel(3, 0x100);
+        // decaying envelope, one step per quarter frame
+        pulse.write_register(0, 0b1100_0000);
+        pulse.write_register(3, 0x01);
+        pulse.tick_quarter_frame();
+        assert_eq!(pulse.sample(), 15.0);
+        pulse.tick_quarter_frame();
+        pulse.tick_quarter_frame();
+        assert_eq!(pulse.sample(), 13.0);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let mut pulse = channel(3, 0x100);
+        // enabled, period 0, shift 1
+        pulse.write_register(1, 0b1000_0001);
+        pulse.tick_half_frame();
+        assert_eq!(pulse.timer_period, 0x180);
+
+        // negated on pulse 1: 0x180 - 0xC0 - 1
+        pulse.write_register(1, 0b1000_1001);
+        pulse.tick_half_frame();
+        assert_eq!(pulse.timer_period, 0xBF);
+    }
+
+    #[test]
+    fn test_sweep_mutes_out_of_range_periods() {
+        let pulse = channel(3, 0x7F0);
+        // the target period 0x7F0 + 0x7F0 is out of range even with the sweep disabled
+        assert_eq!(pulse.sample(), 0.0);
+        let pulse = channel(3, 7);
+        assert_eq!(pulse.sample(), 0.0);
+    }
+}
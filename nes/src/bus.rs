@@ -1,10 +1,30 @@
+use crate::apu::{Apu, IrqSignal};
+use crate::controller::{Controller, Zapper};
+use crate::mapper::{Mapper, Mapper0};
+use crate::ppu::{NmiSignal, Ppu};
+use crate::region::Region;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
+
 pub trait Mem {
-    fn mem_read(&self, addr: u16) -> u8;
+    fn mem_read(&mut self, addr: u16) -> u8;
     fn mem_write(&mut self, addr: u16, data: u8);
 
-    fn mem_read_u16(&self, pos: u16) -> u16 {
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
         let lo = self.mem_read(pos) as u16;
-        let hi = self.mem_read(pos + 1) as u16;
+        let hi = self.mem_read(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    // the high byte comes from the same page, $xxFF pairs with $xx00 instead of the next page
+    fn mem_read_u16_wrap_page(&mut self, pos: u16) -> u16 {
+        let lo = self.mem_read(pos) as u16;
+        let hi = self.mem_read((pos & 0xFF00) | (pos.wrapping_add(1) & 0x00FF)) as u16;
         (hi << 8) | lo
     }
 
@@ -12,19 +32,303 @@ pub trait Mem {
         let hi = (data >> 8) as u8;
         let lo = (data & 0xff) as u8;
         self.mem_write(pos, lo);
-        self.mem_write(pos + 1, hi);
+        self.mem_write(pos.wrapping_add(1), hi);
+    }
+
+    // len bytes from addr on, wrapping from $FFFF to $0000
+    fn bulk_read(&mut self, addr: u16, len: usize) -> Vec<u8> {
+        (0..len).map(|i| self.mem_read(addr.wrapping_add(i as u16))).collect()
+    }
+
+    // a write that also reaches ROM, for loading programs and patching vectors
+    fn poke(&mut self, addr: u16, data: u8) {
+        self.mem_write(addr, data)
+    }
+
+    // a read for debuggers that leaves I/O registers alone: nothing is latched, shifted or acknowledged
+    fn peek(&mut self, addr: u16) -> u8 {
+        self.mem_read(addr)
+    }
+
+    /*
+     * Called by the CPU after every instruction. The defaults are for a memory
+     * with nothing else attached: no clocked devices, no DMA, no interrupts.
+     */
+    fn tick(&mut self, _cycles: u64) {}
+
+    fn take_dma_stall_cycles(&mut self) -> u64 {
+        0
+    }
+
+    fn poll_nmi_status(&mut self) -> Option<NmiSignal> {
+        None
+    }
+
+    fn poll_irq_status(&mut self) -> Option<IrqSignal> {
+        None
+    }
+
+    // the TV system of the devices attached
+    fn region(&self) -> Region {
+        Region::NTSC
+    }
+}
+
+/*
+ * Memory behind a reference or a box, including dyn Mem, is memory as well, e.g. a
+ * CPU<Box<dyn Mem>> or a &mut dyn Mem handed to disasm. Every method is forwarded
+ * so the overrides of the memory behind it still apply.
+ */
+impl<M: Mem + ?Sized> Mem for &mut M {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        (**self).mem_read(addr)
+    }
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        (**self).mem_write(addr, data)
+    }
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
+        (**self).mem_read_u16(pos)
+    }
+    fn mem_read_u16_wrap_page(&mut self, pos: u16) -> u16 {
+        (**self).mem_read_u16_wrap_page(pos)
+    }
+    fn mem_write_u16(&mut self, pos: u16, data: u16) {
+        (**self).mem_write_u16(pos, data)
+    }
+    fn bulk_read(&mut self, addr: u16, len: usize) -> Vec<u8> {
+        (**self).bulk_read(addr, len)
+    }
+    fn poke(&mut self, addr: u16, data: u8) {
+        (**self).poke(addr, data)
+    }
+    fn peek(&mut self, addr: u16) -> u8 {
+        (**self).peek(addr)
+    }
+    fn tick(&mut self, cycles: u64) {
+        (**self).tick(cycles)
+    }
+    fn take_dma_stall_cycles(&mut self) -> u64 {
+        (**self).take_dma_stall_cycles()
+    }
+    fn poll_nmi_status(&mut self) -> Option<NmiSignal> {
+        (**self).poll_nmi_status()
+    }
+    fn poll_irq_status(&mut self) -> Option<IrqSignal> {
+        (**self).poll_irq_status()
+    }
+    fn region(&self) -> Region {
+        (**self).region()
+    }
+}
+
+impl<M: Mem + ?Sized> Mem for Box<M> {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        (**self).mem_read(addr)
+    }
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        (**self).mem_write(addr, data)
+    }
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
+        (**self).mem_read_u16(pos)
+    }
+    fn mem_read_u16_wrap_page(&mut self, pos: u16) -> u16 {
+        (**self).mem_read_u16_wrap_page(pos)
+    }
+    fn mem_write_u16(&mut self, pos: u16, data: u16) {
+        (**self).mem_write_u16(pos, data)
+    }
+    fn bulk_read(&mut self, addr: u16, len: usize) -> Vec<u8> {
+        (**self).bulk_read(addr, len)
+    }
+    fn poke(&mut self, addr: u16, data: u8) {
+        (**self).poke(addr, data)
+    }
+    fn peek(&mut self, addr: u16) -> u8 {
+        (**self).peek(addr)
+    }
+    fn tick(&mut self, cycles: u64) {
+        (**self).tick(cycles)
+    }
+    fn take_dma_stall_cycles(&mut self) -> u64 {
+        (**self).take_dma_stall_cycles()
+    }
+    fn poll_nmi_status(&mut self) -> Option<NmiSignal> {
+        (**self).poll_nmi_status()
+    }
+    fn poll_irq_status(&mut self) -> Option<IrqSignal> {
+        (**self).poll_irq_status()
+    }
+    fn region(&self) -> Region {
+        (**self).region()
     }
 }
 
+/*
+ * 64KB of plain RAM: no mirroring, no I/O registers, every address reads back
+ * what was written. For CPU tests that do not need the NES memory map.
+ */
+pub struct FlatMemory([u8; 0x10000]);
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        FlatMemory::new()
+    }
+}
+
+impl FlatMemory {
+    pub fn new() -> Self {
+        FlatMemory([0; 0x10000])
+    }
+}
+
+impl Mem for FlatMemory {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.0[addr as usize]
+    }
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.0[addr as usize] = data;
+    }
+}
+
+// an access to an address nothing answers to, e.g. reading a write-only APU register
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnmappedAccess {
+    Read(u16),
+    Write(u16, u8),
+}
+
+pub type UnmappedAccessHandler = Box<dyn FnMut(UnmappedAccess) + Send>;
+
+// with std unmapped accesses are logged to stdout, without it they are dropped
+#[cfg(feature = "std")]
+fn default_unmapped_handler() -> Option<UnmappedAccessHandler> {
+    Some(Box::new(|access| match access {
+        UnmappedAccess::Read(addr) => println!("Ignoring mem access at {}", addr),
+        UnmappedAccess::Write(addr, _) => println!("Ignoring mem write-access at {}", addr),
+    }))
+}
+
+#[cfg(not(feature = "std"))]
+fn default_unmapped_handler() -> Option<UnmappedAccessHandler> {
+    None
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Bus {
+    #[serde(with = "serde_bytes")]
     cpu_vram: [u8; 2048],
+    #[serde(with = "crate::mapper::serde_mapper")]
+    mapper: Box<dyn Mapper>,
+    pub ppu: Ppu,
+    pub apu: Apu,
+    pub controller1: Controller,
+    pub controller2: Controller,
+    // plugged into port 2, $4017 reads go to it instead of controller2
+    pub zapper: Option<Zapper>,
+
+    // CPU cycles elapsed, advanced by the CPU after every instruction
+    pub cycles: u64,
+    // fifths of a PPU dot left over from the last tick, PAL runs 3.2 dots per CPU cycle
+    ppu_clock: u64,
+    // cycles the CPU is stalled for by OAM DMA, not yet charged to it
+    dma_stall_cycles: u64,
+    // NMI raised by the PPU, not yet picked up by the CPU
+    nmi_interrupt: Option<NmiSignal>,
+    // IRQ raised by the APU, not yet picked up by the CPU
+    irq_interrupt: Option<IrqSignal>,
+    #[serde(skip, default = "default_unmapped_handler")]
+    unmapped_handler: Option<UnmappedAccessHandler>,
+}
+
+// the DMC only fetches samples from $8000-$FFFF, all of it cartridge space
+struct CartridgeMem<'a>(&'a mut dyn Mapper);
+
+impl Mem for CartridgeMem<'_> {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.0.read(addr)
+    }
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.0.write(addr, data)
+    }
 }
 
 impl Bus {
-    pub fn new() -> Self {
+    pub fn new(mapper: Box<dyn Mapper>) -> Self {
+        Bus::new_with_region(mapper, Region::NTSC)
+    }
+
+    // see Region::from_timing for the region a cartridge asks for
+    pub fn new_with_region(mapper: Box<dyn Mapper>, region: Region) -> Self {
         Bus {
             cpu_vram: [0; 2048],
+            mapper,
+            ppu: Ppu::new_with_region(region),
+            apu: Apu::new_with_region(region),
+            controller1: Controller::new(),
+            controller2: Controller::new(),
+            zapper: None,
+            cycles: 0,
+            ppu_clock: 0,
+            dma_stall_cycles: 0,
+            nmi_interrupt: None,
+            irq_interrupt: None,
+            unmapped_handler: default_unmapped_handler(),
+        }
+    }
+
+    // replaces the handler for unmapped accesses, None ignores them silently
+    pub fn set_unmapped_handler(&mut self, handler: Option<UnmappedAccessHandler>) {
+        self.unmapped_handler = handler;
+    }
+
+    fn unmapped(&mut self, access: UnmappedAccess) {
+        if let Some(handler) = self.unmapped_handler.as_mut() {
+            handler(access);
+        }
+    }
+
+    /*
+     * A bus with a plain NROM cartridge, for tests. prg_rom is padded with zeros to
+     * 32KB and mapped at $8000-$FFFF, so the vectors are its last six bytes.
+     */
+    pub fn new_with_rom(mut prg_rom: Vec<u8>) -> Self {
+        assert!(prg_rom.len() <= 0x8000, "PRG-ROM image larger than 32KB");
+        prg_rom.resize(0x8000, 0);
+        Bus::new(Box::new(Mapper0::from_prg_rom(prg_rom)))
+    }
+
+    /*
+     * OAM DMA - copies CPU page $XX00-$XXFF into OAM starting at OAMADDR.
+     * The CPU is stalled for 513 cycles, plus one when the transfer starts on an odd cycle.
+     */
+    pub fn oam_dma_transfer(&mut self, page: u8) {
+        let base = (page as u16) << 8;
+        for i in 0..256 {
+            let data = self.mem_read(base + i);
+            self.ppu.write_oam_data(data);
         }
+        self.dma_stall_cycles += 513 + self.cycles % 2;
+    }
+
+    // writes the cartridge's PRG-RAM as is, e.g. to the game's .sav file, see cartridge::sram_path
+    #[cfg(feature = "std")]
+    pub fn save_sram_to_file(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.mapper.prg_ram())
+    }
+
+    // the file has to be exactly as large as the cartridge's PRG-RAM
+    #[cfg(feature = "std")]
+    pub fn load_sram_from_file(&mut self, path: &Path) -> io::Result<()> {
+        let data = std::fs::read(path)?;
+        let prg_ram = self.mapper.prg_ram_mut();
+        if data.len() != prg_ram.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} bytes of SRAM, the cartridge has {}", data.len(), prg_ram.len()),
+            ));
+        }
+        prg_ram.copy_from_slice(&data);
+        Ok(())
     }
 }
 
@@ -32,22 +336,55 @@ const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS: u16 = 0x2000;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const APU_REGISTERS: u16 = 0x4000;
+const APU_REGISTERS_END: u16 = 0x4013;
+const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
+const CONTROLLER_1: u16 = 0x4016;
+// controller 2 on reads, the APU frame counter on writes
+const APU_FRAME_COUNTER: u16 = 0x4017;
+const CONTROLLER_2: u16 = 0x4017;
+const EXPANSION_ROM: u16 = 0x4020;
+const EXPANSION_ROM_END: u16 = 0x5FFF;
+const PRG_RAM: u16 = 0x6000;
+const PRG_ROM: u16 = 0x8000;
+const PRG_ROM_END: u16 = 0xFFFF;
 
 impl Mem for Bus {
-    fn mem_read(&self, addr: u16) -> u8 {
+    fn mem_read(&mut self, addr: u16) -> u8 {
         match addr {
             RAM ..= RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0x07FF;
                 self.cpu_vram[mirror_down_addr as usize]
             }
 
-            PPU_REGISTERS_MIRRORS_END ..= PPU_REGISTERS_MIRRORS_END => {
-                let _mirror_down_addr = addr & 0b00100000_00000111;
-                todo!()
+            PPU_REGISTERS ..= PPU_REGISTERS_MIRRORS_END => {
+                let mirror_down_addr = addr & 0b00100000_00000111;
+                match mirror_down_addr {
+                    0x2002 => self.ppu.read_status(),
+                    0x2004 => self.ppu.read_oam_data(),
+                    0x2007 => self.ppu.read_data(&*self.mapper),
+                    // write-only registers
+                    _ => 0,
+                }
             }
 
+            APU_STATUS => self.apu.read_status(),
+            CONTROLLER_1 => self.controller1.read(),
+            CONTROLLER_2 => match self.zapper.as_mut() {
+                Some(zapper) => {
+                    zapper.light_sense = zapper.aim.is_some_and(|(x, y)| self.ppu.light_at(x, y));
+                    zapper.read()
+                }
+                None => self.controller2.read(),
+            },
+
+            EXPANSION_ROM ..= EXPANSION_ROM_END => 0,
+
+            PRG_RAM ..= PRG_ROM_END => self.mapper.read(addr),
+
             _ => {
-                println!("Ignoring mem access at {}", addr);
+                self.unmapped(UnmappedAccess::Read(addr));
                 0
             }
         }
@@ -60,13 +397,458 @@ impl Mem for Bus {
             }
 
             PPU_REGISTERS ..= PPU_REGISTERS_MIRRORS_END => {
-                let _mirror_down_addr = addr & 0b00100000_00000111;
-                todo!("PPU is not supported yet");
+                let mirror_down_addr = addr & 0b00100000_00000111;
+                match mirror_down_addr {
+                    0x2000 => {
+                        if let Some(nmi) = self.ppu.write_ctrl(data) {
+                            self.nmi_interrupt = Some(nmi);
+                        }
+                    }
+                    0x2001 => self.ppu.write_mask(data),
+                    0x2003 => self.ppu.write_oam_addr(data),
+                    0x2004 => self.ppu.write_oam_data(data),
+                    0x2005 => self.ppu.write_scroll(data),
+                    0x2006 => self.ppu.write_addr(data),
+                    0x2007 => self.ppu.write_data(&mut *self.mapper, data),
+                    // PPUSTATUS is read-only
+                    _ => {}
+                }
             }
 
-            _ => {
-                println!("Ignoring mem write-access at {}", addr);
+            APU_REGISTERS ..= APU_REGISTERS_END | APU_STATUS | APU_FRAME_COUNTER => {
+                self.apu.write_register(addr, data)
+            }
+
+            OAM_DMA => self.oam_dma_transfer(data),
+
+            // the strobe goes to both controllers
+            CONTROLLER_1 => {
+                self.controller1.write(data);
+                self.controller2.write(data);
             }
+
+            EXPANSION_ROM ..= EXPANSION_ROM_END => {}
+
+            PRG_RAM ..= PRG_ROM_END => self.mapper.write(addr, data),
+
+            _ => self.unmapped(UnmappedAccess::Write(addr, data)),
+        }
+    }
+
+    // ranges inside one copy of the 2KB RAM are copied directly, anything else goes byte by byte
+    fn bulk_read(&mut self, addr: u16, len: usize) -> Vec<u8> {
+        let start = (addr & 0x07FF) as usize;
+        if addr <= RAM_MIRRORS_END && start + len <= self.cpu_vram.len() {
+            return self.cpu_vram[start..start + len].to_vec();
+        }
+        (0..len).map(|i| self.mem_read(addr.wrapping_add(i as u16))).collect()
+    }
+
+    // a write that also reaches PRG-ROM through the mapper
+    fn poke(&mut self, addr: u16, data: u8) {
+        if addr >= PRG_ROM {
+            self.mapper.patch_prg_rom(addr, data);
+        } else {
+            self.mem_write(addr, data);
+        }
+    }
+
+    // OAM is the only register that reads back without side effects, the others read as 0
+    fn peek(&mut self, addr: u16) -> u8 {
+        match addr {
+            RAM ..= RAM_MIRRORS_END => self.cpu_vram[(addr & 0x07FF) as usize],
+            PPU_REGISTERS ..= PPU_REGISTERS_MIRRORS_END if addr & 0b111 == 4 => self.ppu.read_oam_data(),
+            PRG_RAM ..= PRG_ROM_END => self.mapper.read(addr),
+            _ => 0,
+        }
+    }
+
+    // the PPU runs three dots per CPU cycle, 3.2 on PAL
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        for _ in 0..cycles {
+            if let Some(irq) = self.apu.tick(&mut CartridgeMem(&mut *self.mapper)) {
+                self.irq_interrupt = Some(irq);
+            }
+        }
+        self.dma_stall_cycles += self.apu.dmc.take_stall_cycles();
+
+        self.ppu_clock += cycles * self.ppu.region().ppu_dots_per_5_cycles();
+        for _ in 0..self.ppu_clock / 5 {
+            if let Some(nmi) = self.ppu.tick(&mut *self.mapper) {
+                self.nmi_interrupt = Some(nmi);
+            }
+            if self.ppu.take_mapper_irq() {
+                self.irq_interrupt = Some(IrqSignal);
+            }
+        }
+        self.ppu_clock %= 5;
+    }
+
+    fn take_dma_stall_cycles(&mut self) -> u64 {
+        core::mem::take(&mut self.dma_stall_cycles)
+    }
+
+    fn poll_nmi_status(&mut self) -> Option<NmiSignal> {
+        self.nmi_interrupt.take()
+    }
+
+    fn poll_irq_status(&mut self) -> Option<IrqSignal> {
+        self.irq_interrupt.take()
+    }
+
+    fn region(&self) -> Region {
+        self.ppu.region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::controller::{BUTTON_A, BUTTON_B, BUTTON_DOWN};
+
+    #[test]
+    fn test_prg_rom_through_mapper() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0000] = 0x11;
+        rom[0x7ffc] = 0x00;
+        rom[0x7ffd] = 0x80;
+        let mut bus = Bus::new_with_rom(rom);
+        assert_eq!(bus.mem_read(0x8000), 0x11);
+        assert_eq!(bus.mem_read_u16(0xfffc), 0x8000);
+    }
+
+    #[test]
+    fn test_mem_read_u16_wrap_page() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.mem_write(0x00ff, 0x34);
+        bus.mem_write(0x0000, 0x12);
+        bus.mem_write(0x0100, 0x56);
+        assert_eq!(bus.mem_read_u16_wrap_page(0x00ff), 0x1234);
+        assert_eq!(bus.mem_read_u16(0x00ff), 0x5634);
+        assert_eq!(bus.mem_read_u16_wrap_page(0x00fe), bus.mem_read_u16(0x00fe));
+    }
+
+    #[test]
+    fn test_mem_u16_wraps_at_end_of_memory() {
+        let mut memory = FlatMemory::new();
+        memory.mem_write_u16(0xffff, 0x1234);
+        assert_eq!(memory.mem_read(0xffff), 0x34);
+        assert_eq!(memory.mem_read(0x0000), 0x12);
+        assert_eq!(memory.mem_read_u16(0xffff), 0x1234);
+    }
+
+    #[test]
+    fn test_flat_memory_has_no_mirroring() {
+        let mut memory = FlatMemory::new();
+        memory.mem_write(0x0000, 0x11);
+        memory.mem_write(0x2002, 0x22);
+        memory.mem_write(0xfffc, 0x33);
+        assert_eq!(memory.mem_read(0x0800), 0x00);
+        assert_eq!(memory.mem_read(0x2002), 0x22);
+        assert_eq!(memory.mem_read(0x200a), 0x00);
+        assert_eq!(memory.mem_read(0xfffc), 0x33);
+    }
+
+    #[test]
+    fn test_bulk_read_ram() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        for (i, data) in [0x11, 0x22, 0x33, 0x44].iter().enumerate() {
+            bus.mem_write(0x0200 + i as u16, *data);
         }
+        assert_eq!(bus.bulk_read(0x0200, 4), vec![0x11, 0x22, 0x33, 0x44]);
+        // the same bytes through a mirror
+        assert_eq!(bus.bulk_read(0x1a00, 4), vec![0x11, 0x22, 0x33, 0x44]);
+    }
+
+    #[test]
+    fn test_bulk_read_across_regions() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0000] = 0x55;
+        rom[0x7fff] = 0x66;
+        let mut bus = Bus::new_with_rom(rom);
+        bus.mem_write(0x07ff, 0x11);
+        bus.mem_write(0x0000, 0x22);
+
+        // the end of RAM runs into its first mirror
+        assert_eq!(bus.bulk_read(0x07ff, 2), vec![0x11, 0x22]);
+        // the end of the address space wraps around to RAM
+        assert_eq!(bus.bulk_read(0xffff, 2), vec![0x66, 0x22]);
+        assert_eq!(bus.bulk_read(0x8000, 1), vec![0x55]);
+        assert_eq!(bus.bulk_read(0x0000, 0), vec![]);
+    }
+
+    #[test]
+    fn test_boxed_dyn_mem_forwards_overrides() {
+        let mut mem: Box<dyn Mem> = Box::new(Bus::new_with_rom(vec![]));
+        mem.mem_write(0x0800, 0x11);
+        assert_eq!(mem.mem_read(0x0000), 0x11);
+        // OAM DMA, the stall cycles only exist on the Bus
+        mem.mem_write(0x4014, 0x02);
+        assert_eq!(mem.take_dma_stall_cycles(), 513);
+    }
+
+    #[test]
+    fn test_mut_dyn_mem_as_impl_mem() {
+        fn read_word(mut mem: impl Mem) -> u16 {
+            mem.mem_read_u16(0x0010)
+        }
+
+        let mut memory = FlatMemory::new();
+        memory.mem_write_u16(0x0010, 0x1234);
+        let mem: &mut dyn Mem = &mut memory;
+        assert_eq!(read_word(mem), 0x1234);
+    }
+
+    #[test]
+    fn test_new_with_rom() {
+        let mut bus = Bus::new_with_rom(vec![0xa9, 0x05]);
+        assert_eq!(bus.mem_read(0x8000), 0xa9);
+        assert_eq!(bus.mem_read(0x8001), 0x05);
+        assert_eq!(bus.mem_read(0xc000), 0x00);
+        assert_eq!(bus.mem_read_u16(0xfffc), 0x0000);
+    }
+
+    #[test]
+    fn test_peek_has_no_side_effects() {
+        let accesses = Arc::new(Mutex::new(vec![]));
+        let log = accesses.clone();
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.set_unmapped_handler(Some(Box::new(move |access| log.lock().unwrap().push(access))));
+        bus.mem_write(0x0200, 0x42);
+        bus.ppu.oam_data[0] = 0x99;
+        bus.controller1.update_buttons(BUTTON_B);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+        bus.tick(27395);
+
+        assert_eq!(bus.peek(0x1a00), 0x42);
+        assert_eq!(bus.peek(0x2004), 0x99);
+        assert_eq!(bus.peek(0x2002), 0);
+        assert_eq!(bus.peek(0x4016), 0);
+        assert_eq!(bus.peek(0x4000), 0);
+
+        assert_eq!(bus.ppu.vblank, true);
+        assert_eq!(bus.mem_read(0x4016), 0);
+        assert_eq!(bus.mem_read(0x4016), 1);
+        assert_eq!(*accesses.lock().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_unmapped_handler() {
+        let accesses = Arc::new(Mutex::new(vec![]));
+        let log = accesses.clone();
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.set_unmapped_handler(Some(Box::new(move |access| log.lock().unwrap().push(access))));
+
+        bus.mem_read(0x4000);
+        bus.mem_write(0x4018, 0x42);
+        bus.mem_read(0x0000);
+
+        assert_eq!(*accesses.lock().unwrap(), vec![UnmappedAccess::Read(0x4000), UnmappedAccess::Write(0x4018, 0x42)]);
+    }
+
+    #[test]
+    fn test_prg_rom_is_read_only() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.mem_write(0x8000, 0x44);
+        assert_eq!(bus.mem_read(0x8000), 0x00);
+    }
+
+    #[test]
+    fn test_ppu_status_through_mirror() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.ppu.set_vblank();
+        bus.ppu.poll_nmi();
+        assert_eq!(bus.mem_read(0x3ffa), 0x80);
+        assert_eq!(bus.mem_read(0x2002), 0x00);
+    }
+
+    #[test]
+    fn test_ppu_data_through_bus() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.mem_write(0x2006, 0x21);
+        bus.mem_write(0x2006, 0x00);
+        bus.mem_write(0x2007, 0x42);
+        bus.mem_write(0x2006, 0x21);
+        bus.mem_write(0x2006, 0x00);
+        bus.mem_read(0x2007);
+        assert_eq!(bus.mem_read(0x2007), 0x42);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        for i in 0..256u16 {
+            bus.mem_write(0x0200 + i, i as u8);
+        }
+        bus.mem_write(0x2003, 0x10);
+        bus.mem_write(0x4014, 0x02);
+
+        // the copy starts at OAMADDR and wraps around
+        assert_eq!(bus.ppu.oam_data[0x10], 0x00);
+        assert_eq!(bus.ppu.oam_data[0xFF], 0xEF);
+        assert_eq!(bus.ppu.oam_data[0x00], 0xF0);
+        assert_eq!(bus.ppu.oam_data[0x0F], 0xFF);
+        assert_eq!(bus.ppu.oam_addr, 0x10);
+        assert_eq!(bus.take_dma_stall_cycles(), 513);
+        assert_eq!(bus.take_dma_stall_cycles(), 0);
+    }
+
+    #[test]
+    fn test_oam_dma_odd_cycle() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.tick(3);
+        bus.oam_dma_transfer(0x00);
+        assert_eq!(bus.take_dma_stall_cycles(), 514);
+    }
+
+    #[test]
+    fn test_prg_ram() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.mem_write(0x6000, 0x55);
+        bus.mem_write(0x7fff, 0x66);
+        assert_eq!(bus.mem_read(0x6000), 0x55);
+        assert_eq!(bus.mem_read(0x7fff), 0x66);
+    }
+
+    #[test]
+    fn test_sram_save_and_load() {
+        let path = std::env::temp_dir().join(format!("nes_test_sram_{}.sav", std::process::id()));
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.mem_write(0x6000, 0x55);
+        bus.mem_write(0x7fff, 0x66);
+        bus.save_sram_to_file(&path).unwrap();
+
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.load_sram_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bus.mem_read(0x6000), 0x55);
+        assert_eq!(bus.mem_read(0x7fff), 0x66);
+    }
+
+    #[test]
+    fn test_sram_size_mismatch() {
+        let path = std::env::temp_dir().join(format!("nes_test_sram_short_{}.sav", std::process::id()));
+        std::fs::write(&path, [0x55; 16]).unwrap();
+
+        let mut bus = Bus::new_with_rom(vec![]);
+        let err = bus.load_sram_from_file(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(bus.mem_read(0x6000), 0x00);
+    }
+
+    #[test]
+    fn test_tick_runs_ppu_three_times_faster() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.tick(114);
+        assert_eq!((bus.ppu.scanline, bus.ppu.dot), (1, 1));
+    }
+
+    #[test]
+    fn test_pal_tick_runs_ppu_3_2_times_faster() {
+        let mapper = Box::new(Mapper0::from_prg_rom(vec![0; 0x8000]));
+        let mut bus = Bus::new_with_region(mapper, Region::PAL);
+        assert_eq!(bus.region(), Region::PAL);
+
+        // the leftover fifths of a dot add up to an extra dot every 5 cycles
+        bus.tick(1);
+        assert_eq!(bus.ppu.dot, 3);
+        for _ in 0..4 {
+            bus.tick(1);
+        }
+        assert_eq!(bus.ppu.dot, 16);
+        bus.tick(105);
+        assert_eq!((bus.ppu.scanline, bus.ppu.dot), (1, 11));
+    }
+
+    #[test]
+    fn test_nmi_enabled_during_vblank() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.tick(27395);
+        assert_eq!(bus.ppu.vblank, true);
+        assert_eq!(bus.poll_nmi_status(), None);
+
+        bus.mem_write(0x2000, 0x80);
+        assert_eq!(bus.poll_nmi_status(), Some(NmiSignal));
+    }
+
+    #[test]
+    fn test_nmi_from_ppu() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.mem_write(0x2000, 0x80);
+
+        // VBlank starts on dot 241 * 341 + 1, the NMI goes out on the next one
+        bus.tick(27394);
+        assert_eq!(bus.poll_nmi_status(), None);
+        bus.tick(1);
+        assert_eq!(bus.poll_nmi_status(), Some(NmiSignal));
+        assert_eq!(bus.poll_nmi_status(), None);
+    }
+
+    #[test]
+    fn test_zapper() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        // a white screen, rendering off draws the backdrop
+        bus.ppu.palette_table[0] = 0x30;
+        let mut zapper = Zapper::new();
+        zapper.update(false, Some((10, 20)));
+        bus.zapper = Some(zapper);
+
+        // line 20 has not been drawn yet
+        assert_eq!(bus.mem_read(0x4017), 0b0000_1000);
+        bus.zapper.as_mut().unwrap().trigger = true;
+        assert_eq!(bus.mem_read(0x4017), 0b0001_1000);
+
+        // 21 lines of 341 dots, the beam has just passed the aimed pixel
+        bus.tick(21 * 341 / 3);
+        assert_eq!(bus.mem_read(0x4017), 0b0001_0000);
+
+        // aimed off the screen
+        bus.zapper.as_mut().unwrap().update(true, None);
+        assert_eq!(bus.mem_read(0x4017), 0b0001_1000);
+
+        // the light fades out
+        bus.zapper.as_mut().unwrap().update(false, Some((10, 20)));
+        bus.tick(30 * 341 / 3);
+        assert_eq!(bus.mem_read(0x4017), 0b0000_1000);
+    }
+
+    #[test]
+    fn test_apu_registers() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.mem_write(0x4015, 0x01);
+        bus.mem_write(0x4000, 0b1001_1111);
+        bus.mem_write(0x4003, 0b0000_1000);
+        assert_eq!(bus.apu.pulse1.duty, 2);
+        assert_eq!(bus.mem_read(0x4015), 0x01);
+    }
+
+    #[test]
+    fn test_dmc_fetch_stalls_and_raises_irq() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.mem_write(0x4010, 0x8F);
+        bus.mem_write(0x4015, 0x10);
+        bus.tick(1);
+        assert_eq!(bus.take_dma_stall_cycles(), 4);
+        assert_eq!(bus.poll_irq_status(), Some(IrqSignal));
+        assert_eq!(bus.mem_read(0x4015), 0x80);
+    }
+
+    #[test]
+    fn test_controllers() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.controller1.update_buttons(BUTTON_A | BUTTON_DOWN);
+        bus.controller2.update_buttons(BUTTON_B);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+
+        let controller1: Vec<u8> = (0..8).map(|_| bus.mem_read(0x4016)).collect();
+        let controller2: Vec<u8> = (0..8).map(|_| bus.mem_read(0x4017)).collect();
+        assert_eq!(controller1, vec![1, 0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(controller2, vec![0, 1, 0, 0, 0, 0, 0, 0]);
     }
 }
@@ -0,0 +1,363 @@
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+pub(crate) const PRG_ROM_PAGE_SIZE: usize = 0x4000;
+pub(crate) const CHR_ROM_PAGE_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x2000;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Mirroring {
+    Vertical,
+    Horizontal,
+    FourScreen,
+    // all four nametables show the first or the second 1KB page
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RomError {
+    // the file does not start with "NES\x1A"
+    InvalidTag,
+    // byte 7 declares neither iNES nor NES 2.0
+    UnsupportedVersion,
+    // the file is shorter than the sizes declared in the header
+    Truncated,
+    // a declared size does not fit in memory
+    InvalidSize,
+    // no implementation for this mapper number
+    UnsupportedMapper(u16),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RomVersion {
+    NES10,
+    NES20,
+}
+
+// CPU/PPU timing the cartridge was made for, only NES 2.0 headers declare it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    PlayChoice10,
+    // NES 2.0 only, the console is named by byte 13
+    Extended,
+}
+
+/*
+ * iNES header
+ * bytes 0-3 tag, 4 PRG-ROM size in 16KB units, 5 CHR-ROM size in 8KB units,
+ * 6 mirroring/battery/trainer/mapper low nibble, 7 format version/mapper high nibble/console type
+ *
+ * NES 2.0 header (byte 7 bits 2-3 == 0b10), in addition
+ * 8 mapper bits 8-11/submapper, 9 PRG-ROM/CHR-ROM size high nibbles,
+ * 10 PRG-RAM/PRG-NVRAM shift counts, 11 CHR-RAM/CHR-NVRAM shift counts, 12 timing,
+ * 13-15 VS System type, misc ROMs and default expansion device (not parsed)
+ *
+ * The ROM and RAM sizes are kept in bytes.
+ */
+#[derive(Debug, PartialEq, Clone)]
+pub struct RomHeader {
+    pub version: RomVersion,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub timing: Timing,
+    pub console_type: ConsoleType,
+}
+
+impl RomHeader {
+    pub fn parse(data: &[u8]) -> Result<Self, RomError> {
+        if data.len() < HEADER_SIZE {
+            return Err(RomError::Truncated);
+        }
+        if data[0..4] != NES_TAG {
+            return Err(RomError::InvalidTag);
+        }
+
+        let control_1 = data[6];
+        let control_2 = data[7];
+
+        let version = match control_2 & 0b0000_1100 {
+            0b0000 => RomVersion::NES10,
+            0b1000 => RomVersion::NES20,
+            _ => return Err(RomError::UnsupportedVersion),
+        };
+
+        let four_screen = control_1 & 0b1000 != 0;
+        let vertical = control_1 & 0b1 != 0;
+        let mirroring = match (four_screen, vertical) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+
+        let console_type = match control_2 & 0b11 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::PlayChoice10,
+            _ => ConsoleType::Extended,
+        };
+        let mapper = ((control_2 & 0b1111_0000) | (control_1 >> 4)) as u16;
+        let battery = control_1 & 0b10 != 0;
+        let trainer = control_1 & 0b100 != 0;
+
+        match version {
+            // bytes 8-15 of iNES files are often garbage ("DiskDude!"), so they are not read
+            RomVersion::NES10 => Ok(RomHeader {
+                version,
+                mapper,
+                submapper: 0,
+                prg_rom_size: data[4] as usize * PRG_ROM_PAGE_SIZE,
+                chr_rom_size: data[5] as usize * CHR_ROM_PAGE_SIZE,
+                prg_ram_size: if battery { 0 } else { PRG_RAM_SIZE },
+                prg_nvram_size: if battery { PRG_RAM_SIZE } else { 0 },
+                chr_ram_size: if data[5] == 0 { CHR_ROM_PAGE_SIZE } else { 0 },
+                chr_nvram_size: 0,
+                mirroring,
+                battery,
+                trainer,
+                timing: Timing::Ntsc,
+                console_type: if console_type == ConsoleType::Extended { ConsoleType::Nes } else { console_type },
+            }),
+            RomVersion::NES20 => Ok(RomHeader {
+                version,
+                mapper: mapper | ((data[8] & 0b1111) as u16) << 8,
+                submapper: data[8] >> 4,
+                prg_rom_size: rom_size(data[4], data[9] & 0b1111, PRG_ROM_PAGE_SIZE).ok_or(RomError::InvalidSize)?,
+                chr_rom_size: rom_size(data[5], data[9] >> 4, CHR_ROM_PAGE_SIZE).ok_or(RomError::InvalidSize)?,
+                prg_ram_size: ram_size(data[10] & 0b1111),
+                prg_nvram_size: ram_size(data[10] >> 4),
+                chr_ram_size: ram_size(data[11] & 0b1111),
+                chr_nvram_size: ram_size(data[11] >> 4),
+                mirroring,
+                battery,
+                trainer,
+                timing: match data[12] & 0b11 {
+                    0 => Timing::Ntsc,
+                    1 => Timing::Pal,
+                    2 => Timing::MultiRegion,
+                    _ => Timing::Dendy,
+                },
+                console_type,
+            }),
+        }
+    }
+}
+
+/*
+ * NES 2.0 ROM size: a 12-bit count of pages, or, when the high nibble is $F,
+ * 2^E * (MM*2+1) bytes with the low byte read as EEEEEEMM. None when that overflows usize.
+ */
+fn rom_size(lsb: u8, msb: u8, page_size: usize) -> Option<usize> {
+    if msb == 0xf {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        1usize.checked_shl(exponent)?.checked_mul(multiplier)
+    } else {
+        ((msb as usize) << 8 | lsb as usize).checked_mul(page_size)
+    }
+}
+
+// NES 2.0 RAM size: 0 for none, 64 << shift bytes otherwise
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+pub struct Cartridge {
+    pub header: RomHeader,
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+}
+
+impl Cartridge {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, RomError> {
+        let header = RomHeader::parse(data)?;
+
+        let prg_rom_size = header.prg_rom_size;
+        let chr_rom_size = header.chr_rom_size;
+
+        let prg_rom_start = HEADER_SIZE + if header.trainer { TRAINER_SIZE } else { 0 };
+        let chr_rom_start = prg_rom_start.checked_add(prg_rom_size).ok_or(RomError::InvalidSize)?;
+        let chr_rom_end = chr_rom_start.checked_add(chr_rom_size).ok_or(RomError::InvalidSize)?;
+
+        if data.len() < chr_rom_end {
+            return Err(RomError::Truncated);
+        }
+
+        Ok(Cartridge {
+            prg_rom: data[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom: data[chr_rom_start..chr_rom_end].to_vec(),
+            header,
+        })
+    }
+}
+
+// where the battery-backed PRG-RAM of a ROM is kept: next to it, with a .sav extension
+#[cfg(feature = "std")]
+pub fn sram_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn ines(control_1: u8, control_2: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, prg_banks, chr_banks, control_1, control_2];
+        data.resize(HEADER_SIZE, 0);
+        if control_1 & 0b100 != 0 {
+            data.resize(HEADER_SIZE + TRAINER_SIZE, 0xee);
+        }
+        data.extend(vec![0x11; prg_banks as usize * PRG_ROM_PAGE_SIZE]);
+        data.extend(vec![0x22; chr_banks as usize * CHR_ROM_PAGE_SIZE]);
+        data
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let cartridge = Cartridge::from_bytes(&ines(0b0001_0011, 0b0010_0000, 2, 1)).unwrap();
+        assert_eq!(cartridge.header.mapper, 0x21);
+        assert_eq!(cartridge.header.mirroring, Mirroring::Vertical);
+        assert_eq!(cartridge.header.battery, true);
+        assert_eq!(cartridge.prg_rom, vec![0x11; 2 * PRG_ROM_PAGE_SIZE]);
+        assert_eq!(cartridge.chr_rom, vec![0x22; CHR_ROM_PAGE_SIZE]);
+    }
+
+    #[test]
+    fn test_from_bytes_skips_trainer() {
+        let cartridge = Cartridge::from_bytes(&ines(0b1100, 0, 1, 0)).unwrap();
+        assert_eq!(cartridge.header.trainer, true);
+        assert_eq!(cartridge.header.mirroring, Mirroring::FourScreen);
+        assert_eq!(cartridge.prg_rom, vec![0x11; PRG_ROM_PAGE_SIZE]);
+        assert_eq!(cartridge.chr_rom.len(), 0);
+    }
+
+    #[test]
+    fn test_invalid_tag() {
+        let mut data = ines(0, 0, 1, 1);
+        data[3] = 0x00;
+        assert_eq!(Cartridge::from_bytes(&data).err(), Some(RomError::InvalidTag));
+    }
+
+    #[test]
+    fn test_from_bytes_ines_defaults() {
+        let cartridge = Cartridge::from_bytes(&ines(0b0001_0011, 0b0010_0001, 2, 0)).unwrap();
+        assert_eq!(cartridge.header.version, RomVersion::NES10);
+        assert_eq!(cartridge.header.submapper, 0);
+        assert_eq!(cartridge.header.prg_ram_size, 0);
+        assert_eq!(cartridge.header.prg_nvram_size, PRG_RAM_SIZE);
+        assert_eq!(cartridge.header.chr_ram_size, CHR_ROM_PAGE_SIZE);
+        assert_eq!(cartridge.header.timing, Timing::Ntsc);
+        assert_eq!(cartridge.header.console_type, ConsoleType::VsSystem);
+    }
+
+    /*
+     * NES 2.0 header for mapper $104.5 (12-bit mapper number and submapper) with
+     * $102 PRG banks of 16KB (4MB+32KB), 2 CHR banks, 8KB PRG-RAM, 32KB PRG-NVRAM,
+     * no CHR-RAM, 2KB CHR-NVRAM, Dendy timing, PlayChoice-10
+     */
+    #[test]
+    fn test_from_bytes_nes2() {
+        let mut data = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x02, 0b0100_0010, 0b0000_1010, //
+            0x51, 0x01, 0x97, 0x50, 0x03, 0x00, 0x00, 0x00,
+        ];
+        for bank in 0..0x102 {
+            data.extend(vec![bank as u8; PRG_ROM_PAGE_SIZE]);
+        }
+        data.extend(vec![0x22; 2 * CHR_ROM_PAGE_SIZE]);
+
+        let cartridge = Cartridge::from_bytes(&data).unwrap();
+        let header = &cartridge.header;
+        assert_eq!(header.version, RomVersion::NES20);
+        assert_eq!(header.mapper, 0x104);
+        assert_eq!(header.submapper, 5);
+        assert_eq!(header.prg_rom_size, 0x102 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(header.chr_rom_size, 2 * CHR_ROM_PAGE_SIZE);
+        assert_eq!(header.prg_ram_size, 0x2000);
+        assert_eq!(header.prg_nvram_size, 0x8000);
+        assert_eq!(header.chr_ram_size, 0);
+        assert_eq!(header.chr_nvram_size, 0x800);
+        assert_eq!(header.battery, true);
+        assert_eq!(header.mirroring, Mirroring::Horizontal);
+        assert_eq!(header.timing, Timing::Dendy);
+        assert_eq!(header.console_type, ConsoleType::PlayChoice10);
+
+        assert_eq!(cartridge.prg_rom.len(), 0x102 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(cartridge.prg_rom[0x101 * PRG_ROM_PAGE_SIZE], 0x01);
+        assert_eq!(cartridge.chr_rom, vec![0x22; 2 * CHR_ROM_PAGE_SIZE]);
+    }
+
+    #[test]
+    fn test_nes2_exponent_rom_size() {
+        // $F in the high nibble: 2^3 * (1*2+1) = 24 bytes of PRG-ROM
+        let mut data = ines(0, 0b1000, 0, 0);
+        data[4] = 0b0000_1101;
+        data[9] = 0x0f;
+        assert_eq!(RomHeader::parse(&data).unwrap().prg_rom_size, 24);
+    }
+
+    #[test]
+    fn test_nes2_exponent_rom_size_overflow() {
+        // 2^63 * 7 bytes of PRG-ROM
+        let mut data = ines(0, 0b1000, 0, 0);
+        data[4] = 0xff;
+        data[9] = 0x0f;
+        assert_eq!(Cartridge::from_bytes(&data).err(), Some(RomError::InvalidSize));
+
+        // 2^62 * 3 bytes each of PRG-ROM and CHR-ROM, only the sum overflows
+        data[4] = 0b1111_1001;
+        data[5] = 0b1111_1001;
+        data[9] = 0xff;
+        assert_eq!(Cartridge::from_bytes(&data).err(), Some(RomError::InvalidSize));
+    }
+
+    #[test]
+    fn test_unknown_version_is_unsupported() {
+        for control_2 in [0b0100, 0b1100] {
+            let data = ines(0, control_2, 1, 1);
+            assert_eq!(Cartridge::from_bytes(&data).err(), Some(RomError::UnsupportedVersion));
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut data = ines(0, 0, 1, 1);
+        data.truncate(data.len() - 1);
+        assert_eq!(Cartridge::from_bytes(&data).err(), Some(RomError::Truncated));
+        assert_eq!(Cartridge::from_bytes(&data[..8]).err(), Some(RomError::Truncated));
+    }
+
+    #[test]
+    fn test_sram_path() {
+        assert_eq!(sram_path(Path::new("roms/zelda.nes")), PathBuf::from("roms/zelda.sav"));
+    }
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+pub const BUTTON_A: u8 = 0b0000_0001;
+pub const BUTTON_B: u8 = 0b0000_0010;
+pub const BUTTON_SELECT: u8 = 0b0000_0100;
+pub const BUTTON_START: u8 = 0b0000_1000;
+pub const BUTTON_UP: u8 = 0b0001_0000;
+pub const BUTTON_DOWN: u8 = 0b0010_0000;
+pub const BUTTON_LEFT: u8 = 0b0100_0000;
+pub const BUTTON_RIGHT: u8 = 0b1000_0000;
+
+/*
+ * Standard controller
+ * Writing 1 then 0 to $4016 latches the buttons, each read of $4016 ($4017 for the
+ * second controller) then returns the next one in bit 0: A, B, Select, Start, Up, Down, Left, Right.
+ * While the strobe is high every read returns A, after the 8th read the reads return 1.
+ */
+#[derive(Default, Serialize, Deserialize)]
+pub struct Controller {
+    strobe: bool,
+    index: u8,
+    buttons: u8,
+}
+
+impl Controller {
+    pub fn new() -> Self {
+        Controller {
+            strobe: false,
+            index: 0,
+            buttons: 0,
+        }
+    }
+
+    // pressed buttons, one BUTTON_* bit each
+    pub fn update_buttons(&mut self, state: u8) {
+        self.buttons = state;
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.strobe = val & 1 == 1;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        if self.index > 7 {
+            return 1;
+        }
+        let bit = (self.buttons >> self.index) & 1;
+        if !self.strobe {
+            self.index += 1;
+        }
+        bit
+    }
+}
+
+const ZAPPER_LIGHT: u8 = 0b0000_1000;
+const ZAPPER_TRIGGER: u8 = 0b0001_0000;
+
+/*
+ * Zapper light gun, plugged into port 2 in place of the second controller
+ *   $4017 read ---T L---   T: trigger pulled, L: 0 while the gun sees light
+ * light_sense is refreshed by the bus from the picture on every read, see Ppu::light_at.
+ */
+#[derive(Default, Serialize, Deserialize)]
+pub struct Zapper {
+    pub trigger: bool,
+    pub light_sense: bool,
+    // the pixel the gun points at, None when it points away from the screen
+    pub aim: Option<(u8, u8)>,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Zapper {
+            trigger: false,
+            light_sense: false,
+            aim: None,
+        }
+    }
+
+    // from the front end, e.g. the mouse button and the mouse position scaled to 256x240
+    pub fn update(&mut self, trigger: bool, aim: Option<(u8, u8)>) {
+        self.trigger = trigger;
+        self.aim = aim;
+    }
+
+    pub fn read(&self) -> u8 {
+        let mut val = 0;
+        if !self.light_sense {
+            val |= ZAPPER_LIGHT;
+        }
+        if self.trigger {
+            val |= ZAPPER_TRIGGER;
+        }
+        val
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_reads_buttons_in_order() {
+        let mut controller = Controller::new();
+        controller.update_buttons(BUTTON_A | BUTTON_START | BUTTON_LEFT);
+        controller.write(1);
+        controller.write(0);
+
+        let bits: Vec<u8> = (0..8).map(|_| controller.read()).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 1, 0]);
+        // past the last button
+        assert_eq!(controller.read(), 1);
+    }
+
+    #[test]
+    fn test_strobe_high_returns_a() {
+        let mut controller = Controller::new();
+        controller.update_buttons(BUTTON_A);
+        controller.write(1);
+        assert_eq!(controller.read(), 1);
+        assert_eq!(controller.read(), 1);
+
+        controller.update_buttons(BUTTON_B);
+        assert_eq!(controller.read(), 0);
+    }
+
+    #[test]
+    fn test_restrobe_restarts_sequence() {
+        let mut controller = Controller::new();
+        controller.update_buttons(BUTTON_RIGHT);
+        controller.write(1);
+        controller.write(0);
+        for _ in 0..5 {
+            controller.read();
+        }
+        controller.write(1);
+        controller.write(0);
+        let bits: Vec<u8> = (0..8).map(|_| controller.read()).collect();
+        assert_eq!(bits, vec![0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_zapper_bits() {
+        let mut zapper = Zapper::new();
+        assert_eq!(zapper.read(), 0b0000_1000);
+
+        zapper.update(true, Some((128, 120)));
+        assert_eq!(zapper.read(), 0b0001_1000);
+
+        zapper.light_sense = true;
+        assert_eq!(zapper.read(), 0b0001_0000);
+    }
+}
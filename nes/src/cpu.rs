@@ -1,7 +1,19 @@
-use std::collections::HashMap;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use crate::bus::*;
+use crate::debug;
 use crate::opcodes;
+use crate::region::Region;
+use crate::trace::{self, TraceEntry};
+#[cfg(feature = "debug")]
+use crate::trace::ExecutionHistory;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 #[allow(non_camel_case_types)]
@@ -20,6 +32,27 @@ pub enum AddressingMode {
     NoneAddressing,
 }
 
+// operand notation, nn is a byte and nnnn a word, e.g. "($nn),Y"
+impl fmt::Display for AddressingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let notation = match self {
+            AddressingMode::Immediate => "#$nn",
+            AddressingMode::ZeroPage => "$nn",
+            AddressingMode::ZeroPage_X => "$nn,X",
+            AddressingMode::ZeroPage_Y => "$nn,Y",
+            AddressingMode::Absolute => "$nnnn",
+            AddressingMode::Absolute_X => "$nnnn,X",
+            AddressingMode::Absolute_Y => "$nnnn,Y",
+            AddressingMode::Indirect => "($nnnn)",
+            AddressingMode::Indirect_X => "($nn,X)",
+            AddressingMode::Indirect_Y => "($nn),Y",
+            AddressingMode::Accumulator => "A",
+            AddressingMode::NoneAddressing => "",
+        };
+        write!(f, "{}", notation)
+    }
+}
+
 #[derive(Debug)]
 #[allow(non_camel_case_types)]
 pub enum Flag {
@@ -33,59 +66,340 @@ pub enum Flag {
     Negative = 1 << 7,
 }
 
+// the letter used in NV-BDIZC status dumps, bit 5 has no name
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let letter = match self {
+            Flag::Carry => 'C',
+            Flag::Zero => 'Z',
+            Flag::Interrupt => 'I',
+            Flag::Decimal => 'D',
+            Flag::Break => 'B',
+            Flag::Break2 => '-',
+            Flag::Overflow => 'V',
+            Flag::Negative => 'N',
+        };
+        write!(f, "{}", letter)
+    }
+}
+
+/*
+ * Conditions that stop the run loop before a BRK is reached
+ */
+#[derive(Debug, PartialEq)]
+pub enum CpuError {
+    // the byte at the program counter is not in the opcode table
+    UnknownOpcode(u8),
+    // a KIL/JAM opcode locked up the processor
+    Halt(u8),
+    // the program counter reached a breakpoint, the instruction there has not run yet
+    Breakpoint(u16),
+    // a BRK instruction was executed, run treats it as the end of the program
+    Break,
+    // the stop handle was set, the run can be resumed where it stopped
+    Stopped,
+}
+
+// e.g. "halted by *KIL ($02)"
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode(code) => write!(f, "unknown opcode ${:02X}", code),
+            CpuError::Halt(code) => {
+                let mnemonic = opcodes::OPCODE_TABLE[*code as usize].map_or("???", |op| op.mnemonic);
+                write!(f, "halted by {} (${:02X})", mnemonic, code)
+            }
+            CpuError::Breakpoint(pc) => write!(f, "breakpoint at ${:04X}", pc),
+            CpuError::Break => write!(f, "BRK"),
+            CpuError::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+// what a single step did, the cycles include any interrupt serviced before the instruction and DMA stalls
+#[derive(Debug, PartialEq)]
+pub struct StepResult {
+    pub cycles_consumed: u64,
+    pub pc_before: u16,
+    pub pc_after: u16,
+}
+
+// the registers and cycle counter as plain data, for debuggers and trace tools
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CpuSnapshot {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub pc: u16,
+    pub sp: u8,
+    pub status: u8,
+    pub cycle: u64,
+}
+
+// which accesses trigger a watchpoint, the callback gets Read or Write for the access that happened
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WatchMode {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchMode {
+    fn matches(self, access: WatchMode) -> bool {
+        self == WatchMode::ReadWrite || self == access
+    }
+}
+
+pub type WatchCallback = Box<dyn FnMut(u16, u8, WatchMode) + Send>;
+
 const STACK: u16 = 0x0100;
 const STACK_RESET: u8 = 0xfd;
+// I, B and the unused bit 5
+const POWER_ON_STATUS: u8 = 0x34;
 
-pub struct CPU {
-    pub register_a: u8,
-    pub register_x: u8,
-    pub register_y: u8,
-    pub program_counter: u16,
-    pub stack_pointer: u8,
-    pub status: u8,
+// test programs are copied into RAM here, above the zero page and the stack
+const PROGRAM_START: u16 = 0x0600;
+
+#[cfg(feature = "debug")]
+const HISTORY_CAPACITY: usize = 64;
+
+#[cfg(feature = "debug")]
+fn default_history() -> ExecutionHistory {
+    ExecutionHistory::new(HISTORY_CAPACITY)
+}
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_BRK_VECTOR: u16 = 0xFFFE;
+
+#[derive(Serialize, Deserialize)]
+pub struct CPU<M: Mem = Bus> {
+    // registers are read through the get_* accessors outside the crate,
+    // writes have to go through instructions, reset or interrupts
+    pub(crate) register_a: u8,
+    pub(crate) register_x: u8,
+    pub(crate) register_y: u8,
+    pub(crate) program_counter: u16,
+    pub(crate) stack_pointer: u8,
+    pub(crate) status: u8,
+    // the 2A03 has no decimal mode, BCD arithmetic is only used when enabled explicitly
+    pub(crate) bcd_enabled: bool,
+    pub(crate) cycle_count: u64,
+    pub(crate) nmi_pending: bool,
+    pub(crate) irq_pending: bool,
     // memory: [u8; 0xFFFF],
-    pub bus: Bus,
+    pub(crate) bus: M,
+
+    // profiler and debugger state, not part of save states
+    #[serde(skip)]
+    instruction_counts: Option<BTreeMap<u8, u64>>,
+    #[serde(skip)]
+    breakpoints: BTreeSet<u16>,
+    // the breakpoint the last run stopped at, it is stepped over when the run resumes
+    #[serde(skip)]
+    resume_from: Option<u16>,
+    #[serde(skip)]
+    watchpoints: BTreeMap<u16, WatchMode>,
+    #[serde(skip)]
+    watch_callback: Option<WatchCallback>,
+    // set from anywhere, e.g. a UI thread, to make the run loop return Stopped
+    #[serde(skip)]
+    stop_flag: Arc<AtomicBool>,
+    // the last instructions executed, replace it to change the capacity
+    #[cfg(feature = "debug")]
+    #[serde(skip, default = "default_history")]
+    pub history: ExecutionHistory,
 }
 
-impl Mem for CPU {
-    fn mem_read(&self, addr: u16) -> u8 {
-        self.bus.mem_read(addr)
+// the u16 accesses use the default methods so they go through the watchpoints as well
+impl<M: Mem> Mem for CPU<M> {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        let data = self.bus.mem_read(addr);
+        self.check_watchpoint(addr, data, WatchMode::Read);
+        data
     }
 
     fn mem_write(&mut self, addr: u16, data: u8) {
+        self.check_watchpoint(addr, data, WatchMode::Write);
         self.bus.mem_write(addr, data)
     }
-    fn mem_read_u16(&self, pos: u16) -> u16 {
-        self.bus.mem_read_u16(pos)
+
+    // debugger reads do not trigger watchpoints
+    fn peek(&mut self, addr: u16) -> u8 {
+        self.bus.peek(addr)
+    }
+}
+
+/*
+ * Each item executes one instruction and yields the registers as they were before it.
+ * The first error (including BRK) is yielded and ends the iteration.
+ */
+pub struct CpuIter<'a, M: Mem = Bus> {
+    cpu: &'a mut CPU<M>,
+    done: bool,
+}
+
+impl<M: Mem> Iterator for CpuIter<'_, M> {
+    type Item = Result<CpuSnapshot, CpuError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let before = self.cpu.snapshot();
+        match self.cpu.step() {
+            Ok(_) => Some(Ok(before)),
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/*
+ * Builds a CPU with preset registers, e.g.
+ *     CpuBuilder::new(bus).with_x(0x05).with_pc(0x0600).build()
+ * Registers left alone start as after a reset, without a PC it comes from the reset vector.
+ */
+pub struct CpuBuilder<M: Mem = Bus> {
+    bus: M,
+    a: u8,
+    x: u8,
+    y: u8,
+    status: u8,
+    sp: u8,
+    pc: Option<u16>,
+    cycle_count: Option<u64>,
+    bcd_enabled: bool,
+}
+
+impl<M: Mem> CpuBuilder<M> {
+    pub fn new(bus: M) -> Self {
+        CpuBuilder {
+            bus,
+            a: 0,
+            x: 0,
+            y: 0,
+            status: POWER_ON_STATUS,
+            sp: STACK_RESET,
+            pc: None,
+            cycle_count: None,
+            bcd_enabled: false,
+        }
+    }
+
+    pub fn with_a(mut self, a: u8) -> Self {
+        self.a = a;
+        self
+    }
+
+    pub fn with_x(mut self, x: u8) -> Self {
+        self.x = x;
+        self
+    }
+
+    pub fn with_y(mut self, y: u8) -> Self {
+        self.y = y;
+        self
+    }
+
+    pub fn with_status(mut self, status: u8) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_sp(mut self, sp: u8) -> Self {
+        self.sp = sp;
+        self
+    }
+
+    pub fn with_pc(mut self, pc: u16) -> Self {
+        self.pc = Some(pc);
+        self
+    }
+
+    // the count starts at 0 otherwise, nestest's log expects the 7 cycles of the reset sequence
+    pub fn with_cycle_count(mut self, cycles: u64) -> Self {
+        self.cycle_count = Some(cycles);
+        self
+    }
+
+    pub fn with_bcd(mut self, enabled: bool) -> Self {
+        self.bcd_enabled = enabled;
+        self
     }
 
-    fn mem_write_u16(&mut self, pos: u16, data: u16) {
-        self.bus.mem_write_u16(pos, data)
+    pub fn build(self) -> CPU<M> {
+        let mut cpu = CPU::new(self.bus);
+        cpu.power_on();
+        cpu.register_a = self.a;
+        cpu.register_x = self.x;
+        cpu.register_y = self.y;
+        cpu.status = self.status;
+        cpu.stack_pointer = self.sp;
+        if let Some(pc) = self.pc {
+            cpu.program_counter = pc;
+        }
+        if let Some(cycles) = self.cycle_count {
+            cpu.cycle_count = cycles;
+        }
+        cpu.bcd_enabled = self.bcd_enabled;
+        cpu
     }
 }
 
-impl CPU {
-    pub fn new(bus: Bus) -> Self {
+impl<M: Mem> CPU<M> {
+    pub fn new(bus: M) -> Self {
         CPU {
             register_a: 0,
             register_x: 0,
             register_y: 0,
             program_counter: 0,
-            stack_pointer: 0,
-            status: 0,
+            stack_pointer: STACK_RESET,
+            status: POWER_ON_STATUS,
+            bcd_enabled: false,
+            cycle_count: 0,
+            nmi_pending: false,
+            irq_pending: false,
             // memory: [0; 0xFFFF],
-            bus: bus
+            bus: bus,
+            instruction_counts: None,
+            breakpoints: BTreeSet::new(),
+            resume_from: None,
+            watchpoints: BTreeMap::new(),
+            watch_callback: None,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "debug")]
+            history: default_history(),
         }
     }
 
-    pub fn reset(&mut self) {
+    /*
+     * Power on pathway: clears the registers, sets the status to $34 and runs the
+     * reset sequence, which leaves the stack pointer at $FD. RAM, including anything
+     * put there by load, is left alone.
+     */
+    pub fn power_on(&mut self) {
         self.register_a = 0;
         self.register_x = 0;
         self.register_y = 0;
-        self.status = 0;
-        self.stack_pointer = STACK_RESET;
+        self.status = POWER_ON_STATUS;
+        self.stack_pointer = 0;
+        self.reset();
+    }
+
+    /*
+     * Reset button: jumps through the cartridge's reset vector at $FFFC. The reset
+     * sequence is an interrupt whose three pushes are turned into reads, so only the
+     * stack pointer moves. A, X, Y and the flags other than I are kept.
+     */
+    pub fn reset(&mut self) {
+        self.stack_pointer = self.stack_pointer.wrapping_sub(3);
+        // IRQs stay masked until the program is ready for them
+        self.set_flag(Flag::Interrupt, true);
 
-        self.program_counter = self.mem_read_u16(0xFFFC);
+        self.program_counter = self.mem_read_u16(RESET_VECTOR);
     }
 
     // pub fn mem_read(&self, addr: u16) -> u8 {
@@ -102,162 +416,514 @@ impl CPU {
     //     (high << 8) | (low as u16)
     // }
 
-    // pub fn mem_write_u16(&mut self, pos: u16, data: u16) {
-    //     let low = (data & 0x0F) as u8;
-    //     let high = (data >> 8) as u8;
-    //     self.mem_write(pos, low);
-    //     self.mem_write(pos + 1, high);
-    // }
+    pub fn elapsed_cycles(&self) -> u64 {
+        self.cycle_count
+    }
+
+    pub fn bcd_enabled(&self) -> bool {
+        self.bcd_enabled
+    }
+
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    pub fn bus(&self) -> &M {
+        &self.bus
+    }
+
+    // for wiring the bus up, e.g. controllers or the unmapped handler, not for touching CPU state
+    pub fn bus_mut(&mut self) -> &mut M {
+        &mut self.bus
+    }
+
+    // the TV system of the bus, front ends pace frames against region().cpu_clock_hz()
+    pub fn region(&self) -> Region {
+        self.bus.region()
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn get_sp(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    pub fn get_a(&self) -> u8 {
+        self.register_a
+    }
+
+    pub fn get_x(&self) -> u8 {
+        self.register_x
+    }
+
+    pub fn get_y(&self) -> u8 {
+        self.register_y
+    }
+
+    pub fn get_status(&self) -> u8 {
+        self.status
+    }
+
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            a: self.register_a,
+            x: self.register_x,
+            y: self.register_y,
+            pc: self.program_counter,
+            sp: self.stack_pointer,
+            status: self.status,
+            cycle: self.cycle_count,
+        }
+    }
+
+    pub fn enable_profiling(&mut self) {
+        if self.instruction_counts.is_none() {
+            self.instruction_counts = Some(BTreeMap::new());
+        }
+    }
+
+    pub fn instruction_counts(&self) -> Option<&BTreeMap<u8, u64>> {
+        self.instruction_counts.as_ref()
+    }
+
+    // hex dump of the memory behind the CPU, read past the watchpoints, see debug::mem_dump
+    pub fn mem_dump(&mut self, start: u16, len: u16) -> String {
+        debug::mem_dump(&mut self.bus, start, len)
+    }
+
+    // (opcode, mnemonic, count) hottest first, empty unless profiling is enabled
+    pub fn profile_report(&self) -> Vec<(u8, &'static str, u64)> {
+        let mut report: Vec<(u8, &'static str, u64)> = self
+            .instruction_counts
+            .iter()
+            .flatten()
+            .map(|(&code, &count)| {
+                let mnemonic = opcodes::OPCODE_TABLE[code as usize].map_or("???", |op| op.mnemonic);
+                (code, mnemonic, count)
+            })
+            .collect();
+        report.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        report
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn add_watchpoint(&mut self, addr: u16, mode: WatchMode) {
+        self.watchpoints.insert(addr, mode);
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: u16) {
+        self.watchpoints.remove(&addr);
+    }
+
+    /*
+     * A flag that stops run_with_callback before the next instruction, it can be
+     * set from another thread. The run clears it when it stops.
+     */
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop_flag)
+    }
+
+    // called with the address, the value read or written and the kind of access
+    pub fn set_watch_callback(&mut self, callback: WatchCallback) {
+        self.watch_callback = Some(callback);
+    }
+
+    fn check_watchpoint(&mut self, addr: u16, data: u8, access: WatchMode) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+        let watched = match self.watchpoints.get(&addr) {
+            Some(mode) => mode.matches(access),
+            None => false,
+        };
+        if let (true, Some(callback)) = (watched, self.watch_callback.as_mut()) {
+            callback(addr, data, access);
+        }
+    }
+
+    // the last n instructions executed, oldest first. After an error the failing one is last
+    #[cfg(feature = "debug")]
+    pub fn last_n_instructions(&mut self, n: usize) -> &[TraceEntry] {
+        self.history.last(n)
+    }
+
+    // the trace peeks, so the watchpoints only see the opcode fetch
+    #[cfg(feature = "debug")]
+    fn record_history(&mut self) {
+        let entry = trace::trace(self);
+        self.history.record(entry);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn print_hot_opcodes(&self, n: usize) {
+        if self.instruction_counts.is_none() {
+            println!("Profiling is not enabled");
+            return;
+        }
+
+        for (code, mnemonic, count) in self.profile_report().into_iter().take(n) {
+            println!("{:02x} {} {}", code, mnemonic, count);
+        }
+    }
 
-    pub fn load_and_run(&mut self, program: Vec<u8>) {
+    // powers on, then runs the program from $0600 whatever the reset vector says
+    pub fn load_and_run(&mut self, program: Vec<u8>) -> Result<(), CpuError> {
         self.load(program);
-        self.run();
+        self.power_on();
+        self.program_counter = PROGRAM_START;
+        self.run()
     }
 
+    /*
+     * Test program pathway: copies the program into RAM at $0600 and points
+     * the PC at it directly. The reset vector lives in cartridge ROM and is
+     * not touched, so a reset after load goes wherever the cartridge says.
+     */
     pub fn load(&mut self, program: Vec<u8>) {
         for i in 0..(program.len() as u16) {
-            self.mem_write(0x0600 + i, program[i as usize]);
+            self.mem_write(PROGRAM_START + i, program[i as usize]);
+        }
+        self.program_counter = PROGRAM_START;
+    }
+
+    /*
+     * Copies the program to addr and points the reset vector and the PC at it.
+     * ROM addresses are patched through the cartridge, which only NROM boards allow.
+     */
+    pub fn load_at(&mut self, program: Vec<u8>, addr: u16) {
+        for (i, byte) in program.iter().enumerate() {
+            self.bus.poke(addr.wrapping_add(i as u16), *byte);
         }
-        self.mem_write_u16(0xFFFC, 0x0600);
-        self.program_counter = 0x0600;
+        let [lo, hi] = addr.to_le_bytes();
+        self.bus.poke(RESET_VECTOR, lo);
+        self.bus.poke(RESET_VECTOR + 1, hi);
+        self.program_counter = addr;
     }
 
-    pub fn run_with_callback<F>(&mut self, mut callback: F)
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> Result<(), CpuError>
     where
-        F: FnMut(&mut CPU),
+        F: FnMut(&mut CPU<M>),
     {
-        let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
-
         loop {
+            if self.stop_flag.swap(false, Ordering::Relaxed) {
+                return Err(CpuError::Stopped);
+            }
+
+            if !self.breakpoints.is_empty() {
+                let pc = self.program_counter;
+                if self.breakpoints.contains(&pc) && self.resume_from != Some(pc) {
+                    self.resume_from = Some(pc);
+                    return Err(CpuError::Breakpoint(pc));
+                }
+                self.resume_from = None;
+            }
+
+            let cycles_before = self.cycle_count;
+            self.poll_interrupts();
+
             callback(self);
-            let code = self.mem_read(self.program_counter);
-            self.program_counter += 1;
-            let program_counter_state = self.program_counter;
-
-            let opcode = opcodes
-                .get(&code)
-                .expect(&format!("OpCode {:x} is not recognized", code));
-            match code {
-                //
-                //                  LOAD/STORE OPERATIONS
-                //
-                0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => self.ldx(&opcode.mode),
-                0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => self.ldy(&opcode.mode),
-                0x86 | 0x96 | 0x8e => self.stx(&opcode.mode),
-                0x84 | 0x94 | 0x8c => self.sty(&opcode.mode),
-                0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => self.sta(&opcode.mode),
-                0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => self.lda(&opcode.mode),
-
-                //
-                //                  REGISTER TRANSFER
-                //
-                0xaa => self.tax(&opcode.mode),
-                0xa8 => self.tay(&opcode.mode),
-                0x8a => self.txa(&opcode.mode),
-                0x98 => self.tay(&opcode.mode),
-
-                //
-                //                  STACK OPERATIONS
-                //
-                0x08 => self.php(&opcode.mode),
-                0x48 => self.pha(&opcode.mode),
-                0x68 => self.pla(&opcode.mode),
-                0x28 => self.plp(&opcode.mode),
-                0xba => self.tsx(&opcode.mode),
-                0x9a => self.txs(&opcode.mode),
-
-                //
-                //                  LOGICAL
-                //
-                0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => self.and(&opcode.mode),
-                0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => self.eor(&opcode.mode),
-                0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => self.ora(&opcode.mode),
-                0x24 | 0x2c => self.bit(&opcode.mode),
-
-                //
-                //                  ARITHMETIC
-                //
-                0xe9 | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => self.sbc(&opcode.mode),
-                0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => self.adc(&opcode.mode),
-                0xc9 | 0xc5 | 0xd5 | 0xcd | 0xdd | 0xd9 | 0xc1 | 0xd1 => self.cmp(&opcode.mode),
-                0xe0 | 0xe4 | 0xec => self.cpx(&opcode.mode),
-                0xc0 | 0xc4 | 0xcc => self.cpy(&opcode.mode),
-
-                //
-                //                  Increments & Decrements
-                //
-                0xe8 => self.inx(&opcode.mode),
-                0xc8 => self.iny(&opcode.mode),
-                0x88 => self.dey(&opcode.mode),
-                0xca => self.dex(&opcode.mode),
-                0xe6 | 0xf6 | 0xee | 0xfe => self.inc(&opcode.mode),
-                0xc6 | 0xd6 | 0xce | 0xde => self.dec(&opcode.mode),
-
-                //
-                //                  Shifts
-                //
-                0x0a => self.asl_a(&opcode.mode),
-                0x4a => self.lsr_a(&opcode.mode),
-                0x6a => self.ror_a(&opcode.mode),
-                0x2a => self.rol_a(&opcode.mode),
-                0x06 | 0x16 | 0x0e | 0x1e => self.asl(&opcode.mode),
-                0x66 | 0x76 | 0x6e | 0x7e => self.ror(&opcode.mode),
-                0x26 | 0x36 | 0x2e | 0x3e => self.rol(&opcode.mode),
-                0x46 | 0x56 | 0x4e | 0x5e => self.lsr(&opcode.mode),
-
-                //
-                //                  Jumps & Calls
-                //
-                0x60 => self.rts(&opcode.mode),
-                0x20 => self.jsr(&opcode.mode),
-                0x4c | 0x6c => self.jmp(&opcode.mode),
-
-                //
-                //                  Branches
-                //
-                0x70 => self.branch(self.get_flag(Flag::Overflow) == true),
-                0x50 => self.branch(self.get_flag(Flag::Overflow) == false),
-                0x30 => self.branch(self.get_flag(Flag::Negative) == true),
-                0x10 => self.branch(self.get_flag(Flag::Negative) == false),
-                0xf0 => self.branch(self.get_flag(Flag::Zero) == true),
-                0xd0 => self.branch(self.get_flag(Flag::Zero) == false),
-                0xb0 => self.branch(self.get_flag(Flag::Carry) == true),
-                0x90 => self.branch(self.get_flag(Flag::Carry) == false),
-
-                //
-                //                  Status Flag Changes
-                //
-                0x18 => self.clc(&opcode.mode),
-                0xd8 => self.cld(&opcode.mode),
-                0x58 => self.cli(&opcode.mode),
-                0xb8 => self.clv(&opcode.mode),
-                0x38 => self.sec(&opcode.mode),
-                0xf8 => self.sed(&opcode.mode),
-                0x78 => self.sei(&opcode.mode),
-
-                //
-                //                  System Functions
-                //
-                0xea => {}
-                0x40 => self.rti(&opcode.mode),
-                0x00 => return,
-
-                _ => {}
+            match self.execute(cycles_before) {
+                Ok(_) => {}
+                Err(CpuError::Break) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /*
+     * Services a pending interrupt, then executes exactly one instruction.
+     * BRK is executed and reported as CpuError::Break.
+     */
+    pub fn step(&mut self) -> Result<StepResult, CpuError> {
+        let cycles_before = self.cycle_count;
+        self.poll_interrupts();
+        self.execute(cycles_before)
+    }
+
+    /*
+     * Runs whole instructions while they fit in budget and returns the cycles used.
+     * The fit is judged by the opcode's base cycles, so a page crossing, a taken
+     * branch, an interrupt or a DMA stall can carry the last one a few cycles over.
+     */
+    pub fn run_for_cycles(&mut self, budget: u64) -> Result<u64, CpuError> {
+        let start = self.cycle_count;
+        loop {
+            // peeked past the watchpoints, the instruction has not run yet
+            let code = self.bus.peek(self.program_counter);
+            // unknown opcodes are left to step to report
+            let cycles = opcodes::OPCODE_TABLE[code as usize].map_or(0, |opcode| opcode.cycles as u64);
+            if self.cycle_count - start + cycles > budget {
+                return Ok(self.cycle_count - start);
+            }
+            self.step()?;
+        }
+    }
+
+    // steps the CPU one instruction per item, see CpuIter
+    pub fn iter(&mut self) -> CpuIter<'_, M> {
+        CpuIter { cpu: self, done: false }
+    }
+
+    fn poll_interrupts(&mut self) {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.trigger_nmi();
+        }
+
+        // a masked IRQ stays pending until the interrupt disable flag is cleared
+        if self.irq_pending && !self.get_flag(Flag::Interrupt) {
+            self.irq_pending = false;
+            self.trigger_irq();
+        }
+    }
+
+    // runs the instruction at the program counter, cycles_before is where the interrupts started counting
+    fn execute(&mut self, cycles_before: u64) -> Result<StepResult, CpuError> {
+        #[cfg(feature = "debug")]
+        self.record_history();
+
+        let pc_before = self.program_counter;
+        let code = self.mem_read(self.program_counter);
+        self.program_counter = self.program_counter.wrapping_add(1);
+        let program_counter_state = self.program_counter;
+
+        let opcode = match opcodes::OPCODE_TABLE[code as usize] {
+            Some(opcode) => opcode,
+            None => return Err(CpuError::UnknownOpcode(code)),
+        };
+
+        if let Some(counts) = self.instruction_counts.as_mut() {
+            *counts.entry(code).or_insert(0) += 1;
+        }
+
+        self.cycle_count += opcode.cycles as u64;
+        if has_page_cross_penalty(opcode) && self.page_crossed(&opcode.mode) {
+            self.cycle_count += 1;
+        }
+
+        match code {
+            //
+            //                  LOAD/STORE OPERATIONS
+            //
+            0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => self.ldx(&opcode.mode),
+            0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => self.ldy(&opcode.mode),
+            0x86 | 0x96 | 0x8e => self.stx(&opcode.mode),
+            0x84 | 0x94 | 0x8c => self.sty(&opcode.mode),
+            0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => self.sta(&opcode.mode),
+            0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => self.lda(&opcode.mode),
+
+            //
+            //                  REGISTER TRANSFER
+            //
+            0xaa => self.tax(&opcode.mode),
+            0xa8 => self.tay(&opcode.mode),
+            0x8a => self.txa(&opcode.mode),
+            0x98 => self.tya(&opcode.mode),
+
+            //
+            //                  STACK OPERATIONS
+            //
+            0x08 => self.php(&opcode.mode),
+            0x48 => self.pha(&opcode.mode),
+            0x68 => self.pla(&opcode.mode),
+            0x28 => self.plp(&opcode.mode),
+            0xba => self.tsx(&opcode.mode),
+            0x9a => self.txs(&opcode.mode),
+
+            //
+            //                  LOGICAL
+            //
+            0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => self.and(&opcode.mode),
+            0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => self.eor(&opcode.mode),
+            0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => self.ora(&opcode.mode),
+            0x24 | 0x2c => self.bit(&opcode.mode),
+
+            //
+            //                  ARITHMETIC
+            //
+            0xe9 | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => self.sbc(&opcode.mode),
+            0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => self.adc(&opcode.mode),
+            0xc9 | 0xc5 | 0xd5 | 0xcd | 0xdd | 0xd9 | 0xc1 | 0xd1 => self.cmp(&opcode.mode),
+            0xe0 | 0xe4 | 0xec => self.cpx(&opcode.mode),
+            0xc0 | 0xc4 | 0xcc => self.cpy(&opcode.mode),
+
+            //
+            //                  Increments & Decrements
+            //
+            0xe8 => self.inx(&opcode.mode),
+            0xc8 => self.iny(&opcode.mode),
+            0x88 => self.dey(&opcode.mode),
+            0xca => self.dex(&opcode.mode),
+            0xe6 | 0xf6 | 0xee | 0xfe => self.inc(&opcode.mode),
+            0xc6 | 0xd6 | 0xce | 0xde => self.dec(&opcode.mode),
+
+            //
+            //                  Shifts
+            //
+            0x0a => self.asl_a(&opcode.mode),
+            0x4a => self.lsr_a(&opcode.mode),
+            0x6a => self.ror_a(&opcode.mode),
+            0x2a => self.rol_a(&opcode.mode),
+            0x06 | 0x16 | 0x0e | 0x1e => self.asl(&opcode.mode),
+            0x66 | 0x76 | 0x6e | 0x7e => self.ror(&opcode.mode),
+            0x26 | 0x36 | 0x2e | 0x3e => self.rol(&opcode.mode),
+            0x46 | 0x56 | 0x4e | 0x5e => self.lsr(&opcode.mode),
+
+            //
+            //                  Jumps & Calls
+            //
+            0x60 => self.rts(&opcode.mode),
+            0x20 => self.jsr(&opcode.mode),
+            0x4c | 0x6c => self.jmp(&opcode.mode),
+
+            //
+            //                  Branches
+            //
+            0x70 => self.branch(self.get_flag(Flag::Overflow) == true),
+            0x50 => self.branch(self.get_flag(Flag::Overflow) == false),
+            0x30 => self.branch(self.get_flag(Flag::Negative) == true),
+            0x10 => self.branch(self.get_flag(Flag::Negative) == false),
+            0xf0 => self.branch(self.get_flag(Flag::Zero) == true),
+            0xd0 => self.branch(self.get_flag(Flag::Zero) == false),
+            0xb0 => self.branch(self.get_flag(Flag::Carry) == true),
+            0x90 => self.branch(self.get_flag(Flag::Carry) == false),
+
+            //
+            //                  Status Flag Changes
+            //
+            0x18 => self.clc(&opcode.mode),
+            0xd8 => self.cld(&opcode.mode),
+            0x58 => self.cli(&opcode.mode),
+            0xb8 => self.clv(&opcode.mode),
+            0x38 => self.sec(&opcode.mode),
+            0xf8 => self.sed(&opcode.mode),
+            0x78 => self.sei(&opcode.mode),
+
+            //
+            //                  System Functions
+            //
+            0xea => {}
+            0x40 => self.rti(&opcode.mode),
+            0x00 => {
+                self.brk(&opcode.mode);
+                return Err(CpuError::Break);
             }
-            if program_counter_state == self.program_counter {
-                self.program_counter += (opcode.len - 1) as u16;
+
+            //
+            //                  Unofficial opcodes
+            //
+            0xa7 | 0xb7 | 0xaf | 0xbf | 0xa3 | 0xb3 => self.lax(&opcode.mode),
+            0x87 | 0x97 | 0x8f | 0x83 => self.sax(&opcode.mode),
+            0xc7 | 0xd7 | 0xcf | 0xdf | 0xdb | 0xc3 | 0xd3 => self.dcp(&opcode.mode),
+            0xe7 | 0xf7 | 0xef | 0xff | 0xfb | 0xe3 | 0xf3 => self.isb(&opcode.mode),
+            0x07 | 0x17 | 0x0f | 0x1f | 0x1b | 0x03 | 0x13 => self.slo(&opcode.mode),
+            0x27 | 0x37 | 0x2f | 0x3f | 0x3b | 0x23 | 0x33 => self.rla(&opcode.mode),
+            0x47 | 0x57 | 0x4f | 0x5f | 0x5b | 0x43 | 0x53 => self.sre(&opcode.mode),
+            0x67 | 0x77 | 0x6f | 0x7f | 0x7b | 0x63 | 0x73 => self.rra(&opcode.mode),
+            0x4b => self.alr(&opcode.mode),
+            0x0b | 0x2b => self.anc(&opcode.mode),
+            0x6b => self.arr(&opcode.mode),
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2 | 0xf2 => {
+                return Err(CpuError::Halt(code));
             }
+
+            _ => {}
+        }
+        if program_counter_state == self.program_counter {
+            self.program_counter = self.program_counter.wrapping_add((opcode.len - 1) as u16);
+        }
+
+        self.cycle_count += self.bus.take_dma_stall_cycles();
+        self.bus.tick(self.cycle_count - cycles_before);
+        if self.bus.poll_nmi_status().is_some() {
+            self.nmi_pending = true;
+        }
+        if self.bus.poll_irq_status().is_some() {
+            self.irq_pending = true;
+        }
+
+        Ok(StepResult {
+            cycles_consumed: self.cycle_count - cycles_before,
+            pc_before,
+            pc_after: self.program_counter,
+        })
+    }
+
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        self.run_with_callback(|_| {})
+    }
+
+    // runs the program, handing the state before every instruction to trace_callback
+    pub fn run_with_trace<F>(&mut self, mut trace_callback: F) -> Result<(), CpuError>
+    where
+        F: FnMut(TraceEntry),
+    {
+        self.run_with_callback(|cpu| trace_callback(trace::trace(cpu)))
+    }
+
+    pub fn trigger_nmi(&mut self) {
+        self.interrupt(NMI_VECTOR, false);
+        self.cycle_count += 7;
+    }
+
+    pub fn trigger_irq(&mut self) {
+        if self.get_flag(Flag::Interrupt) {
+            return;
         }
+        self.interrupt(IRQ_BRK_VECTOR, false);
+        self.cycle_count += 7;
     }
 
-    pub fn run(&mut self) {
-        self.run_with_callback(|_| {});
+    fn interrupt(&mut self, vector: u16, break_flag: bool) {
+        self.push_stack_u16(self.program_counter);
+        let mut status = self.status | Flag::Break2 as u8;
+        if break_flag {
+            status |= Flag::Break as u8;
+        } else {
+            status &= !(Flag::Break as u8);
+        }
+        self.push_stack(status);
+        self.set_flag(Flag::Interrupt, true);
+
+        self.program_counter = self.mem_read_u16(vector);
     }
 
     fn adc(&mut self, mode: &AddressingMode) {
         let addr = self.fetch(mode);
         let m = self.mem_read(addr);
 
+        if self.decimal_mode() {
+            self.add_decimal(m);
+        } else {
+            self.add_to_register_a(m);
+        }
+    }
+
+    fn sbc(&mut self, mode: &AddressingMode) {
+        let addr = self.fetch(mode);
+        let m = self.mem_read(addr);
+
+        if self.decimal_mode() {
+            self.sub_decimal(m);
+        } else {
+            self.add_to_register_a(!m);
+        }
+    }
+
+    fn add_to_register_a(&mut self, m: u8) {
         let tmp = self.register_a as u16 + m as u16 + (self.status & 0x01) as u16;
 
         self.set_flag(Flag::Zero, tmp & 0x00FF == 0);
@@ -271,21 +937,62 @@ impl CPU {
         self.register_a = (tmp & 0x00FF) as u8;
     }
 
-    fn sbc(&mut self, mode: &AddressingMode) {
-        let addr = self.fetch(mode);
-        let m = !self.mem_read(addr);
+    fn decimal_mode(&self) -> bool {
+        self.bcd_enabled && self.get_flag(Flag::Decimal)
+    }
 
-        let tmp = self.register_a as u16 + m as u16 + (self.status & 0x01) as u16;
+    /*
+     * BCD addition as done by the NMOS 6502:
+     * each nibble is adjusted by 6 when it overflows past 9, Z comes from the binary sum
+     * while N and V come from the intermediate result before the high nibble adjustment.
+     */
+    fn add_decimal(&mut self, m: u8) {
+        let a = self.register_a;
+        let carry = (self.status & 0x01) as u16;
 
-        self.set_flag(Flag::Zero, tmp & 0x00FF == 0);
-        self.set_flag(Flag::Negative, tmp & 0x0080 != 0);
-        self.set_flag(Flag::Carry, tmp & 0x0100 != 0);
+        let binary = a as u16 + m as u16 + carry;
+        self.set_flag(Flag::Zero, binary & 0x00FF == 0);
+
+        let mut lo = (a & 0x0F) as u16 + (m & 0x0F) as u16 + carry;
+        if lo > 0x09 {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (a & 0xF0) as u16 + (m & 0xF0) as u16 + lo;
+
+        self.set_flag(Flag::Negative, sum & 0x0080 != 0);
         self.set_flag(
             Flag::Overflow,
-            (self.register_a as u16 ^ tmp) & !(self.register_a as u16 ^ m as u16) & 0x0080 != 0,
+            (a as u16 ^ sum) & !(a as u16 ^ m as u16) & 0x0080 != 0,
         );
 
-        self.register_a = (tmp & 0x00FF) as u8;
+        if sum > 0x9F {
+            sum += 0x60;
+        }
+        self.set_flag(Flag::Carry, sum > 0xFF);
+
+        self.register_a = (sum & 0x00FF) as u8;
+    }
+
+    /*
+     * BCD subtraction as done by the NMOS 6502:
+     * all flags come from the binary subtraction, only the result is decimal adjusted.
+     */
+    fn sub_decimal(&mut self, m: u8) {
+        let a = self.register_a;
+        let carry = (self.status & 0x01) as i16;
+
+        self.add_to_register_a(!m);
+
+        let mut lo = (a & 0x0F) as i16 - (m & 0x0F) as i16 + carry - 1;
+        if lo < 0 {
+            lo = ((lo - 0x06) & 0x0F) - 0x10;
+        }
+        let mut result = (a & 0xF0) as i16 - (m & 0xF0) as i16 + lo;
+        if result < 0 {
+            result -= 0x60;
+        }
+
+        self.register_a = (result & 0x00FF) as u8;
     }
     fn sec(&mut self, mode: &AddressingMode) {
         self.set_flag(Flag::Carry, true);
@@ -342,15 +1049,29 @@ impl CPU {
     fn branch(&mut self, condition: bool) {
         if condition {
             let jmp = self.mem_read(self.program_counter) as i8;
-            let jmp_addr = self
-                .program_counter
-                .wrapping_add(1)
-                .wrapping_add(jmp as u16);
+            let next_addr = self.program_counter.wrapping_add(1);
+            let jmp_addr = next_addr.wrapping_add(jmp as u16);
+
+            // +1 cycle if the branch is taken, +2 if it lands on another page
+            self.cycle_count += 1;
+            if next_addr & 0xFF00 != jmp_addr & 0xFF00 {
+                self.cycle_count += 1;
+            }
+
             self.program_counter = jmp_addr;
         }
     }
 
-    fn brk(&mut self, mode: &AddressingMode) {}
+    /*
+     * BRK - Force Interrupt
+     * Pushes the address of the byte following the BRK padding byte and the status
+     * (with bits 4 and 5 set), sets the interrupt disable flag and loads the IRQ/BRK vector.
+     */
+    fn brk(&mut self, mode: &AddressingMode) {
+        // BRK is followed by a padding byte which is skipped on return
+        self.program_counter = self.program_counter.wrapping_add(1);
+        self.interrupt(IRQ_BRK_VECTOR, true);
+    }
 
     fn dec(&mut self, mode: &AddressingMode) {
         let addr = self.fetch(mode);
@@ -393,9 +1114,9 @@ impl CPU {
     }
     fn jsr(&mut self, mode: &AddressingMode) {
         let jump_addr = self.fetch(mode);
-        let pc = self.program_counter.clone();
+        // the pushed address is the last byte of the JSR instruction, RTS adds one after pulling it
+        self.push_stack_u16(self.program_counter.wrapping_add(1));
         self.program_counter = jump_addr;
-        self.push_stack_u16(pc.wrapping_add(2));
     }
 
     fn lsr(&mut self, mode: &AddressingMode) {
@@ -481,20 +1202,25 @@ impl CPU {
     }
 
     fn pha(&mut self, mode: &AddressingMode) {
-        let accumulator = self.register_a.clone();
-        self.push_stack(accumulator);
+        self.push_stack(self.register_a);
     }
     fn php(&mut self, mode: &AddressingMode) {
-        let status = self.status.clone();
+        // Break bits only exist on the pushed copy, the live status is left untouched
+        let status = self.status | Flag::Break as u8 | Flag::Break2 as u8;
         self.push_stack(status);
-        self.set_flag(Flag::Break, true);
-        self.set_flag(Flag::Break2, true);
     }
     fn pla(&mut self, mode: &AddressingMode) {
         self.register_a = self.pop_stack();
+        self.set_zero_and_negative_flag(self.register_a);
     }
     fn plp(&mut self, mode: &AddressingMode) {
-        self.status = self.pop_stack();
+        let status = self.pop_stack();
+        self.restore_status(status);
+    }
+
+    fn restore_status(&mut self, data: u8) {
+        // bit 4 only exists on the stack copy and bit 5 always reads back as set
+        self.status = data;
         self.set_flag(Flag::Break, false);
         self.set_flag(Flag::Break2, true);
     }
@@ -571,10 +1297,20 @@ impl CPU {
 
         self.set_zero_and_negative_flag(self.register_a);
     }
-    fn rti(&mut self, mode: &AddressingMode) {}
-    fn rts(&mut self, mode: &AddressingMode) {
-        self.program_counter = self.pop_stack_u16();
-    }
+    /*
+     * RTI - Return from Interrupt
+     * Pulls the processor flags from the stack followed by the program counter.
+     * Bit 4 does not exist in the status register, so it is cleared and bit 5 is forced on.
+     */
+    fn rti(&mut self, mode: &AddressingMode) {
+        let status = self.pop_stack();
+        self.restore_status(status);
+
+        self.program_counter = self.pop_stack_u16();
+    }
+    fn rts(&mut self, mode: &AddressingMode) {
+        self.program_counter = self.pop_stack_u16().wrapping_add(1);
+    }
 
     fn tsx(&mut self, mode: &AddressingMode) {
         self.register_x = self.stack_pointer;
@@ -586,7 +1322,6 @@ impl CPU {
     }
     fn txs(&mut self, mode: &AddressingMode) {
         self.stack_pointer = self.register_x;
-        self.set_zero_and_negative_flag(self.stack_pointer);
     }
     fn tya(&mut self, mode: &AddressingMode) {
         self.register_a = self.register_y;
@@ -604,33 +1339,24 @@ impl CPU {
      * with another memory held value and sets the zero and carry flags as appropriate.
      */
     fn cpy(&mut self, mode: &AddressingMode) {
-        let addr = self.fetch(mode);
-        let m = self.mem_read(addr);
-        let tmp: i16 = self.register_y as i16 - m as i16;
-
-        self.set_flag(Flag::Zero, tmp == 0);
-        self.set_flag(Flag::Carry, tmp >= 0);
-        self.set_flag(Flag::Negative, tmp < 0);
+        self.compare(mode, self.register_y);
     }
 
     fn cpx(&mut self, mode: &AddressingMode) {
-        let addr = self.fetch(mode);
-        let m = self.mem_read(addr);
-        let cmp: i16 = self.register_x as i16 - m as i16;
-
-        self.set_flag(Flag::Zero, cmp == 0);
-        self.set_flag(Flag::Carry, cmp >= 0);
-        self.set_flag(Flag::Negative, cmp < 0);
+        self.compare(mode, self.register_x);
     }
 
     fn cmp(&mut self, mode: &AddressingMode) {
+        self.compare(mode, self.register_a);
+    }
+
+    fn compare(&mut self, mode: &AddressingMode, compare_with: u8) {
         let addr = self.fetch(mode);
         let m = self.mem_read(addr);
-        let cmp: i16 = self.register_a as i16 - m as i16;
 
-        self.set_flag(Flag::Zero, cmp == 0);
-        self.set_flag(Flag::Carry, cmp >= 0);
-        self.set_flag(Flag::Negative, cmp < 0);
+        // unsigned subtraction: carry means no borrow, negative is bit 7 of the result byte
+        self.set_flag(Flag::Carry, compare_with >= m);
+        self.set_zero_and_negative_flag(compare_with.wrapping_sub(m));
     }
 
     /*
@@ -665,6 +1391,113 @@ impl CPU {
         self.set_zero_and_negative_flag(self.register_a);
     }
 
+    /*
+     * LAX - Load Accumulator and X (unofficial)
+     * A,X,Z,N = M
+     */
+    fn lax(&mut self, mode: &AddressingMode) {
+        let addr = self.fetch(mode);
+        let data = self.mem_read(addr);
+        self.register_a = data;
+        self.register_x = data;
+        self.set_zero_and_negative_flag(data);
+    }
+
+    /*
+     * SAX - Store A AND X (unofficial)
+     * M = A & X, no flags are affected
+     */
+    fn sax(&mut self, mode: &AddressingMode) {
+        let addr = self.fetch(mode);
+        self.mem_write(addr, self.register_a & self.register_x);
+    }
+
+    /*
+     * DCP - Decrement memory then Compare (unofficial)
+     * M = M - 1, Z,C,N = A-M
+     */
+    fn dcp(&mut self, mode: &AddressingMode) {
+        self.dec(mode);
+        self.cmp(mode);
+    }
+
+    /*
+     * ISB - Increment memory then Subtract with Carry (unofficial)
+     * M = M + 1, A = A - M - (1 - C)
+     */
+    fn isb(&mut self, mode: &AddressingMode) {
+        self.inc(mode);
+        self.sbc(mode);
+    }
+
+    /*
+     * SLO - Arithmetic Shift Left memory then OR with accumulator (unofficial)
+     * M = M << 1, A = A | M
+     */
+    fn slo(&mut self, mode: &AddressingMode) {
+        self.asl(mode);
+        self.ora(mode);
+    }
+
+    /*
+     * RLA - Rotate Left memory then AND with accumulator (unofficial)
+     * M = M << 1 | C, A = A & M
+     */
+    fn rla(&mut self, mode: &AddressingMode) {
+        self.rol(mode);
+        self.and(mode);
+    }
+
+    /*
+     * SRE - Logical Shift Right memory then Exclusive OR with accumulator (unofficial)
+     * M = M >> 1, A = A ^ M
+     */
+    fn sre(&mut self, mode: &AddressingMode) {
+        self.lsr(mode);
+        self.eor(mode);
+    }
+
+    /*
+     * RRA - Rotate Right memory then Add with Carry (unofficial)
+     * M = C << 7 | M >> 1, A = A + M + C (the carry out of the rotate)
+     */
+    fn rra(&mut self, mode: &AddressingMode) {
+        self.ror(mode);
+        self.adc(mode);
+    }
+
+    /*
+     * ALR - AND with accumulator then Logical Shift Right accumulator (unofficial)
+     * A = (A & M) >> 1
+     */
+    fn alr(&mut self, mode: &AddressingMode) {
+        self.and(mode);
+        self.lsr_a(mode);
+    }
+
+    /*
+     * ANC - AND with accumulator then copy Negative into Carry (unofficial)
+     * A = A & M, C = N
+     */
+    fn anc(&mut self, mode: &AddressingMode) {
+        self.and(mode);
+        self.set_flag(Flag::Carry, self.get_flag(Flag::Negative));
+    }
+
+    /*
+     * ARR - AND with accumulator then Rotate Right accumulator (unofficial)
+     * A = C << 7 | (A & M) >> 1, C = bit 6 of A, V = bit 6 ^ bit 5 of A
+     */
+    fn arr(&mut self, mode: &AddressingMode) {
+        self.and(mode);
+        self.ror_a(mode);
+
+        let bit_6 = self.register_a & 0x40 != 0;
+        let bit_5 = self.register_a & 0x20 != 0;
+        self.set_flag(Flag::Carry, bit_6);
+        self.set_flag(Flag::Overflow, bit_6 ^ bit_5);
+    }
+
     fn sta(&mut self, mode: &AddressingMode) {
         let addr = self.fetch(mode);
         self.mem_write(addr, self.register_a);
@@ -709,7 +1542,26 @@ impl CPU {
         self.status & flag as u8 != 0
     }
 
-    fn fetch(&self, mode: &AddressingMode) -> u16 {
+    // worked out from peeks, the reads with side effects happen once, when the instruction fetches its operand
+    fn page_crossed(&mut self, mode: &AddressingMode) -> bool {
+        let pc = self.program_counter;
+        let (base, index) = match mode {
+            AddressingMode::Absolute_X | AddressingMode::Absolute_Y => {
+                let base = u16::from_le_bytes([self.bus.peek(pc), self.bus.peek(pc.wrapping_add(1))]);
+                let index = if let AddressingMode::Absolute_X = mode { self.register_x } else { self.register_y };
+                (base, index)
+            }
+            AddressingMode::Indirect_Y => {
+                let ptr = self.bus.peek(pc);
+                let base = u16::from_le_bytes([self.bus.peek(ptr as u16), self.bus.peek(ptr.wrapping_add(1) as u16)]);
+                (base, self.register_y)
+            }
+            _ => return false,
+        };
+        base & 0xFF00 != base.wrapping_add(index as u16) & 0xFF00
+    }
+
+    fn fetch(&mut self, mode: &AddressingMode) -> u16 {
         match mode {
             AddressingMode::Immediate => self.program_counter,
             AddressingMode::ZeroPage => self.mem_read(self.program_counter) as u16,
@@ -735,18 +1587,20 @@ impl CPU {
                 addr
             }
             AddressingMode::Indirect => {
-                let pos = self.mem_read(self.program_counter);
-                self.mem_read_u16(pos as u16)
+                let ptr = self.mem_read_u16(self.program_counter);
+                // 6502 bug: the high byte is not carried over to the next page
+                self.mem_read_u16_wrap_page(ptr)
             }
             AddressingMode::Indirect_X => {
                 let base = self.mem_read(self.program_counter);
                 let ptr: u8 = (base as u8).wrapping_add(self.register_x);
-                self.mem_read_u16(ptr as u16)
+                // pointers stored in the zero page wrap around at $FF instead of reading $0100
+                self.mem_read_u16_wrap_page(ptr as u16)
             }
             AddressingMode::Indirect_Y => {
                 let base = self.mem_read(self.program_counter);
-                let ptr: u8 = (base as u8).wrapping_add(self.register_y);
-                self.mem_read_u16(ptr as u16)
+                let deref_base = self.mem_read_u16_wrap_page(base as u16);
+                deref_base.wrapping_add(self.register_y as u16)
             }
             _ => {
                 panic!("mode {:?} is not supported", mode);
@@ -755,10 +1609,80 @@ impl CPU {
     }
 }
 
+// read instructions take an extra cycle when indexing crosses a page, writes always pay for it
+fn has_page_cross_penalty(opcode: &opcodes::OpCode) -> bool {
+    matches!(
+        opcode.mnemonic,
+        "LDA" | "LDX" | "LDY" | "AND" | "EOR" | "ORA" | "ADC" | "SBC" | "CMP" | "*LAX"
+    )
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use crate::cartridge::Mirroring;
+    use crate::mapper::Mapper4;
+    use proptest::prelude::*;
+    use std::sync::Mutex;
+
+    // 32KB of PRG-ROM whose reset vector points at the program loaded at $0600
+    fn test_bus() -> Bus {
+        let mut rom = vec![0; 0x8000];
+        rom[0x7ffc] = 0x00;
+        rom[0x7ffd] = 0x06;
+        Bus::new_with_rom(rom)
+    }
+
+    // plain 64KB for instruction tests, the reset vector points at $0600 as well
+    fn test_memory() -> FlatMemory {
+        let mut memory = FlatMemory::new();
+        memory.mem_write_u16(RESET_VECTOR, PROGRAM_START);
+        memory
+    }
+
+    // FlatMemory that counts the reads with side effects, peeks are not counted
+    struct CountingMemory {
+        memory: FlatMemory,
+        reads: usize,
+    }
+
+    impl Mem for CountingMemory {
+        fn mem_read(&mut self, addr: u16) -> u8 {
+            self.reads += 1;
+            self.memory.mem_read(addr)
+        }
+        fn mem_write(&mut self, addr: u16, data: u8) {
+            self.memory.mem_write(addr, data)
+        }
+        fn peek(&mut self, addr: u16) -> u8 {
+            self.memory.peek(addr)
+        }
+    }
+
+    fn counting_cpu(program: Vec<u8>) -> CPU<CountingMemory> {
+        let mut cpu = CPU::new(CountingMemory { memory: test_memory(), reads: 0 });
+        cpu.load(program);
+        cpu.bus.reads = 0;
+        cpu
+    }
+
+    // load_and_run powers the CPU on, setup runs after that, right before the first instruction
+    fn load_and_run_with<M: Mem, F>(cpu: &mut CPU<M>, program: Vec<u8>, setup: F)
+    where
+        F: FnOnce(&mut CPU<M>),
+    {
+        let mut setup = Some(setup);
+        cpu.load(program);
+        cpu.power_on();
+        cpu.program_counter = PROGRAM_START;
+        cpu.run_with_callback(|cpu| {
+            if let Some(setup) = setup.take() {
+                setup(cpu);
+            }
+        })
+        .unwrap();
+    }
 
     // #[test]
     // fn test_jump_and_call() {
@@ -772,387 +1696,2348 @@ mod test {
     // }
 
     #[test]
-    fn test_stack_function() {
-        // a9 aa 08 48 28 68
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa9, 0xaa, 0x08, 0x48, 0x28, 0x68, 0x00]);
+    fn test_load_and_run_ignores_reset_vector() {
+        // a9 05 00 at $0600, a9 ff 00 at the reset vector $8000
+        let mut rom = vec![0; 0x8000];
+        rom[0..3].copy_from_slice(&[0xa9, 0xff, 0x00]);
+        rom[0x7ffc] = 0x00;
+        rom[0x7ffd] = 0x80;
+        let mut cpu = CPU::new(Bus::new_with_rom(rom));
+        cpu.load_and_run(vec![0xa9, 0x05, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x05);
+    }
 
-        assert_eq!(cpu.register_a, 0x80);
-        assert_eq!(cpu.status, 0xaa);
+    #[test]
+    fn test_addressing_mode_display() {
+        let modes = [
+            (AddressingMode::Immediate, "#$nn"),
+            (AddressingMode::ZeroPage, "$nn"),
+            (AddressingMode::ZeroPage_X, "$nn,X"),
+            (AddressingMode::ZeroPage_Y, "$nn,Y"),
+            (AddressingMode::Absolute, "$nnnn"),
+            (AddressingMode::Absolute_X, "$nnnn,X"),
+            (AddressingMode::Absolute_Y, "$nnnn,Y"),
+            (AddressingMode::Indirect, "($nnnn)"),
+            (AddressingMode::Indirect_X, "($nn,X)"),
+            (AddressingMode::Indirect_Y, "($nn),Y"),
+            (AddressingMode::Accumulator, "A"),
+            (AddressingMode::NoneAddressing, ""),
+        ];
+        for (mode, expected) in modes {
+            assert_eq!(mode.to_string(), expected);
+        }
     }
 
     #[test]
-    fn test_bne() {
-        // a2 08 ca 8e 00 02 e0 03 d0 f8 8e 01 02 00
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![
-            0xa2, 0x08, 0xca, 0x8e, 0x00, 0x02, 0xe0, 0x03, 0xd0, 0xf8, 0x8e, 0x01, 0x02, 0x00,
-        ]);
-        assert_eq!(cpu.get_flag(Flag::Zero), true);
-        assert_eq!(cpu.register_x, 0x03);
+    fn test_flag_display() {
+        let flags = [
+            Flag::Negative,
+            Flag::Overflow,
+            Flag::Break2,
+            Flag::Break,
+            Flag::Decimal,
+            Flag::Interrupt,
+            Flag::Zero,
+            Flag::Carry,
+        ];
+        let letters: String = flags.iter().map(|flag| flag.to_string()).collect();
+        assert_eq!(letters, "NV-BDIZC");
     }
 
     #[test]
-    fn test_ror_a() {
-        // 38 a9 ec 6a
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0x38, 0xa9, 0xec, 0x6a, 0x00]);
+    fn test_cpu_error_display() {
+        assert_eq!(CpuError::UnknownOpcode(0x9e).to_string(), "unknown opcode $9E");
+        assert_eq!(CpuError::Halt(0x02).to_string(), "halted by *KIL ($02)");
+        assert_eq!(CpuError::Breakpoint(0x0603).to_string(), "breakpoint at $0603");
+        assert_eq!(CpuError::Break.to_string(), "BRK");
+        assert_eq!(CpuError::Stopped.to_string(), "stopped");
+    }
 
-        assert_eq!(cpu.register_a, 0xf6);
-        assert_eq!(cpu.get_flag(Flag::Negative), true);
-        assert_eq!(cpu.get_flag(Flag::Carry), false);
-        assert_eq!(cpu.get_flag(Flag::Zero), false);
+    #[test]
+    fn test_builder() {
+        let cpu = CpuBuilder::new(test_memory())
+            .with_a(0x01)
+            .with_x(0x02)
+            .with_y(0x03)
+            .with_status(0b1100_0001)
+            .with_sp(0xf0)
+            .with_pc(0x0634)
+            .with_cycle_count(7)
+            .with_bcd(true)
+            .build();
+        assert_eq!((cpu.get_a(), cpu.get_x(), cpu.get_y()), (0x01, 0x02, 0x03));
+        assert_eq!((cpu.get_status(), cpu.get_sp(), cpu.get_pc()), (0b1100_0001, 0xf0, 0x0634));
+        assert_eq!((cpu.elapsed_cycles(), cpu.bcd_enabled()), (7, true));
+
+        // defaults are the power on state
+        let cpu = CpuBuilder::new(test_memory()).build();
+        assert_eq!(cpu.get_status(), POWER_ON_STATUS);
+        assert_eq!(cpu.get_sp(), STACK_RESET);
+        assert_eq!(cpu.get_pc(), 0x0600);
+        assert_eq!((cpu.elapsed_cycles(), cpu.bcd_enabled()), (0, false));
     }
 
     #[test]
-    fn test_ror() {
-        // 38 a9 ed 85 02 66 02
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0x38, 0xa9, 0xed, 0x85, 0x02, 0x66, 0x02, 0x00]);
+    fn test_register_accessors() {
+        // a9 42 a2 10 a0 20 38 48
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0xa9, 0x42, 0xa2, 0x10, 0xa0, 0x20, 0x38, 0x48]);
+        cpu.power_on();
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
 
-        assert_eq!(cpu.mem_read(0x0002), 0xf6);
-        assert_eq!(cpu.get_flag(Flag::Negative), true);
-        assert_eq!(cpu.get_flag(Flag::Carry), true);
-        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_a(), 0x42);
+        assert_eq!(cpu.get_x(), 0x10);
+        assert_eq!(cpu.get_y(), 0x20);
+        assert_eq!(cpu.get_status(), POWER_ON_STATUS | Flag::Carry as u8);
+        assert_eq!(cpu.get_sp(), STACK_RESET - 1);
+        assert_eq!(cpu.get_pc(), 0x0608);
     }
 
     #[test]
-    fn test_rol() {
-        // 38 a9 ec 85 02 26 02
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0x38, 0xa9, 0xec, 0x85, 0x02, 0x26, 0x02, 0x00]);
-
-        assert_eq!(cpu.mem_read(0x0002), 0xd9);
-        assert_eq!(cpu.get_flag(Flag::Carry), true);
-        assert_eq!(cpu.get_flag(Flag::Zero), false);
-        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    fn test_snapshot_before_and_after_instructions() {
+        // a2 05 ca 48
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0xa2, 0x05, 0xca, 0x48]);
+        cpu.power_on();
+        let before = cpu.snapshot();
+
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        let after = cpu.snapshot();
+
+        let power_on = CpuSnapshot {
+            a: 0,
+            x: 0,
+            y: 0,
+            pc: 0x0600,
+            sp: STACK_RESET,
+            status: POWER_ON_STATUS,
+            cycle: 0,
+        };
+        assert_eq!(before, power_on);
+        assert_eq!(
+            after,
+            CpuSnapshot { x: 0x04, pc: 0x0604, sp: STACK_RESET - 1, cycle: 2 + 2 + 3, ..power_on }
+        );
+        // the snapshot is a copy, it does not follow the CPU
+        assert_eq!(before.pc, 0x0600);
     }
 
     #[test]
-    fn test_rol_a() {
-        //a9 76 2a
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0x38, 0xa9, 0xec, 0x2a, 0x00]);
-        assert_eq!(cpu.get_flag(Flag::Carry), true);
-        assert_eq!(cpu.get_flag(Flag::Zero), false);
-        assert_eq!(cpu.get_flag(Flag::Negative), true);
-        assert_eq!(cpu.register_a, 0xd9);
+    fn test_program_in_rom_through_reset_vector() {
+        // a9 05 aa 00 at $8000
+        let mut rom = vec![0xa9, 0x05, 0xaa, 0x00];
+        rom.resize(0x8000, 0);
+        rom[0x7ffc] = 0x00;
+        rom[0x7ffd] = 0x80;
+        let mut cpu = CPU::new(Bus::new_with_rom(rom));
+
+        cpu.reset();
+        assert_eq!(cpu.program_counter, 0x8000);
+        cpu.run().unwrap();
+        assert_eq!(cpu.register_x, 0x05);
     }
 
     #[test]
-    fn test_asl() {
-        //a9 ec 85 02 06 02
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa9, 0xec, 0x85, 0x02, 0x06, 0x02, 0x00]);
-        assert_eq!(cpu.get_flag(Flag::Carry), true);
-        assert_eq!(cpu.get_flag(Flag::Negative), true);
-        assert_eq!(cpu.get_flag(Flag::Zero), false);
-        assert_eq!(cpu.mem_read(0x0002), 0xd8);
+    fn test_load_at_rom_address() {
+        // a2 05 ca d0 fd 00
+        let rom = vec![0; 0x8000];
+        let mut cpu = CPU::new(Bus::new_with_rom(rom));
+        cpu.load_at(vec![0xa2, 0x05, 0xca, 0xd0, 0xfd, 0x00], 0xc000);
+
+        assert_eq!(cpu.program_counter, 0xc000);
+        assert_eq!(cpu.mem_read_u16(RESET_VECTOR), 0xc000);
+
+        cpu.reset();
+        assert_eq!(cpu.program_counter, 0xc000);
+        cpu.run().unwrap();
+        assert_eq!(cpu.register_x, 0);
     }
 
     #[test]
-    fn test_asl_a() {
-        //38 a9 ec 0a
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0x38, 0xa9, 0xec, 0x0a, 0x00]);
-        assert_eq!(cpu.register_a, 0xd8);
-        assert_eq!(cpu.get_flag(Flag::Carry), true);
-        assert_eq!(cpu.get_flag(Flag::Negative), true);
-        assert_eq!(cpu.get_flag(Flag::Zero), false);
+    fn test_mem_dump() {
+        // a9 41 8d 00 02 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x41, 0x8d, 0x00, 0x02, 0x00]).unwrap();
+        assert_eq!(
+            cpu.mem_dump(0x0200, 2),
+            "0200: 41 00                                            A.\n"
+        );
     }
 
     #[test]
-    fn test_sbc() {
-        //a9 50 e9 b0 00
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa9, 0x50, 0xe9, 0xb0, 0x00]);
+    fn test_dyn_mem() {
+        // a9 05 aa 00
+        let memory: Box<dyn Mem> = Box::new(test_memory());
+        let mut cpu = CPU::new(memory);
+        cpu.load_and_run(vec![0xa9, 0x05, 0xaa, 0x00]).unwrap();
+        assert_eq!(cpu.register_x, 0x05);
+    }
 
-        assert_eq!(cpu.register_a, 0x9f);
-        assert_eq!(cpu.get_flag(Flag::Overflow), true);
-        assert_eq!(cpu.get_flag(Flag::Negative), true);
-        assert_eq!(cpu.get_flag(Flag::Carry), false);
-        assert_eq!(cpu.get_flag(Flag::Zero), false);
+    #[test]
+    fn test_mem_write_u16_round_trip() {
+        let mut cpu = CPU::new(test_memory());
+        for (addr, data) in [(0x0010, 0x1234), (0x0200, 0xabcd), (0x07fe, 0x00f0)] {
+            cpu.mem_write_u16(addr, data);
+            assert_eq!(cpu.mem_read_u16(addr), data);
+            assert_eq!(cpu.mem_read(addr), (data & 0xff) as u8);
+        }
     }
 
     #[test]
-    fn test_adc_positive_overflow() {
-        //a9 50 69 50
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa9, 0x50, 0x69, 0x50, 0x00]);
+    fn test_load_and_reset_entry_points() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x7ffc] = 0x00;
+        rom[0x7ffd] = 0x80;
+        let mut cpu = CPU::new(Bus::new_with_rom(rom));
 
-        assert_eq!(cpu.register_a, 0xa0);
-        assert_eq!(cpu.get_flag(Flag::Overflow), true);
-        assert_eq!(cpu.get_flag(Flag::Zero), false);
-        assert_eq!(cpu.get_flag(Flag::Carry), false);
-        assert_eq!(cpu.get_flag(Flag::Negative), true);
+        cpu.load(vec![0xe8, 0x00]);
+        assert_eq!(cpu.program_counter, 0x0600);
+
+        cpu.reset();
+        assert_eq!(cpu.program_counter, 0x8000);
+        assert_eq!(cpu.mem_read(0x0600), 0xe8);
     }
 
     #[test]
-    fn test_adc_negative_overflow() {
-        //a9 d0 69 90
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa9, 0xd0, 0x69, 0x90, 0x00]);
+    fn test_profile_instruction_counts() {
+        // a2 64 ca d0 fd 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.enable_profiling();
+        cpu.load_and_run(vec![0xa2, 0x64, 0xca, 0xd0, 0xfd, 0x00]).unwrap();
 
-        assert_eq!(cpu.register_a, 0x60);
-        assert_eq!(cpu.get_flag(Flag::Overflow), true);
-        assert_eq!(cpu.get_flag(Flag::Zero), false);
-        assert_eq!(cpu.get_flag(Flag::Carry), true);
-        assert_eq!(cpu.get_flag(Flag::Negative), false);
+        let counts = cpu.instruction_counts().unwrap();
+        assert_eq!(counts.get(&0xa2), Some(&1));
+        assert_eq!(counts.get(&0xca), Some(&100));
+        assert_eq!(counts.get(&0xd0), Some(&100));
+        assert_eq!(counts.get(&0x00), Some(&1));
     }
-    /**
-     *
-     *
-     * test cases for LDA
-     *
-     *
-     */
+
     #[test]
-    fn test_lda_zero_page() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.mem_write(0x0006, 0xFA);
-        cpu.load_and_run(vec![0xa5, 0x06, 0x00]);
-        assert_eq!(cpu.register_a, 0xFA);
+    fn test_profiling_disabled_by_default() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xe8, 0x00]).unwrap();
+        assert!(cpu.instruction_counts().is_none());
+        assert!(cpu.profile_report().is_empty());
     }
 
     #[test]
-    fn test_lda_zero_page_x() {
-        let mut cpu = CPU::new(Bus::new());
+    fn test_profile_report() {
+        // a2 0a a9 01 8d 00 02 ca d0 f8 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.enable_profiling();
+        cpu.load_and_run(vec![0xa2, 0x0a, 0xa9, 0x01, 0x8d, 0x00, 0x02, 0xca, 0xd0, 0xf8, 0x00]).unwrap();
+
+        assert_eq!(cpu.profile_report(), vec![
+            (0x8d, "STA", 10),
+            (0xa9, "LDA", 10),
+            (0xca, "DEX", 10),
+            (0xd0, "BNE", 10),
+            (0x00, "BRK", 1),
+            (0xa2, "LDX", 1),
+        ]);
+    }
 
-        cpu.mem_write(0x0006, 0xFA);
-        cpu.register_x = 0x05;
-        cpu.load_and_run(vec![0xb5, 0x01, 0x00]);
-        assert_eq!(cpu.register_a, 0xFA);
+    #[test]
+    fn test_jsr_pushes_address_of_last_instruction_byte() {
+        // 20 04 06 00 e8 60
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0x20, 0x04, 0x06, 0x00, 0xe8, 0x60]);
+
+        let mut pushed = (0, 0);
+        cpu.run_with_callback(|cpu| {
+            if cpu.program_counter == 0x0605 {
+                pushed = (cpu.mem_read(STACK + 0xfd), cpu.mem_read(STACK + 0xfc));
+            }
+        }).unwrap();
+
+        assert_eq!(pushed, (0x06, 0x02));
+        assert_eq!(cpu.register_x, 0x01);
     }
 
     #[test]
-    fn test_lda_zero_page_y() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.mem_write(0x0006, 0xFA);
-        cpu.register_x = 0x03;
-        cpu.load_and_run(vec![0xb5, 0x03, 0x00]);
-        assert_eq!(cpu.register_a, 0xFA);
+    fn test_jmp_indirect() {
+        // 6c 20 01
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0120, 0x0608);
+        cpu.load_and_run(vec![
+            0x6c, 0x20, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe8, 0x00,
+        ]).unwrap();
+
+        assert_eq!(cpu.register_x, 0x01);
     }
 
     #[test]
-    fn test_lda_zero_absolute() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.mem_write(0x1234, 0xFA);
-        cpu.load_and_run(vec![0xad, 0x34, 0x12, 0x00]);
-        assert_eq!(cpu.register_a, 0xFA);
+    fn test_program_counter_wraps_at_end_of_memory() {
+        // 4c ff ff, then a9 42 across $FFFF-$0000
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0xffff, 0xa9);
+        cpu.mem_write(0x0000, 0x42);
+        cpu.load_and_run(vec![0x4c, 0xff, 0xff]).unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+
+        // 4c fe ff, then ad 34 12 across $FFFE-$0000
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0xfffe, 0xad);
+        cpu.mem_write(0xffff, 0x34);
+        cpu.mem_write(0x0000, 0x12);
+        cpu.mem_write(0x1234, 0x55);
+        cpu.load_and_run(vec![0x4c, 0xfe, 0xff]).unwrap();
+        assert_eq!(cpu.register_a, 0x55);
     }
 
     #[test]
-    fn test_lda_zero_absolute_x() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.mem_write(0x1234, 0xFA);
-        cpu.register_x = 0x10;
-        cpu.load_and_run(vec![0xbd, 0x24, 0x12, 0x00]);
-        assert_eq!(cpu.register_a, 0xFA);
+    fn test_jmp_indirect_page_boundary_bug() {
+        // 6c ff 01
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x01ff, 0x08);
+        cpu.mem_write(0x0100, 0x06);
+        cpu.mem_write(0x0200, 0x07);
+        cpu.load_and_run(vec![
+            0x6c, 0xff, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe8, 0x00,
+        ]).unwrap();
+
+        assert_eq!(cpu.register_x, 0x01);
     }
 
     #[test]
-    fn test_lda_zero_absolute_y() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.mem_write(0x1234, 0xFA);
-        cpu.register_y = 0x20;
-        cpu.load_and_run(vec![0xb9, 0x14, 0x12, 0x00]);
-        assert_eq!(cpu.register_a, 0xFA);
+    fn test_cycle_count() {
+        // a9 c0 aa e8 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]).unwrap();
+        assert_eq!(cpu.elapsed_cycles(), 2 + 2 + 2 + 7);
     }
 
     #[test]
-    fn test_lda_zero_indirect_x() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_x = 0x01;
-        cpu.register_a = 0x05;
-        cpu.mem_write(0x0001, cpu.register_a);
-        cpu.register_a = 0x07;
-        cpu.mem_write(0x0002, cpu.register_a);
-        cpu.register_y = 0x0a;
-        cpu.mem_write(0x0705, cpu.register_y);
+    fn test_cycle_count_branch() {
+        // a2 00 d0 00 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0x00, 0xd0, 0x00, 0x00]).unwrap();
+        assert_eq!(cpu.elapsed_cycles(), 2 + 2 + 7);
 
-        cpu.load_and_run(vec![0xa1, 0x00, 0x00]);
+        // a2 01 d0 00 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0x01, 0xd0, 0x00, 0x00]).unwrap();
+        assert_eq!(cpu.elapsed_cycles(), 2 + 3 + 7);
+    }
 
-        assert_eq!(cpu.register_a, 0x0a);
+    #[test]
+    fn test_cycle_count_branch_backward_to_new_page() {
+        // a2 01 d0 80
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0x01, 0xd0, 0x80, 0x00]).unwrap();
+        assert_eq!(cpu.elapsed_cycles(), 2 + 4 + 7);
     }
 
     #[test]
-    fn test_lda_zero_indirect_y() {
-        let mut cpu = CPU::new(Bus::new());
+    fn test_cycle_count_branch_forward_to_new_page() {
+        // 4c f0 06 ... a2 01 d0 7f
+        let mut program = vec![0x00; 0xf4];
+        program[0x00..0x03].copy_from_slice(&[0x4c, 0xf0, 0x06]);
+        program[0xf0..0xf4].copy_from_slice(&[0xa2, 0x01, 0xd0, 0x7f]);
 
-        cpu.register_y = 0x02;
-        cpu.mem_write_u16(0x0002, 0x0705);
-        cpu.mem_write(0x0705, 0xfa);
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(program);
+        let mut branch_target = 0;
+        cpu.run_with_callback(|cpu| branch_target = cpu.program_counter).unwrap();
 
-        cpu.load_and_run(vec![0xb1, 0x00, 0x00]);
+        assert_eq!(branch_target, 0x0773);
+        assert_eq!(cpu.elapsed_cycles(), 3 + 2 + 4 + 7);
+    }
 
-        assert_eq!(cpu.register_a, 0xfa);
+    #[test]
+    fn test_run_for_cycles() {
+        // ea ea ea ea ea 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0xea, 0xea, 0xea, 0xea, 0xea, 0x00]);
+
+        // a fourth NOP would take 8 cycles
+        assert_eq!(cpu.run_for_cycles(7), Ok(6));
+        assert_eq!(cpu.program_counter, 0x0603);
+        assert_eq!(cpu.run_for_cycles(1), Ok(0));
+        assert_eq!(cpu.run_for_cycles(4), Ok(4));
+        assert_eq!(cpu.program_counter, 0x0605);
+        assert_eq!(cpu.elapsed_cycles(), 10);
     }
 
     #[test]
-    fn test_0xa9_lda_immediate_load_data() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa9, 0x05, 0x00]);
-        assert_eq!(cpu.register_a, 0x05);
-        assert!(cpu.status & 0b0000_0010 == 0b00);
-        assert!(cpu.status & 0b1000_0000 == 0);
+    fn test_run_for_cycles_reads_each_opcode_once() {
+        // ea ea ea ea
+        let mut cpu = counting_cpu(vec![0xea, 0xea, 0xea, 0xea]);
+        assert_eq!(cpu.run_for_cycles(7), Ok(6));
+        assert_eq!(cpu.bus.reads, 3);
     }
 
     #[test]
-    fn test_0xa9_lda_zero_flag() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa9, 0x00, 0x00]);
-        assert!(cpu.status & 0b0000_0010 == 0b10);
+    fn test_indexed_operands_are_read_once() {
+        // bd ff 06: the opcode, two operand bytes and the value
+        let mut cpu = counting_cpu(vec![0xbd, 0xff, 0x06]);
+        cpu.register_x = 0x01;
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.reads, 4);
+
+        // b1 10: the opcode, the operand, two pointer bytes and the value
+        let mut cpu = counting_cpu(vec![0xb1, 0x10]);
+        cpu.mem_write_u16(0x0010, 0x06ff);
+        cpu.register_y = 0x01;
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.reads, 5);
+        assert_eq!(cpu.elapsed_cycles(), 6);
     }
 
-    /*****************************************************/
+    #[test]
+    fn test_run_for_cycles_exact_budget() {
+        // a9 05 8d 00 02 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0xa9, 0x05, 0x8d, 0x00, 0x02, 0x00]);
 
-    /**
-     *
-     *CPY test cases
-     *
-     *
-     *
-     *
-     *
-     */
+        assert_eq!(cpu.run_for_cycles(2 + 4), Ok(6));
+        assert_eq!(cpu.mem_read(0x0200), 0x05);
+        assert_eq!(cpu.run_for_cycles(100), Err(CpuError::Break));
+    }
 
     #[test]
-    fn test_cpy_immediate() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa0, 0x05, 0xc0, 0x05, 0x00]);
-        assert!(cpu.status & Flag::Carry as u8 != 0);
-        assert!(cpu.status & Flag::Zero as u8 != 0);
-        assert!(cpu.status & Flag::Negative as u8 == 0);
+    fn test_cycle_count_page_crossed() {
+        // a2 01 bd 00 06 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0x01, 0xbd, 0x00, 0x06, 0x00]).unwrap();
+        assert_eq!(cpu.elapsed_cycles(), 2 + 4 + 7);
+
+        // a2 01 bd ff 06 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0x01, 0xbd, 0xff, 0x06, 0x00]).unwrap();
+        assert_eq!(cpu.elapsed_cycles(), 2 + 5 + 7);
+
+        // a2 01 9d ff 06 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0x01, 0x9d, 0xff, 0x06, 0x00]).unwrap();
+        assert_eq!(cpu.elapsed_cycles(), 2 + 5 + 7);
     }
 
     #[test]
-    fn test_cpy_zero() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa0, 0x05, 0xa2, 0x04, 0x86, 0x02, 0xc4, 0x02, 0x00]);
-        println!("{}", cpu.status);
-        assert!(cpu.status & Flag::Carry as u8 != 0);
-        assert!(cpu.status & Flag::Zero as u8 == 0);
-        assert!(cpu.status & Flag::Negative as u8 == 0);
+    fn test_stack_function() {
+        // a9 aa 08 48 28 68
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xaa, 0x08, 0x48, 0x28, 0x68, 0x00]).unwrap();
+
+        // PHP pushed the reset status with the break bits set
+        assert_eq!(cpu.register_a, 0xb0 | Flag::Interrupt as u8);
+        // PLA cleared the zero flag restored by PLP
+        assert_eq!(cpu.status, 0xa8 | Flag::Interrupt as u8);
+    }
+
+    #[test]
+    fn test_php_pushes_break_bits() {
+        // 08
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0x08, 0x00], |cpu| {
+            cpu.status = 0b1010_0001;
+        });
+
+        assert_eq!(cpu.mem_read(STACK + 0xfd), 0b1011_0001);
+        assert_eq!(cpu.status, 0b1010_0001 | Flag::Interrupt as u8);
+    }
+
+    #[test]
+    fn test_php_does_not_modify_break_bits() {
+        // 08
+        let mut cpu = CpuBuilder::new(test_memory()).with_status(0b0000_0011).build();
+        cpu.load(vec![0x08, 0x00]);
+
+        let mut before_brk = 0;
+        cpu.run_with_callback(|cpu| before_brk = cpu.status).unwrap();
+
+        assert_eq!(before_brk & 0b0011_0000, 0);
+        assert_eq!(before_brk, 0b0000_0011);
+    }
+
+    #[test]
+    fn test_php_plp_restores_status() {
+        // 08 28
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0x08, 0x28, 0x00], |cpu| {
+            cpu.status = 0b1010_0001;
+        });
+
+        assert_eq!(cpu.status, 0b1010_0001 | Flag::Interrupt as u8);
+    }
+
+    #[test]
+    fn test_plp_ignores_break_bit() {
+        // a9 10 48 28
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x10, 0x48, 0x28, 0x00]).unwrap();
+
+        assert_eq!(cpu.get_flag(Flag::Break), false);
+        assert_eq!(cpu.get_flag(Flag::Break2), true);
+    }
+
+    #[test]
+    fn test_pla_zero_flag() {
+        // a9 00 48 a9 01 68
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x00, 0x48, 0xa9, 0x01, 0x68, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+    }
+
+    #[test]
+    fn test_pla_negative_flag() {
+        // a9 ff 48 a9 01 68
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xff, 0x48, 0xa9, 0x01, 0x68, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0xff);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_rti() {
+        // a9 06 48 a9 10 48 a9 d1 48 40
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![
+            0xa9, 0x06, 0x48, 0xa9, 0x10, 0x48, 0xa9, 0xd1, 0x48, 0x40, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0xe8, 0x00,
+        ]).unwrap();
+
+        assert_eq!(cpu.register_x, 0x01);
+        assert_eq!(cpu.status, 0x61 | Flag::Interrupt as u8);
+    }
+
+    #[test]
+    fn test_brk() {
+        // ea 00
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xea, 0x00], |cpu| {
+            cpu.status = 0b1000_0001;
+        });
+
+        assert_eq!(cpu.mem_read(STACK + 0xfd), 0x06);
+        assert_eq!(cpu.mem_read(STACK + 0xfc), 0x03);
+        assert_eq!(cpu.mem_read(STACK + 0xfb), 0b1011_0001);
+        assert_eq!(cpu.stack_pointer, 0xfa);
+        assert_eq!(cpu.get_flag(Flag::Interrupt), true);
+        assert_eq!(cpu.mem_read_u16(0xFFFE), cpu.program_counter);
+    }
+
+    #[test]
+    fn test_txs_does_not_affect_flags() {
+        // a2 40 a9 00 38 9a
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0x40, 0xa9, 0x00, 0x38, 0x9a, 0x00]).unwrap();
+
+        // BRK pushed three bytes below the transferred stack pointer
+        assert_eq!(cpu.stack_pointer, 0x40 - 3);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+    }
+
+    #[test]
+    fn test_tya() {
+        // a0 42 98 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa0, 0x42, 0x98, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+
+        // a9 01 a0 00 98 00
+        cpu.load_and_run(vec![0xa9, 0x01, 0xa0, 0x00, 0x98, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+
+        // a0 80 98 00
+        cpu.load_and_run(vec![0xa0, 0x80, 0x98, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x80);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_nmi() {
+        // ea ea ea 00, handler: e8 40
+        let mut cpu = CPU::new(test_memory());
+        let handler = cpu.mem_read_u16(NMI_VECTOR);
+        cpu.mem_write(handler, 0xe8);
+        cpu.mem_write(handler + 1, 0x40);
+        cpu.load(vec![0xea, 0xea, 0xea, 0x00]);
+
+        let mut visited = vec![];
+        cpu.run_with_callback(|cpu| {
+            if cpu.program_counter == 0x0601 {
+                cpu.nmi_pending = true;
+            }
+            visited.push(cpu.program_counter);
+        }).unwrap();
+
+        assert_eq!(visited, vec![0x0600, 0x0601, handler, handler + 1, 0x0602, 0x0603]);
+        assert_eq!(cpu.register_x, 0x01);
+        assert_eq!(cpu.nmi_pending, false);
+    }
+
+    #[test]
+    fn test_trigger_nmi_pushes_state() {
+        let mut cpu = CpuBuilder::new(test_memory())
+            .with_pc(0x0634)
+            .with_status(0b1101_0001)
+            .build();
+
+        cpu.trigger_nmi();
+
+        assert_eq!(cpu.mem_read(STACK + 0xfd), 0x06);
+        assert_eq!(cpu.mem_read(STACK + 0xfc), 0x34);
+        assert_eq!(cpu.mem_read(STACK + 0xfb), 0b1110_0001);
+        assert_eq!(cpu.stack_pointer, 0xfa);
+        assert_eq!(cpu.get_flag(Flag::Interrupt), true);
+        assert_eq!(cpu.mem_read_u16(NMI_VECTOR), cpu.program_counter);
+        assert_eq!(cpu.elapsed_cycles(), 7);
+    }
+
+    #[test]
+    fn test_irq_serviced_when_interrupts_enabled() {
+        // 58 ea ea 00, handler: e8 40
+        let mut cpu = CPU::new(test_memory());
+        let handler = cpu.mem_read_u16(IRQ_BRK_VECTOR);
+        cpu.mem_write(handler, 0xe8);
+        cpu.mem_write(handler + 1, 0x40);
+        cpu.load(vec![0x58, 0xea, 0xea, 0x00]);
+
+        let mut visited = vec![];
+        cpu.run_with_callback(|cpu| {
+            if cpu.program_counter == 0x0601 {
+                cpu.irq_pending = true;
+            }
+            visited.push(cpu.program_counter);
+        }).unwrap();
+
+        assert_eq!(visited, vec![0x0600, 0x0601, handler, handler + 1, 0x0602, 0x0603]);
+        assert_eq!(cpu.register_x, 0x01);
+        assert_eq!(cpu.irq_pending, false);
+    }
+
+    #[test]
+    fn test_irq_ignored_when_interrupts_disabled() {
+        // 78 ea ea 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0x78, 0xea, 0xea, 0x00]);
+
+        let mut visited = vec![];
+        cpu.run_with_callback(|cpu| {
+            if cpu.program_counter == 0x0601 {
+                cpu.irq_pending = true;
+            }
+            visited.push(cpu.program_counter);
+        }).unwrap();
+
+        assert_eq!(visited, vec![0x0600, 0x0601, 0x0602, 0x0603]);
+        assert_eq!(cpu.irq_pending, true);
+    }
+
+    /*
+     * MMC3 with the IRQ latch at 10 and the background on. The counter is loaded on
+     * the first line and counts down once per line, the IRQ goes out on line 10.
+     *   e000: a9 0a 8d 00 c0 8d 01 c0 8d 01 e0 a9 08 8d 01 20 58 4c 11 e0
+     *   e100: 4c 00 e1
+     */
+    #[test]
+    fn test_mmc3_scanline_irq() {
+        let mut prg_rom = vec![0; 0x8000];
+        let program = [
+            0xa9, 0x0a, 0x8d, 0x00, 0xc0, 0x8d, 0x01, 0xc0, 0x8d, 0x01, 0xe0, 0xa9, 0x08, 0x8d, 0x01,
+            0x20, 0x58, 0x4c, 0x11, 0xe0,
+        ];
+        prg_rom[0x6000..0x6000 + program.len()].copy_from_slice(&program);
+        prg_rom[0x6100..0x6103].copy_from_slice(&[0x4c, 0x00, 0xe1]);
+        prg_rom[0x7ffc..].copy_from_slice(&[0x00, 0xe0, 0x00, 0xe1]);
+        let mapper = Mapper4::new(prg_rom, vec![0; 0x2000], Mirroring::Horizontal);
+        let mut cpu = CPU::new(Bus::new(Box::new(mapper)));
+        cpu.power_on();
+
+        while cpu.get_pc() != 0xe100 {
+            cpu.step().unwrap();
+            assert!(cpu.bus.ppu.scanline < 20, "no IRQ from the mapper");
+        }
+        assert_eq!(cpu.bus.ppu.scanline, 10);
+        assert!(cpu.bus.ppu.dot > 260);
+        assert_eq!(cpu.get_flag(Flag::Interrupt), true);
+    }
+
+    #[test]
+    fn test_trigger_irq_respects_interrupt_flag() {
+        let mut cpu = CpuBuilder::new(test_memory()).with_pc(0x0634).build();
+
+        cpu.trigger_irq();
+        assert_eq!(cpu.program_counter, 0x0634);
+        assert_eq!(cpu.stack_pointer, STACK_RESET);
+
+        cpu.status = 0;
+        cpu.trigger_irq();
+        assert_eq!(cpu.mem_read(STACK + 0xfd), 0x06);
+        assert_eq!(cpu.mem_read(STACK + 0xfc), 0x34);
+        assert_eq!(cpu.mem_read(STACK + 0xfb), 0b0010_0000);
+        assert_eq!(cpu.get_flag(Flag::Interrupt), true);
+        assert_eq!(cpu.mem_read_u16(IRQ_BRK_VECTOR), cpu.program_counter);
+    }
+
+    #[test]
+    fn test_bne() {
+        // a2 08 ca 8e 00 02 e0 03 d0 f8 8e 01 02 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![
+            0xa2, 0x08, 0xca, 0x8e, 0x00, 0x02, 0xe0, 0x03, 0xd0, 0xf8, 0x8e, 0x01, 0x02, 0x00,
+        ]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+        assert_eq!(cpu.register_x, 0x03);
+    }
+
+    #[test]
+    fn test_ror_a() {
+        // 38 a9 ec 6a
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0x38, 0xa9, 0xec, 0x6a, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0xf6);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+    }
+
+    #[test]
+    fn test_ror() {
+        // 38 a9 ed 85 02 66 02
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0x38, 0xa9, 0xed, 0x85, 0x02, 0x66, 0x02, 0x00]).unwrap();
+
+        assert_eq!(cpu.mem_read(0x0002), 0xf6);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+    }
+
+    #[test]
+    fn test_rol() {
+        // 38 a9 ec 85 02 26 02
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0x38, 0xa9, 0xec, 0x85, 0x02, 0x26, 0x02, 0x00]).unwrap();
+
+        assert_eq!(cpu.mem_read(0x0002), 0xd9);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_rol_a() {
+        //a9 76 2a
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0x38, 0xa9, 0xec, 0x2a, 0x00]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+        assert_eq!(cpu.register_a, 0xd9);
+    }
+
+    #[test]
+    fn test_asl() {
+        //a9 ec 85 02 06 02
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xec, 0x85, 0x02, 0x06, 0x02, 0x00]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.mem_read(0x0002), 0xd8);
+    }
+
+    #[test]
+    fn test_asl_a() {
+        //38 a9 ec 0a
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0x38, 0xa9, 0xec, 0x0a, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0xd8);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+    }
+
+    #[test]
+    fn test_sbc() {
+        //a9 50 e9 b0 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x50, 0xe9, 0xb0, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x9f);
+        assert_eq!(cpu.get_flag(Flag::Overflow), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+    }
+
+    #[test]
+    fn test_sbc_zero_page_x() {
+        // a2 02 38 a9 50 f5 10 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x12, 0x30);
+        cpu.load_and_run(vec![0xa2, 0x02, 0x38, 0xa9, 0x50, 0xf5, 0x10, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x20);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+    }
+
+    #[test]
+    fn test_adc_positive_overflow() {
+        //a9 50 69 50
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x50, 0x69, 0x50, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0xa0);
+        assert_eq!(cpu.get_flag(Flag::Overflow), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_adc_negative_overflow() {
+        //a9 d0 69 90
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xd0, 0x69, 0x90, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x60);
+        assert_eq!(cpu.get_flag(Flag::Overflow), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+    }
+
+    fn run_decimal(program: Vec<u8>) -> CPU<FlatMemory> {
+        let mut cpu = CPU::new(test_memory());
+        cpu.bcd_enabled = true;
+        cpu.load_and_run(program).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_adc_decimal() {
+        // f8 18 a9 09 69 01
+        let cpu = run_decimal(vec![0xf8, 0x18, 0xa9, 0x09, 0x69, 0x01, 0x00]);
+        assert_eq!(cpu.register_a, 0x10);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+
+        // f8 38 a9 58 69 46
+        let cpu = run_decimal(vec![0xf8, 0x38, 0xa9, 0x58, 0x69, 0x46, 0x00]);
+        assert_eq!(cpu.register_a, 0x05);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+
+        // f8 18 a9 99 69 01
+        let cpu = run_decimal(vec![0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x00]);
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+
+        // f8 18 a9 12 69 34
+        let cpu = run_decimal(vec![0xf8, 0x18, 0xa9, 0x12, 0x69, 0x34, 0x00]);
+        assert_eq!(cpu.register_a, 0x46);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+    }
+
+    #[test]
+    fn test_sbc_decimal() {
+        // f8 38 a9 46 e9 12
+        let cpu = run_decimal(vec![0xf8, 0x38, 0xa9, 0x46, 0xe9, 0x12, 0x00]);
+        assert_eq!(cpu.register_a, 0x34);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+
+        // f8 38 a9 40 e9 13
+        let cpu = run_decimal(vec![0xf8, 0x38, 0xa9, 0x40, 0xe9, 0x13, 0x00]);
+        assert_eq!(cpu.register_a, 0x27);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+
+        // f8 38 a9 00 e9 01
+        let cpu = run_decimal(vec![0xf8, 0x38, 0xa9, 0x00, 0xe9, 0x01, 0x00]);
+        assert_eq!(cpu.register_a, 0x99);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+
+        // f8 18 a9 32 e9 02
+        let cpu = run_decimal(vec![0xf8, 0x18, 0xa9, 0x32, 0xe9, 0x02, 0x00]);
+        assert_eq!(cpu.register_a, 0x29);
+    }
+
+    #[test]
+    fn test_adc_ignores_decimal_flag_by_default() {
+        // f8 18 a9 09 69 01
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x09, 0x69, 0x01, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x0a);
+    }
+
+    // A and the carry set up, then one ADC/SBC immediate
+    fn arithmetic(opcode: u8, a: u8, m: u8, carry: bool) -> CPU<FlatMemory> {
+        let mut cpu = CpuBuilder::new(test_memory())
+            .with_a(a)
+            .with_status(if carry { Flag::Carry as u8 } else { 0 })
+            .build();
+        cpu.load(vec![opcode, m]);
+        cpu.step().unwrap();
+        cpu
+    }
+
+    fn sign(value: u8) -> bool {
+        value & 0x80 != 0
+    }
+
+    proptest! {
+        #[test]
+        fn test_adc_flags(a: u8, m: u8, carry: bool) {
+            // 69 mm
+            let cpu = arithmetic(0x69, a, m, carry);
+            let sum = a as u16 + m as u16 + carry as u16;
+            let result = sum as u8;
+
+            prop_assert_eq!(cpu.register_a, result);
+            prop_assert_eq!(cpu.get_flag(Flag::Zero), result == 0);
+            prop_assert_eq!(cpu.get_flag(Flag::Negative), sign(result));
+            prop_assert_eq!(cpu.get_flag(Flag::Carry), sum > 0xff);
+            prop_assert_eq!(cpu.get_flag(Flag::Overflow), sign(a) == sign(m) && sign(result) != sign(a));
+        }
+
+        #[test]
+        fn test_sbc_flags(a: u8, m: u8, carry: bool) {
+            // e9 mm
+            let cpu = arithmetic(0xe9, a, m, carry);
+            let difference = a as i16 - m as i16 - !carry as i16;
+            let result = difference as u8;
+
+            prop_assert_eq!(cpu.register_a, result);
+            prop_assert_eq!(cpu.get_flag(Flag::Zero), result == 0);
+            prop_assert_eq!(cpu.get_flag(Flag::Negative), sign(result));
+            // carry is the inverted borrow
+            prop_assert_eq!(cpu.get_flag(Flag::Carry), difference >= 0);
+            // subtracting is adding the complement, so the operands overflow when their signs differ
+            prop_assert_eq!(cpu.get_flag(Flag::Overflow), sign(a) != sign(m) && sign(result) != sign(a));
+        }
+    }
+
+    /**
+     *
+     *
+     * test cases for LDA
+     *
+     *
+     */
+    #[test]
+    fn test_lda_zero_page() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0006, 0xFA);
+        cpu.load_and_run(vec![0xa5, 0x06, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0xFA);
+    }
+
+    #[test]
+    fn test_lda_zero_page_x() {
+        let mut cpu = CPU::new(test_memory());
+
+        cpu.mem_write(0x0006, 0xFA);
+        load_and_run_with(&mut cpu, vec![0xb5, 0x01, 0x00], |cpu| {
+            cpu.register_x = 0x05;
+        });
+        assert_eq!(cpu.register_a, 0xFA);
+    }
+
+    #[test]
+    fn test_lda_zero_page_x_wraps_around() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0005, 0xFA);
+        cpu.mem_write(0x0105, 0xAF);
+        load_and_run_with(&mut cpu, vec![0xb5, 0x06, 0x00], |cpu| {
+            cpu.register_x = 0xFF;
+        });
+        assert_eq!(cpu.register_a, 0xFA);
+    }
+
+    #[test]
+    fn test_ldx_zero_page_y_wraps_around() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0005, 0xFA);
+        cpu.mem_write(0x0105, 0xAF);
+        load_and_run_with(&mut cpu, vec![0xb6, 0x06, 0x00], |cpu| {
+            cpu.register_y = 0xFF;
+        });
+        assert_eq!(cpu.register_x, 0xFA);
+    }
+
+    #[test]
+    fn test_lda_zero_page_y() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0006, 0xFA);
+        load_and_run_with(&mut cpu, vec![0xb5, 0x03, 0x00], |cpu| {
+            cpu.register_x = 0x03;
+        });
+        assert_eq!(cpu.register_a, 0xFA);
+    }
+
+    #[test]
+    fn test_lda_zero_absolute() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x1234, 0xFA);
+        cpu.load_and_run(vec![0xad, 0x34, 0x12, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0xFA);
+    }
+
+    #[test]
+    fn test_lda_zero_absolute_x() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x1234, 0xFA);
+        load_and_run_with(&mut cpu, vec![0xbd, 0x24, 0x12, 0x00], |cpu| {
+            cpu.register_x = 0x10;
+        });
+        assert_eq!(cpu.register_a, 0xFA);
+    }
+
+    #[test]
+    fn test_lda_zero_absolute_y() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x1234, 0xFA);
+        load_and_run_with(&mut cpu, vec![0xb9, 0x14, 0x12, 0x00], |cpu| {
+            cpu.register_y = 0x20;
+        });
+        assert_eq!(cpu.register_a, 0xFA);
+    }
+
+    #[test]
+    fn test_ldy_zero_page_x() {
+        // a2 01 b4 10 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x11, 0x55);
+        cpu.load_and_run(vec![0xa2, 0x01, 0xb4, 0x10, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_y, 0x55);
+    }
+
+    #[test]
+    fn test_ldy_absolute_x() {
+        // a2 01 bc 00 02 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0201, 0x66);
+        cpu.load_and_run(vec![0xa2, 0x01, 0xbc, 0x00, 0x02, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_y, 0x66);
+    }
+
+    #[test]
+    fn test_sty_zero_page_x() {
+        // a0 77 a2 02 94 10 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa0, 0x77, 0xa2, 0x02, 0x94, 0x10, 0x00]).unwrap();
+
+        assert_eq!(cpu.mem_read(0x12), 0x77);
+        assert_eq!(cpu.mem_read(0x10 + 0x77), 0x00);
+    }
+
+    #[test]
+    fn test_inc_absolute_x() {
+        // a2 01 fe 00 02 e8 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0x01, 0xfe, 0x00, 0x02, 0xe8, 0x00]).unwrap();
+
+        assert_eq!(cpu.mem_read(0x0201), 0x01);
+        // the INX right after the 3-byte instruction runs
+        assert_eq!(cpu.register_x, 0x02);
+    }
+
+    #[test]
+    fn test_lda_zero_indirect_x() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0001, 0x05);
+        cpu.mem_write(0x0002, 0x07);
+        cpu.mem_write(0x0705, 0x0a);
+
+        load_and_run_with(&mut cpu, vec![0xa1, 0x00, 0x00], |cpu| {
+            cpu.register_x = 0x01;
+        });
+
+        assert_eq!(cpu.register_a, 0x0a);
+    }
+
+    #[test]
+    fn test_lda_indirect_x_pointer_at_ff() {
+        // a1 ff
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x00ff, 0x05);
+        cpu.mem_write(0x0000, 0x07);
+        cpu.mem_write(0x0100, 0x03);
+        cpu.mem_write(0x0705, 0x0a);
+        cpu.mem_write(0x0305, 0x0b);
+
+        cpu.load_and_run(vec![0xa1, 0xff, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x0a);
+    }
+
+    #[test]
+    fn test_lda_indirect_x_zero_page_wrap() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x00ff, 0x05);
+        cpu.mem_write(0x0000, 0x07);
+        cpu.mem_write(0x0100, 0x03);
+        cpu.mem_write(0x0705, 0x0a);
+        cpu.mem_write(0x0305, 0x0b);
+
+        load_and_run_with(&mut cpu, vec![0xa1, 0xfe, 0x00], |cpu| {
+            cpu.register_x = 0x01;
+        });
+
+        assert_eq!(cpu.register_a, 0x0a);
+    }
+
+    #[test]
+    fn test_lda_indirect_y_zero_page_wrap() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x00ff, 0x04);
+        cpu.mem_write(0x0000, 0x07);
+        cpu.mem_write(0x0100, 0x03);
+        cpu.mem_write(0x0705, 0x0a);
+        cpu.mem_write(0x0305, 0x0b);
+
+        load_and_run_with(&mut cpu, vec![0xb1, 0xff, 0x00], |cpu| {
+            cpu.register_y = 0x01;
+        });
+
+        assert_eq!(cpu.register_a, 0x0a);
+    }
+
+    #[test]
+    fn test_lda_zero_indirect_y() {
+        let mut cpu = CPU::new(test_memory());
+
+        cpu.mem_write_u16(0x0000, 0x0703);
+        cpu.mem_write(0x0705, 0xfa);
+
+        load_and_run_with(&mut cpu, vec![0xb1, 0x00, 0x00], |cpu| {
+            cpu.register_y = 0x02;
+        });
+
+        assert_eq!(cpu.register_a, 0xfa);
+    }
+
+    #[test]
+    fn test_lda_indirect_y_page_crossing() {
+        let mut cpu = CPU::new(test_memory());
+
+        cpu.mem_write_u16(0x0010, 0x02f0);
+        cpu.mem_write(0x0310, 0x42);
+        cpu.mem_write(0x0210, 0x24);
+
+        load_and_run_with(&mut cpu, vec![0xb1, 0x10, 0x00], |cpu| {
+            cpu.register_y = 0x20;
+        });
+
+        assert_eq!(cpu.register_a, 0x42);
+    }
+
+    #[test]
+    fn test_sta_indirect_y() {
+        // a0 04 a9 99 91 10
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0010, 0x0200);
+
+        cpu.load_and_run(vec![0xa0, 0x04, 0xa9, 0x99, 0x91, 0x10, 0x00]).unwrap();
+
+        assert_eq!(cpu.mem_read(0x0204), 0x99);
+        assert_eq!(cpu.mem_read(0x0014), 0x00);
+    }
+
+    #[test]
+    fn test_0xa9_lda_immediate_load_data() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x05, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x05);
+        assert!(cpu.status & 0b0000_0010 == 0b00);
+        assert!(cpu.status & 0b1000_0000 == 0);
+    }
+
+    #[test]
+    fn test_0xa9_lda_zero_flag() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x00, 0x00]).unwrap();
+        assert!(cpu.status & 0b0000_0010 == 0b10);
+    }
+
+    /*****************************************************/
+
+    /**
+     *
+     *CPY test cases
+     *
+     *
+     *
+     *
+     *
+     */
+
+    #[test]
+    fn test_cpy_immediate() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa0, 0x05, 0xc0, 0x05, 0x00]).unwrap();
+        assert!(cpu.status & Flag::Carry as u8 != 0);
+        assert!(cpu.status & Flag::Zero as u8 != 0);
+        assert!(cpu.status & Flag::Negative as u8 == 0);
+    }
+
+    #[test]
+    fn test_cpy_zero() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa0, 0x05, 0xa2, 0x04, 0x86, 0x02, 0xc4, 0x02, 0x00]).unwrap();
+        println!("{}", cpu.status);
+        assert!(cpu.status & Flag::Carry as u8 != 0);
+        assert!(cpu.status & Flag::Zero as u8 == 0);
+        assert!(cpu.status & Flag::Negative as u8 == 0);
     }
 
     #[test]
     fn test_cpy_absolute() {
-        let mut cpu = CPU::new(Bus::new());
+        let mut cpu = CPU::new(test_memory());
         cpu.load_and_run(vec![
             0xa0, 0x05, 0xa2, 0x06, 0x8e, 0x34, 0x12, 0xcc, 0x34, 0x12, 0x00,
-        ]);
+        ]).unwrap();
         assert!(cpu.status & Flag::Carry as u8 == 0);
         assert!(cpu.status & Flag::Zero as u8 == 0);
         assert!(cpu.status & Flag::Negative as u8 != 0);
     }
 
     #[test]
-    fn test_cpy_compare_y_register_set_carry() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_y = 0x30;
-        cpu.load_and_run(vec![0xc0, 0x29, 0x00]);
-        assert!(cpu.status & 0b1000_0011 == 0b0000_0001);
+    fn test_cpy_compare_y_register_set_carry() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xc0, 0x29, 0x00], |cpu| {
+            cpu.register_y = 0x30;
+        });
+        assert!(cpu.status & 0b1000_0011 == 0b0000_0001);
+    }
+
+    #[test]
+    fn test_0xc0_cpy_compare_y_register_set_zero() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xc0, 0x29, 0x00], |cpu| {
+            cpu.register_y = 0x29;
+        });
+        assert_eq!(cpu.status & Flag::Zero as u8, Flag::Zero as u8);
+    }
+
+    #[test]
+    fn test_0xc0_cpy_compare_y_register_set_negative() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xc0, 0x29, 0x00], |cpu| {
+            cpu.register_y = 0x20;
+        });
+        assert!(cpu.status & 0b1000_0011 == 0b1000_0000);
+    }
+
+    #[test]
+    fn test_cmp_unsigned_boundary() {
+        // a9 80 c9 01
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x80, 0xc9, 0x01, 0x00]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+
+        // a9 7f c9 80
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x7f, 0xc9, 0x80, 0x00]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+
+        // a9 01 c9 ff
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x01, 0xc9, 0xff, 0x00]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+    }
+
+    #[test]
+    fn test_cpx_unsigned_boundary() {
+        // a2 80 e0 01
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0x80, 0xe0, 0x01, 0x00]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+
+        // a2 ff e0 7f
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa2, 0xff, 0xe0, 0x7f, 0x00]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_cpy_unsigned_boundary() {
+        // a0 80 c0 01
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa0, 0x80, 0xc0, 0x01, 0x00]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+
+        // a0 00 c0 80
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa0, 0x00, 0xc0, 0x80, 0x00]).unwrap();
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_0xa8_tay_transfer_accumulator_to_y() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xa8, 0x00], |cpu| {
+            cpu.register_a = 0x23;
+        });
+        assert_eq!(cpu.register_y, cpu.register_a);
+        assert!(cpu.status & 0b0000_0010 == 0);
+        assert!(cpu.status & 0b1000_0000 == 0);
+    }
+
+    #[test]
+    fn test_0xa8_tay_transfer_accumulator_to_y_zero_flag() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xa8, 0x00], |cpu| {
+            cpu.register_a = 0;
+        });
+        assert_eq!(cpu.register_y, cpu.register_a);
+        assert!(cpu.status & 0b0000_0010 == 0b0000_0010);
+        assert!(cpu.status & 0b1000_0000 == 0);
+    }
+
+    #[test]
+    fn test_0xa8_tay_transfer_accumulator_to_y_negative_flag() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xa8, 0x00], |cpu| {
+            cpu.register_a = 0xF0;
+        });
+        assert_eq!(cpu.register_y, cpu.register_a);
+        assert!(cpu.status & 0b0000_0010 == 0);
+        assert!(cpu.status & 0b1000_0000 != 0);
+    }
+
+    #[test]
+    fn test_0x98_tya_transfer_y_to_accumulator() {
+        // a0 f0 98
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa0, 0xf0, 0x98, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0xf0);
+        assert_eq!(cpu.register_y, 0xf0);
+        assert!(cpu.status & 0b0000_0010 == 0);
+        assert!(cpu.status & 0b1000_0000 != 0);
+    }
+
+    #[test]
+    fn test_0x98_tya_transfer_y_to_accumulator_zero_flag() {
+        // a0 00 98
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xa0, 0x00, 0x98, 0x00], |cpu| {
+            cpu.register_a = 0x23;
+        });
+        assert_eq!(cpu.register_a, 0x00);
+        assert!(cpu.status & 0b0000_0010 != 0);
+        assert!(cpu.status & 0b1000_0000 == 0);
+    }
+
+    #[test]
+    fn test_0xaa_tax_move_a_to_x() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xaa, 0x00], |cpu| {
+            cpu.register_a = 10;
+        });
+
+        assert_eq!(cpu.register_x, 10)
+    }
+
+    #[test]
+    fn test_0xaa_tax_move_a_to_x_zero_flag_on() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xaa, 0x00], |cpu| {
+            cpu.register_a = 0x00;
+        });
+        assert!(cpu.status & 0b0000_0010 != 0);
+        assert!(cpu.status & 0b1000_0000 == 0);
+    }
+
+    #[test]
+    fn test_0xaa_tax_move_a_to_x_zero_negative_flag_on() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xaa, 0x00], |cpu| {
+            cpu.register_a = 0xf1;
+        });
+        assert!(cpu.status & 0b0000_0010 == 0);
+        assert!(cpu.status & 0b1000_0000 != 0);
+    }
+
+    #[test]
+    fn test_5_ops_working_together() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_x, 0xc1)
+    }
+
+    #[test]
+    fn test_inx_overflow() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xe8, 0x00], |cpu| {
+            cpu.register_x = 0xff;
+        });
+
+        assert_eq!(cpu.register_x, 0);
+        assert!(cpu.status & Flag::Zero as u8 != 0);
+    }
+
+    #[test]
+    fn test_inx_positive() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0xe8, 0xe8, 0x00], |cpu| {
+            cpu.register_x = 0x11;
+        });
+
+        assert_eq!(cpu.register_x, 0x13);
+        assert!(cpu.status & Flag::Zero as u8 == 0);
+        assert!(cpu.status & Flag::Negative as u8 == 0);
+    }
+
+    /*****************************************************/
+
+    /**
+     *
+     * Unofficial opcodes test cases
+     *
+     */
+
+    #[test]
+    fn test_lax_zero_page() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0010, 0x85);
+        cpu.load_and_run(vec![0xa7, 0x10, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x85);
+        assert_eq!(cpu.register_x, 0x85);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+    }
+
+    #[test]
+    fn test_lax_zero_page_y() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0012, 0x00);
+        load_and_run_with(&mut cpu, vec![0xb7, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x11;
+            cpu.register_y = 0x02;
+        });
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.register_x, 0x00);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn test_lax_absolute() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0234, 0x42);
+        cpu.load_and_run(vec![0xaf, 0x34, 0x02, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.register_x, 0x42);
+    }
+
+    #[test]
+    fn test_lax_absolute_y() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0301, 0x42);
+        load_and_run_with(&mut cpu, vec![0xbf, 0xff, 0x02, 0x00], |cpu| {
+            cpu.register_y = 0x02;
+        });
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.register_x, 0x42);
+        assert_eq!(cpu.elapsed_cycles(), 5 + 7);
+    }
+
+    #[test]
+    fn test_lax_indirect_x() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0012, 0x0234);
+        cpu.mem_write(0x0234, 0x42);
+        load_and_run_with(&mut cpu, vec![0xa3, 0x10, 0x00], |cpu| {
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.register_x, 0x42);
+    }
+
+    #[test]
+    fn test_lax_indirect_y() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0010, 0x0230);
+        cpu.mem_write(0x0234, 0x42);
+        load_and_run_with(&mut cpu, vec![0xb3, 0x10, 0x00], |cpu| {
+            cpu.register_y = 0x04;
+        });
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.register_x, 0x42);
+    }
+
+    #[test]
+    fn test_sax_zero_page() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0x87, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0b1100_1010;
+            cpu.register_x = 0b1010_0110;
+            cpu.status = 0b0000_0010;
+        });
+        assert_eq!(cpu.mem_read(0x0010), 0b1000_0010);
+        assert_eq!(cpu.status & !(Flag::Interrupt as u8), 0b0000_0010);
+    }
+
+    #[test]
+    fn test_sax_zero_page_y() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0x97, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0xf0;
+            cpu.register_x = 0x0f;
+            cpu.register_y = 0x03;
+            cpu.status = 0b1000_0001;
+        });
+        assert_eq!(cpu.mem_read(0x0013), 0x00);
+        assert_eq!(cpu.status & !(Flag::Interrupt as u8), 0b1000_0001);
+    }
+
+    #[test]
+    fn test_sax_absolute() {
+        let mut cpu = CPU::new(test_memory());
+        load_and_run_with(&mut cpu, vec![0x8f, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0xff;
+            cpu.register_x = 0x81;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x81);
+        assert_eq!(cpu.status, POWER_ON_STATUS);
+    }
+
+    #[test]
+    fn test_sax_indirect_x() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0012, 0x0234);
+        load_and_run_with(&mut cpu, vec![0x83, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x3c;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x00);
+        assert_eq!(cpu.status, POWER_ON_STATUS);
+    }
+
+    #[test]
+    fn test_dcp_zero_page_equal() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0010, 0x43);
+        load_and_run_with(&mut cpu, vec![0xc7, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x42;
+        });
+        assert_eq!(cpu.mem_read(0x0010), 0x42);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+    }
+
+    #[test]
+    fn test_dcp_zero_page_x_wraps_memory() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0012, 0x00);
+        load_and_run_with(&mut cpu, vec![0xd7, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x80;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0012), 0xff);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_dcp_absolute() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0234, 0x02);
+        load_and_run_with(&mut cpu, vec![0xcf, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0x80;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x01);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
     }
 
     #[test]
-    fn test_0xc0_cpy_compare_y_register_set_zero() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_y = 0x29;
-        cpu.load_and_run(vec![0xc0, 0x29, 0x00]);
-        assert_eq!(cpu.status & Flag::Zero as u8, Flag::Zero as u8);
+    fn test_dcp_absolute_x_and_y() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0236, 0x10);
+        load_and_run_with(&mut cpu, vec![0xdf, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0x20;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0236), 0x0f);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0236, 0x10);
+        load_and_run_with(&mut cpu, vec![0xdb, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0x20;
+            cpu.register_y = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0236), 0x0f);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
     }
 
     #[test]
-    fn test_0xc0_cpy_compare_y_register_set_negative() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_y = 0x20;
-        cpu.load_and_run(vec![0xc0, 0x29, 0x00]);
-        assert!(cpu.status & 0b1000_0011 == 0b1000_0000);
+    fn test_dcp_indirect() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0012, 0x0234);
+        cpu.mem_write(0x0234, 0x05);
+        load_and_run_with(&mut cpu, vec![0xc3, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x04;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x04);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0010, 0x0230);
+        cpu.mem_write(0x0234, 0x05);
+        load_and_run_with(&mut cpu, vec![0xd3, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x04;
+            cpu.register_y = 0x04;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x04);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
     }
 
     #[test]
-    fn test_0xa8_tay_transfer_accumulator_to_y() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_a = 0x23;
-        cpu.load_and_run(vec![0xa8, 0x00]);
-        assert_eq!(cpu.register_y, cpu.register_a);
-        assert!(cpu.status & 0b0000_0010 == 0);
-        assert!(cpu.status & 0b1000_0000 == 0);
+    fn test_isb_zero_page() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0010, 0x0f);
+        load_and_run_with(&mut cpu, vec![0xe7, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x20;
+            cpu.set_flag(Flag::Carry, true);
+        });
+        assert_eq!(cpu.mem_read(0x0010), 0x10);
+        assert_eq!(cpu.register_a, 0x10);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Overflow), false);
     }
 
     #[test]
-    fn test_0xa8_tay_transfer_accumulator_to_y_zero_flag() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_a = 0;
-        cpu.load_and_run(vec![0xa8, 0x00]);
-        assert_eq!(cpu.register_y, cpu.register_a);
-        assert!(cpu.status & 0b0000_0010 == 0b0000_0010);
-        assert!(cpu.status & 0b1000_0000 == 0);
+    fn test_isb_borrow() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0012, 0x0f);
+        load_and_run_with(&mut cpu, vec![0xf7, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x20;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0012), 0x10);
+        assert_eq!(cpu.register_a, 0x0f);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
     }
 
     #[test]
-    fn test_0xa8_tay_transfer_accumulator_to_y_negative_flag() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_a = 0xF0;
-        cpu.load_and_run(vec![0xa8, 0x00]);
-        assert_eq!(cpu.register_y, cpu.register_a);
-        assert!(cpu.status & 0b0000_0010 == 0);
-        assert!(cpu.status & 0b1000_0000 != 0);
+    fn test_isb_overflow() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0234, 0x00);
+        load_and_run_with(&mut cpu, vec![0xef, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0x80;
+            cpu.set_flag(Flag::Carry, true);
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x01);
+        assert_eq!(cpu.register_a, 0x7f);
+        assert_eq!(cpu.get_flag(Flag::Overflow), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
     }
 
     #[test]
-    fn test_0xaa_tax_move_a_to_x() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_a = 10;
-        cpu.load_and_run(vec![0xaa, 0x00]);
+    fn test_isb_memory_wraps() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0236, 0xff);
+        load_and_run_with(&mut cpu, vec![0xfb, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0x05;
+            cpu.register_y = 0x02;
+            cpu.set_flag(Flag::Carry, true);
+        });
+        assert_eq!(cpu.mem_read(0x0236), 0x00);
+        assert_eq!(cpu.register_a, 0x05);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+    }
 
-        assert_eq!(cpu.register_x, 10)
+    #[test]
+    fn test_isb_indirect_y() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0010, 0x0230);
+        cpu.mem_write(0x0234, 0x01);
+        load_and_run_with(&mut cpu, vec![0xf3, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x01;
+            cpu.register_y = 0x04;
+            cpu.set_flag(Flag::Carry, true);
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x02);
+        assert_eq!(cpu.register_a, 0xff);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
     }
 
     #[test]
-    fn test_0xaa_tax_move_a_to_x_zero_flag_on() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_a = 0x00;
-        cpu.load_and_run(vec![0xaa, 0x00]);
-        assert!(cpu.status & 0b0000_0010 != 0);
-        assert!(cpu.status & 0b1000_0000 == 0);
+    fn test_slo_zero_page() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0010, 0x81);
+        load_and_run_with(&mut cpu, vec![0x07, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x01;
+        });
+        assert_eq!(cpu.mem_read(0x0010), 0x02);
+        assert_eq!(cpu.register_a, 0x03);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), false);
     }
 
     #[test]
-    fn test_0xaa_tax_move_a_to_x_zero_negative_flag_on() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_a = 0xf1;
-        cpu.load_and_run(vec![0xaa, 0x00]);
-        assert!(cpu.status & 0b0000_0010 == 0);
-        assert!(cpu.status & 0b1000_0000 != 0);
+    fn test_slo_zero_page_x() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0012, 0x40);
+        load_and_run_with(&mut cpu, vec![0x17, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x01;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0012), 0x80);
+        assert_eq!(cpu.register_a, 0x81);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
     }
 
     #[test]
-    fn test_5_ops_working_together() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
+    fn test_slo_absolute_zero_result() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0234, 0x80);
+        load_and_run_with(&mut cpu, vec![0x0f, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0x00;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x00);
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+    }
 
-        assert_eq!(cpu.register_x, 0xc1)
+    #[test]
+    fn test_slo_indirect_x() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0012, 0x0234);
+        cpu.mem_write(0x0234, 0x05);
+        load_and_run_with(&mut cpu, vec![0x03, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x10;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x0a);
+        assert_eq!(cpu.register_a, 0x1a);
     }
 
     #[test]
-    fn test_inx_overflow() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_x = 0xff;
-        cpu.load_and_run(vec![0xe8, 0x00]);
+    fn test_rla_carry_in_and_out() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0010, 0x80);
+        load_and_run_with(&mut cpu, vec![0x27, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0xff;
+            cpu.set_flag(Flag::Carry, true);
+        });
+        assert_eq!(cpu.mem_read(0x0010), 0x01);
+        assert_eq!(cpu.register_a, 0x01);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+    }
+
+    #[test]
+    fn test_rla_zero_page_x() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0012, 0x40);
+        load_and_run_with(&mut cpu, vec![0x37, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0xf0;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0012), 0x80);
+        assert_eq!(cpu.register_a, 0x80);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_rla_absolute_y_zero_result() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0236, 0x0f);
+        load_and_run_with(&mut cpu, vec![0x3b, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0x01;
+            cpu.register_y = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0236), 0x1e);
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn test_rla_indirect_y() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0010, 0x0230);
+        cpu.mem_write(0x0234, 0x21);
+        load_and_run_with(&mut cpu, vec![0x33, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x42;
+            cpu.register_y = 0x04;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x42);
+        assert_eq!(cpu.register_a, 0x42);
+    }
+
+    #[test]
+    fn test_sre_zero_page() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0010, 0x03);
+        load_and_run_with(&mut cpu, vec![0x47, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x0f;
+        });
+        assert_eq!(cpu.mem_read(0x0010), 0x01);
+        assert_eq!(cpu.register_a, 0x0e);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+    }
+
+    #[test]
+    fn test_sre_zero_page_x_no_carry() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0012, 0x02);
+        load_and_run_with(&mut cpu, vec![0x57, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x81;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0012), 0x01);
+        assert_eq!(cpu.register_a, 0x80);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_sre_absolute_x_zero_result() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0236, 0x85);
+        load_and_run_with(&mut cpu, vec![0x5f, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0x42;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0236), 0x42);
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn test_sre_indirect_x() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0012, 0x0234);
+        cpu.mem_write(0x0234, 0x10);
+        load_and_run_with(&mut cpu, vec![0x43, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x01;
+            cpu.register_x = 0x02;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x08);
+        assert_eq!(cpu.register_a, 0x09);
+    }
+
+    #[test]
+    fn test_rra_carry_out_feeds_adc() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0010, 0x03);
+        load_and_run_with(&mut cpu, vec![0x67, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x10;
+        });
+        assert_eq!(cpu.mem_read(0x0010), 0x01);
+        assert_eq!(cpu.register_a, 0x12);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+    }
+
+    #[test]
+    fn test_rra_carry_in() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0012, 0x02);
+        load_and_run_with(&mut cpu, vec![0x77, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x01;
+            cpu.register_x = 0x02;
+            cpu.set_flag(Flag::Carry, true);
+        });
+        assert_eq!(cpu.mem_read(0x0012), 0x81);
+        assert_eq!(cpu.register_a, 0x82);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_rra_overflow() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write(0x0234, 0xa0);
+        load_and_run_with(&mut cpu, vec![0x6f, 0x34, 0x02, 0x00], |cpu| {
+            cpu.register_a = 0x50;
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0x50);
+        assert_eq!(cpu.register_a, 0xa0);
+        assert_eq!(cpu.get_flag(Flag::Overflow), true);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+    }
+
+    #[test]
+    fn test_rra_carry_from_addition() {
+        let mut cpu = CPU::new(test_memory());
+        cpu.mem_write_u16(0x0010, 0x0230);
+        cpu.mem_write(0x0234, 0x80);
+        load_and_run_with(&mut cpu, vec![0x73, 0x10, 0x00], |cpu| {
+            cpu.register_a = 0x80;
+            cpu.register_y = 0x04;
+            cpu.set_flag(Flag::Carry, true);
+        });
+        assert_eq!(cpu.mem_read(0x0234), 0xc0);
+        assert_eq!(cpu.register_a, 0x40);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Overflow), true);
+    }
+
+    #[test]
+    fn test_alr_carry_from_and_result() {
+        // a9 ff 4b 03
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xff, 0x4b, 0x03, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x01);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+    }
+
+    #[test]
+    fn test_alr_and_clears_bit_zero() {
+        // a9 ff 4b fe
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xff, 0x4b, 0xfe, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x7f);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+    }
+
+    #[test]
+    fn test_alr_zero_result() {
+        // a9 01 4b 01
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0x01, 0x4b, 0x01, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn test_anc_sets_carry_from_bit_7() {
+        // a9 f0 0b 80
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xf0, 0x0b, 0x80, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x80);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn test_anc_clears_carry() {
+        // 38 a9 f0 2b 7f
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0x38, 0xa9, 0xf0, 0x2b, 0x7f, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x70);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Negative), false);
+    }
+
+    #[test]
+    fn test_arr_bits_6_and_5_set() {
+        // a9 ff 6b c0
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xff, 0x6b, 0xc0, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x60);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Overflow), false);
+    }
+
+    #[test]
+    fn test_arr_bit_6_set() {
+        // a9 ff 6b 80
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xff, 0x6b, 0x80, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x40);
+        assert_eq!(cpu.get_flag(Flag::Carry), true);
+        assert_eq!(cpu.get_flag(Flag::Overflow), true);
+    }
+
+    #[test]
+    fn test_arr_bit_5_set() {
+        // a9 ff 6b 40
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xff, 0x6b, 0x40, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x20);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Overflow), true);
+    }
+
+    #[test]
+    fn test_arr_bits_6_and_5_clear() {
+        // a9 ff 6b 01
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0xa9, 0xff, 0x6b, 0x01, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+        assert_eq!(cpu.get_flag(Flag::Overflow), false);
+        assert_eq!(cpu.get_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn test_arr_rotates_carry_in() {
+        // 38 a9 ff 6b 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load_and_run(vec![0x38, 0xa9, 0xff, 0x6b, 0x00, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x80);
+        assert_eq!(cpu.get_flag(Flag::Negative), true);
+        assert_eq!(cpu.get_flag(Flag::Carry), false);
+    }
+
+    #[test]
+    fn test_kil_halts_cpu() {
+        // e8 02 e8
+        let mut cpu = CPU::new(test_memory());
+        let result = cpu.load_and_run(vec![0xe8, 0x02, 0xe8, 0x00]);
+        assert_eq!(result, Err(CpuError::Halt(0x02)));
+        assert_eq!(cpu.register_x, 1);
+    }
+
+    #[test]
+    fn test_kil_all_variants_halt() {
+        for code in [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xb2, 0xd2, 0xf2] {
+            let mut cpu = CPU::new(test_memory());
+            assert_eq!(cpu.load_and_run(vec![code, 0x00]), Err(CpuError::Halt(code)));
+        }
+    }
+
+    #[test]
+    fn test_unknown_opcode_returns_error() {
+        // e8 9e
+        let mut cpu = CPU::new(test_memory());
+        let result = cpu.load_and_run(vec![0xe8, 0x9e, 0x00]);
+        assert_eq!(result, Err(CpuError::UnknownOpcode(0x9e)));
+        assert_eq!(cpu.register_x, 1);
+    }
+
+    #[test]
+    fn test_power_on_state() {
+        let mut cpu = CPU::new(test_bus());
+        cpu.power_on();
+        let power_on = CpuSnapshot { a: 0, x: 0, y: 0, pc: 0x0600, sp: 0xfd, status: 0x34, cycle: 0 };
+        assert_eq!(cpu.snapshot(), power_on);
+    }
+
+    #[test]
+    fn test_reset_keeps_registers() {
+        // a9 42 a2 10 a0 20 38 f8 58
+        let mut cpu = CPU::new(test_bus());
+        cpu.load(vec![0xa9, 0x42, 0xa2, 0x10, 0xa0, 0x20, 0x38, 0xf8, 0x58]);
+        cpu.power_on();
+        for _ in 0..6 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_flag(Flag::Interrupt), false);
+
+        // only I is set and the stack pointer moves down by three
+        cpu.reset();
+        let reset = CpuSnapshot { a: 0x42, x: 0x10, y: 0x20, pc: 0x0600, sp: 0xfa, status: 0x3d, cycle: 12 };
+        assert_eq!(cpu.snapshot(), reset);
+
+        cpu.reset();
+        assert_eq!(cpu.get_sp(), 0xf7);
+    }
+
+    #[test]
+    fn test_reset_stack_pointer_wraps() {
+        let mut cpu = CpuBuilder::new(test_bus()).with_sp(0x01).build();
+        cpu.reset();
+        assert_eq!(cpu.get_sp(), 0xfe);
+    }
+
+    #[test]
+    fn test_oam_dma_stalls_cpu() {
+        // a9 06 8d 14 40
+        let mut cpu = CPU::new(test_bus());
+        cpu.load_and_run(vec![0xa9, 0x06, 0x8d, 0x14, 0x40, 0x00]).unwrap();
+        // LDA 2 + STA 4 + DMA 513 (the transfer starts on an even cycle) + BRK 7
+        assert_eq!(cpu.elapsed_cycles(), 2 + 4 + 513 + 7);
+        assert_eq!(cpu.bus.ppu.oam_data[0x00], 0xa9);
+        assert_eq!(cpu.bus.ppu.oam_data[0x02], 0x8d);
+    }
+
+    #[test]
+    fn test_nmi_from_vblank() {
+        // a9 80 8d 00 20 4c 05 06, handler: 02
+        let mut cpu = CPU::new(test_bus());
+        let handler = cpu.mem_read_u16(NMI_VECTOR);
+        cpu.mem_write(handler, 0x02);
+
+        let result = cpu.load_and_run(vec![0xa9, 0x80, 0x8d, 0x00, 0x20, 0x4c, 0x05, 0x06]);
+
+        assert_eq!(result, Err(CpuError::Halt(0x02)));
+        assert_eq!(cpu.bus.ppu.scanline, 241);
+        // the NMI is taken after the instruction during which it was raised
+        let vblank_cycle = (241 * 341 + 2) / 3;
+        assert!(cpu.elapsed_cycles() >= vblank_cycle + 7 + 2);
+        assert!(cpu.elapsed_cycles() <= vblank_cycle + 3 + 7 + 2);
+    }
+
+    #[test]
+    fn test_frame_counter_irq() {
+        // 58 4c 01 06, handler: 02
+        let mut cpu = CPU::new(test_bus());
+        let handler = cpu.mem_read_u16(IRQ_BRK_VECTOR);
+        cpu.mem_write(handler, 0x02);
+
+        let result = cpu.load_and_run(vec![0x58, 0x4c, 0x01, 0x06]);
+
+        assert_eq!(result, Err(CpuError::Halt(0x02)));
+        assert!(cpu.elapsed_cycles() > 29829);
+        assert!(cpu.elapsed_cycles() < 29829 + 3 + 7 + 2 + 1);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn test_history_up_to_unknown_opcode() {
+        // a9 01 a2 02 a0 03 e8 c8 9e
+        let mut cpu = CPU::new(test_memory());
+        let result = cpu.load_and_run(vec![0xa9, 0x01, 0xa2, 0x02, 0xa0, 0x03, 0xe8, 0xc8, 0x9e]);
+        assert_eq!(result, Err(CpuError::UnknownOpcode(0x9e)));
+
+        let history: Vec<(u16, String)> = cpu
+            .last_n_instructions(5)
+            .iter()
+            .map(|entry| (entry.pc, entry.mnemonic.clone()))
+            .collect();
+        assert_eq!(history, vec![
+            (0x0602, String::from("LDX #$02")),
+            (0x0604, String::from("LDY #$03")),
+            (0x0606, String::from("INX")),
+            (0x0607, String::from("INY")),
+            (0x0608, String::from("??? $9E")),
+        ]);
+        assert_eq!(cpu.last_n_instructions(5)[4].register_x, 0x03);
+        assert_eq!(cpu.last_n_instructions(100).len(), 6);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn test_history_capacity() {
+        // e8 d0 fd
+        let mut cpu = CPU::new(test_memory());
+        cpu.history = ExecutionHistory::new(3);
+        cpu.load_and_run(vec![0xe8, 0xd0, 0xfd, 0x00]).unwrap();
+
+        let pcs: Vec<u16> = cpu.last_n_instructions(10).iter().map(|entry| entry.pc).collect();
+        assert_eq!(pcs, vec![0x0600, 0x0601, 0x0603]);
+    }
+
+    #[test]
+    fn test_stop_handle_from_another_thread() {
+        // 4c 00 06
+        let mut cpu = CPU::new(test_memory());
+        let stop = cpu.stop_handle();
+        cpu.load(vec![0x4c, 0x00, 0x06]);
+
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            stop.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(cpu.run(), Err(CpuError::Stopped));
+        stopper.join().unwrap();
+        assert_eq!(cpu.program_counter, 0x0600);
+    }
 
+    #[test]
+    fn test_cpu_is_send() {
+        // the run loop can be moved to an emulation thread
+        fn assert_send<T: Send>() {}
+        assert_send::<CPU<Bus>>();
+        assert_send::<CPU<FlatMemory>>();
+    }
+
+    #[test]
+    fn test_stop_handle_resume() {
+        // e8 e8 e8 00
+        let mut cpu = CPU::new(test_memory());
+        let stop = cpu.stop_handle();
+        cpu.load(vec![0xe8, 0xe8, 0xe8, 0x00]);
+
+        let result = cpu.run_with_callback(|cpu| {
+            if cpu.program_counter == 0x0601 {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+        assert_eq!(result, Err(CpuError::Stopped));
+        assert_eq!(cpu.register_x, 2);
+
+        cpu.run().unwrap();
+        assert_eq!(cpu.register_x, 3);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        // a9 05 aa e8 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.add_breakpoint(0x0603);
+
+        let result = cpu.load_and_run(vec![0xa9, 0x05, 0xaa, 0xe8, 0x00]);
+
+        assert_eq!(result, Err(CpuError::Breakpoint(0x0603)));
+        assert_eq!(cpu.program_counter, 0x0603);
+        assert_eq!(cpu.register_a, 0x05);
+        assert_eq!(cpu.register_x, 0x05);
+        assert_eq!(cpu.elapsed_cycles(), 2 + 2);
+
+        // resuming steps over the breakpoint
+        cpu.run().unwrap();
+        assert_eq!(cpu.register_x, 0x06);
+    }
+
+    #[test]
+    fn test_breakpoint_hit_again() {
+        // a2 03 ca d0 fd 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.add_breakpoint(0x0602);
+        cpu.load(vec![0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x00]);
+
+        let mut hits = vec![];
+        while let Err(CpuError::Breakpoint(pc)) = cpu.run() {
+            hits.push((pc, cpu.register_x));
+        }
+        assert_eq!(hits, vec![(0x0602, 3), (0x0602, 2), (0x0602, 1)]);
         assert_eq!(cpu.register_x, 0);
-        assert!(cpu.status & Flag::Zero as u8 != 0);
     }
 
     #[test]
-    fn test_inx_positive() {
-        let mut cpu = CPU::new(Bus::new());
-        cpu.register_x = 0x11;
-        cpu.load_and_run(vec![0xe8, 0xe8, 0x00]);
+    fn test_remove_breakpoint() {
+        // a9 05 aa e8 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.add_breakpoint(0x0603);
+        cpu.remove_breakpoint(0x0603);
+        cpu.load_and_run(vec![0xa9, 0x05, 0xaa, 0xe8, 0x00]).unwrap();
+        assert_eq!(cpu.register_x, 0x06);
+    }
 
-        assert_eq!(cpu.register_x, 0x13);
-        assert!(cpu.status & Flag::Zero as u8 == 0);
-        assert!(cpu.status & Flag::Negative as u8 == 0);
+    fn watch_log<M: Mem>(cpu: &mut CPU<M>) -> Arc<Mutex<Vec<(u16, u8, WatchMode)>>> {
+        let log = Arc::new(Mutex::new(vec![]));
+        let callback_log = Arc::clone(&log);
+        cpu.set_watch_callback(Box::new(move |addr, data, access| {
+            callback_log.lock().unwrap().push((addr, data, access));
+        }));
+        log
+    }
+
+    #[test]
+    fn test_write_watchpoint() {
+        // a9 42 8d 00 02 ad 00 02 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.add_watchpoint(0x0200, WatchMode::Write);
+        let log = watch_log(&mut cpu);
+
+        cpu.load_and_run(vec![0xa9, 0x42, 0x8d, 0x00, 0x02, 0xad, 0x00, 0x02, 0x00]).unwrap();
+
+        assert_eq!(*log.lock().unwrap(), vec![(0x0200, 0x42, WatchMode::Write)]);
+    }
+
+    #[test]
+    fn test_read_write_watchpoint() {
+        // a9 42 85 10 e6 10 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.add_watchpoint(0x0010, WatchMode::ReadWrite);
+        let log = watch_log(&mut cpu);
+
+        cpu.load_and_run(vec![0xa9, 0x42, 0x85, 0x10, 0xe6, 0x10, 0x00]).unwrap();
+
+        assert_eq!(*log.lock().unwrap(), vec![
+            (0x0010, 0x42, WatchMode::Write),
+            (0x0010, 0x42, WatchMode::Read),
+            (0x0010, 0x43, WatchMode::Write),
+        ]);
+
+        cpu.remove_watchpoint(0x0010);
+        cpu.mem_write(0x0010, 0x00);
+        assert_eq!(log.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_step() {
+        // a9 05 aa e8 8d 00 02 d0 fa 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0xa9, 0x05, 0xaa, 0xe8, 0x8d, 0x00, 0x02, 0xd0, 0xfa, 0x00]);
+
+        let steps: Vec<StepResult> = (0..5).map(|_| cpu.step().unwrap()).collect();
+        let progression: Vec<(u16, u16, u64)> =
+            steps.iter().map(|step| (step.pc_before, step.pc_after, step.cycles_consumed)).collect();
+        assert_eq!(progression, vec![
+            (0x0600, 0x0602, 2),
+            (0x0602, 0x0603, 2),
+            (0x0603, 0x0604, 2),
+            (0x0604, 0x0607, 4),
+            // taken branch back to INX
+            (0x0607, 0x0603, 3),
+        ]);
+        assert_eq!(cpu.register_x, 0x06);
+        assert_eq!(cpu.mem_read(0x0200), 0x05);
+    }
+
+    #[test]
+    fn test_iter_yields_snapshots_before_each_instruction() {
+        // a2 03 ca d0 fd 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x00]);
+        cpu.power_on();
+
+        let states: Vec<(u16, u8, u64)> = cpu
+            .iter()
+            .take(5)
+            .map(|state| state.unwrap())
+            .map(|state| (state.pc, state.x, state.cycle))
+            .collect();
+
+        assert_eq!(
+            states,
+            vec![
+                (0x0600, 0x00, 0),
+                (0x0602, 0x03, 2),
+                (0x0603, 0x02, 4),
+                (0x0602, 0x02, 7),
+                (0x0603, 0x01, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_iter_stops_after_error() {
+        // e8 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0xe8, 0x00]);
+        cpu.power_on();
+
+        let states: Vec<Result<CpuSnapshot, CpuError>> = cpu.iter().collect();
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[1], Err(CpuError::Break));
+    }
+
+    #[test]
+    fn test_step_errors() {
+        // 00
+        let mut cpu = CPU::new(test_memory());
+        cpu.load(vec![0x00]);
+        assert_eq!(cpu.step(), Err(CpuError::Break));
+        assert_eq!(cpu.mem_read_u16(IRQ_BRK_VECTOR), cpu.program_counter);
+
+        // 9e
+        cpu.load(vec![0x9e]);
+        assert_eq!(cpu.step(), Err(CpuError::UnknownOpcode(0x9e)));
     }
 }
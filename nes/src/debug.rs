@@ -0,0 +1,182 @@
+use crate::bus::Mem;
+use crate::ppu::palette;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+const BYTES_PER_LINE: u16 = 16;
+
+// the 4KB halves of CHR memory, $0000-$0FFF and $1000-$1FFF
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternTable {
+    Left,
+    Right,
+}
+
+const PATTERN_TABLE_SIZE: usize = 128;
+
+/*
+ * Hex dump of len bytes from start, 16 per line with their printable ASCII:
+ * 0200: 4C F5 C5 EA EA EA EA EA                          L.......
+ * A short last line is padded so the ASCII column stays aligned. Addresses wrap at $FFFF.
+ */
+pub fn mem_dump(mem: &mut impl Mem, start: u16, len: u16) -> String {
+    let mut dump = String::new();
+    let mut offset = 0;
+
+    while offset < len {
+        let addr = start.wrapping_add(offset);
+        let count = (len - offset).min(BYTES_PER_LINE);
+        let bytes: Vec<u8> = (0..count).map(|i| mem.peek(addr.wrapping_add(i))).collect();
+
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| if (0x20..=0x7e).contains(&byte) { byte as char } else { '.' })
+            .collect();
+        let _ = writeln!(dump, "{:04X}: {:<47}  {}", addr, hex.join(" "), ascii);
+
+        offset += count;
+    }
+    dump
+}
+
+/*
+ * The 256 tiles of a pattern table as a 16x16 grid, 128x128 RGB pixels row by row.
+ * A tile is 16 bytes: 8 rows of the low bit plane, then 8 of the high one, bit 7 on the left.
+ * Pixel values 0-3 take their colors from the first background palette of palette;
+ * CHR past the end of chr reads as 0.
+ */
+pub fn render_pattern_table(chr: &[u8], palette: &[u8; 32], table: PatternTable) -> Box<[u8; 128 * 128 * 3]> {
+    let base = match table {
+        PatternTable::Left => 0x0000,
+        PatternTable::Right => 0x1000,
+    };
+    let chr_byte = |addr: usize| chr.get(addr).copied().unwrap_or(0);
+    let mut pixels = Box::new([0; PATTERN_TABLE_SIZE * PATTERN_TABLE_SIZE * 3]);
+
+    for tile in 0..256 {
+        let (tile_x, tile_y) = (tile % 16 * 8, tile / 16 * 8);
+        for row in 0..8 {
+            let low = chr_byte(base + tile * 16 + row);
+            let high = chr_byte(base + tile * 16 + row + 8);
+            for column in 0..8 {
+                let bit = 7 - column;
+                let value = ((high >> bit) & 1) << 1 | ((low >> bit) & 1);
+                let (r, g, b) = palette::palette_index_to_rgb(palette[value as usize]);
+
+                let offset = ((tile_y + row) * PATTERN_TABLE_SIZE + tile_x + column) * 3;
+                pixels[offset..offset + 3].copy_from_slice(&[r, g, b]);
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::bus::Bus;
+
+    #[test]
+    fn test_mem_dump() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        let bytes = b"\x4c\xf5\xc5Hello, NES!\x00\xff~ \x7f";
+        for (i, byte) in bytes.iter().enumerate() {
+            bus.mem_write(0x0200 + i as u16, *byte);
+        }
+
+        assert_eq!(
+            mem_dump(&mut bus, 0x0200, bytes.len() as u16),
+            "0200: 4C F5 C5 48 65 6C 6C 6F 2C 20 4E 45 53 21 00 FF  L..Hello, NES!..\n\
+             0210: 7E 20 7F                                         ~ .\n"
+        );
+    }
+
+    #[test]
+    fn test_mem_dump_wraps() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.mem_write(0x0000, 0x41);
+        assert_eq!(
+            mem_dump(&mut bus, 0xffff, 2),
+            "FFFF: 00 41                                            .A\n"
+        );
+        assert_eq!(mem_dump(&mut bus, 0x0000, 0), "");
+    }
+
+    #[test]
+    fn test_mem_dump_leaves_io_registers_alone() {
+        let mut bus = Bus::new_with_rom(vec![]);
+        bus.ppu.set_vblank();
+        assert_eq!(
+            mem_dump(&mut bus, 0x2000, 8),
+            "2000: 00 00 00 00 00 00 00 00                          ........\n"
+        );
+        assert_eq!(bus.ppu.vblank, true);
+    }
+
+    // background palette 0: backdrop, red, green, white
+    fn test_palette() -> [u8; 32] {
+        let mut palette = [0; 32];
+        palette[..4].copy_from_slice(&[0x0F, 0x16, 0x1A, 0x30]);
+        palette
+    }
+
+    // tiles drawn as their pixel values, row by row
+    fn reference(tiles: &[(usize, [&str; 8])]) -> Vec<u8> {
+        let palette = test_palette();
+        let mut values = vec![0; PATTERN_TABLE_SIZE * PATTERN_TABLE_SIZE];
+        for (tile, rows) in tiles {
+            for (row, line) in rows.iter().enumerate() {
+                for (column, value) in line.bytes().enumerate() {
+                    let (x, y) = (tile % 16 * 8 + column, tile / 16 * 8 + row);
+                    values[y * PATTERN_TABLE_SIZE + x] = (value - b'0') as usize;
+                }
+            }
+        }
+        values
+            .iter()
+            .flat_map(|&value| {
+                let (r, g, b) = palette::palette_index_to_rgb(palette[value]);
+                [r, g, b]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_render_pattern_table() {
+        let mut chr = vec![0; 0x2000];
+        // left tile 1: a frame on the sides, the top row in color 3 and 2
+        for row in 0..8 {
+            chr[16 + row] = 0b1000_0001;
+        }
+        chr[16 + 8] = 0xFF;
+        // left tile 17: the high plane only
+        for row in 0..8 {
+            chr[17 * 16 + 8 + row] = 0xFF;
+        }
+        // right tile 0: solid color 3
+        for row in 0..16 {
+            chr[0x1000 + row] = 0xFF;
+        }
+
+        let left = render_pattern_table(&chr, &test_palette(), PatternTable::Left);
+        let right = render_pattern_table(&chr, &test_palette(), PatternTable::Right);
+
+        assert_eq!(left.to_vec(), reference(&[
+            (1, ["32222223", "10000001", "10000001", "10000001",
+                 "10000001", "10000001", "10000001", "10000001"]),
+            (17, ["22222222"; 8]),
+        ]));
+        assert_eq!(right.to_vec(), reference(&[(0, ["33333333"; 8])]));
+    }
+
+    #[test]
+    fn test_render_pattern_table_short_chr() {
+        let pixels = render_pattern_table(&[0xFF], &test_palette(), PatternTable::Right);
+        assert_eq!(pixels.to_vec(), reference(&[]));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::bus::Mem;
+use crate::cpu::AddressingMode;
+use crate::opcodes;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/*
+ * Disassembles count instructions starting at start, returns the address of every
+ * instruction with its text, e.g. (0x0600, "LDA #$05"). Unknown opcodes come out as "??? $XX".
+ */
+pub fn disassemble(mem: &mut impl Mem, start: u16, count: usize) -> Vec<(u16, String)> {
+    let mut lines = vec![];
+    let mut addr = start;
+
+    for _ in 0..count {
+        let (text, len) = disassemble_instruction(mem, addr);
+        lines.push((addr, text));
+        addr = addr.wrapping_add(len as u16);
+    }
+    lines
+}
+
+// the text and the length of the instruction at addr
+pub fn disassemble_instruction(mem: &mut impl Mem, addr: u16) -> (String, u8) {
+    let code = mem.peek(addr);
+    let opcode = match opcodes::OPCODE_TABLE[code as usize] {
+        Some(opcode) => opcode,
+        None => return (format!("??? ${:02X}", code), 1),
+    };
+
+    let lo = if opcode.len > 1 { mem.peek(addr.wrapping_add(1)) } else { 0 };
+    let hi = if opcode.len > 2 { mem.peek(addr.wrapping_add(2)) } else { 0 };
+    let word = u16::from_le_bytes([lo, hi]);
+
+    let operand = match (&opcode.mode, opcode.len) {
+        // branches, the operand is the target of the relative jump
+        (AddressingMode::NoneAddressing, 2) => {
+            let target = addr.wrapping_add(2).wrapping_add(lo as i8 as u16);
+            format!("${:04X}", target)
+        }
+        // fill the mode's notation, the word first so its nnnn is not taken for two bytes
+        (mode, _) => mode
+            .to_string()
+            .replace("nnnn", &format!("{:04X}", word))
+            .replace("nn", &format!("{:02X}", lo)),
+    };
+
+    let text = if operand.is_empty() {
+        opcode.mnemonic.to_string()
+    } else {
+        format!("{} {}", opcode.mnemonic, operand)
+    };
+    (text, opcode.len)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::bus::Bus;
+
+    fn disassemble_program(program: &[u8], count: usize) -> Vec<(u16, String)> {
+        let mut bus = Bus::new_with_rom(vec![]);
+        for (i, byte) in program.iter().enumerate() {
+            bus.mem_write(0x0600 + i as u16, *byte);
+        }
+        disassemble(&mut bus, 0x0600, count)
+    }
+
+    fn text(lines: Vec<(u16, String)>) -> Vec<String> {
+        lines.into_iter().map(|(_, text)| text).collect()
+    }
+
+    #[test]
+    fn test_addressing_modes() {
+        let program = [
+            0xa9, 0x05, // LDA #$05
+            0xa5, 0x10, // LDA $10
+            0xb5, 0x10, // LDA $10,X
+            0xb6, 0x10, // LDX $10,Y
+            0x8d, 0x00, 0x02, // STA $0200
+            0x9d, 0x00, 0x02, // STA $0200,X
+            0x99, 0x00, 0x02, // STA $0200,Y
+            0x6c, 0xfc, 0xff, // JMP ($FFFC)
+            0xa1, 0x20, // LDA ($20,X)
+            0xb1, 0x20, // LDA ($20),Y
+            0x0a, // ASL A
+            0xe8, // INX
+        ];
+        assert_eq!(text(disassemble_program(&program, 12)), vec![
+            "LDA #$05", "LDA $10", "LDA $10,X", "LDX $10,Y", "STA $0200", "STA $0200,X",
+            "STA $0200,Y", "JMP ($FFFC)", "LDA ($20,X)", "LDA ($20),Y", "ASL A", "INX",
+        ]);
+    }
+
+    #[test]
+    fn test_addresses() {
+        // a9 01 8d 00 02 00
+        let lines = disassemble_program(&[0xa9, 0x01, 0x8d, 0x00, 0x02, 0x00], 3);
+        assert_eq!(lines, vec![
+            (0x0600, String::from("LDA #$01")),
+            (0x0602, String::from("STA $0200")),
+            (0x0605, String::from("BRK")),
+        ]);
+    }
+
+    #[test]
+    fn test_branch_targets() {
+        // d0 fe, f0 04, 10 80
+        let lines = disassemble_program(&[0xd0, 0xfe, 0xf0, 0x04, 0x10, 0x80], 3);
+        assert_eq!(text(lines), vec!["BNE $0600", "BEQ $0608", "BPL $0586"]);
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let lines = disassemble_program(&[0x9e, 0xe8], 2);
+        assert_eq!(lines, vec![(0x0600, String::from("??? $9E")), (0x0601, String::from("INX"))]);
+    }
+
+    #[test]
+    fn test_unofficial_opcode() {
+        let lines = disassemble_program(&[0xa7, 0x33], 1);
+        assert_eq!(text(lines), vec!["*LAX $33"]);
+    }
+}
@@ -0,0 +1,25 @@
+// without std the emulator core builds on core and alloc, e.g. for embedded front ends
+#![cfg_attr(not(feature = "std"), no_std)]
+// Copy values are passed around by value, never cloned
+#![deny(clippy::clone_on_copy)]
+
+pub mod cpu;
+pub mod opcodes;
+pub mod bus;
+pub mod cartridge;
+pub mod mapper;
+pub mod ppu;
+pub mod apu;
+pub mod region;
+pub mod controller;
+pub mod disasm;
+pub mod debug;
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod save_state;
+#[cfg(feature = "std")]
+pub mod rewind;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+extern crate alloc;
@@ -1,10 +1,7 @@
-pub mod cpu;
-pub mod opcodes;
-pub mod bus;
-
-use bus::Mem;
-use cpu::CPU;
-use bus::Bus;
+use nes::bus::Mem;
+use nes::cpu::CPU;
+use nes::bus::Bus;
+use nes::mapper::Mapper0;
 use rand::Rng;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
@@ -13,9 +10,6 @@ use sdl2::pixels::PixelFormatEnum;
 use sdl2::EventPump;
 use std::time::Duration;
 
-#[macro_use]
-extern crate lazy_static;
-
 fn color(byte: u8) -> Color {
     match byte {
         0 => sdl2::pixels::Color::BLACK,
@@ -30,7 +24,7 @@ fn color(byte: u8) -> Color {
     }
 }
 
-fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
+fn read_screen_state(cpu: &mut CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
     let mut frame_idx = 0;
     let mut update = false;
     for i in 0x0200..0x600 {
@@ -126,15 +120,20 @@ fn main() {
     ];
 
     //load the game
-    let mut cpu = CPU::new(Bus::new());
+    // the game runs from RAM, the ROM only has to supply the reset vector
+    let mut rom = vec![0; 0x8000];
+    rom[0x7ffc] = 0x00;
+    rom[0x7ffd] = 0x06;
+
+    let mut cpu = CPU::new(Bus::new(Box::new(Mapper0::from_prg_rom(rom))));
     cpu.load(game_code);
-    cpu.reset();
+    cpu.power_on();
 
     let mut screen_state = [0 as u8; 32 * 3 * 32];
     let mut rng = rand::thread_rng();
 
     // run the game cycle
-    cpu.run_with_callback(move |cpu| {
+    let result = cpu.run_with_callback(move |cpu| {
         handle_user_input(cpu, &mut event_pump);
 
         cpu.mem_write(0xfe, rng.gen_range(1, 16));
@@ -149,4 +148,8 @@ fn main() {
 
         ::std::thread::sleep(std::time::Duration::new(0, 5_000));
     });
+
+    if let Err(err) = result {
+        println!("CPU stopped: {}", err);
+    }
 }
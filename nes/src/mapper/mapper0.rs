@@ -0,0 +1,145 @@
+use crate::cartridge::Mirroring;
+use crate::mapper::{Mapper, MapperState};
+use alloc::vec;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/*
+ * NROM - no bank switching
+ * 16KB or 32KB of PRG-ROM, a 16KB ROM is mirrored into $C000-$FFFF.
+ * Boards without CHR-ROM carry 8KB of CHR-RAM instead.
+ * $6000-$7FFF is 8KB of PRG-RAM.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mapper0 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Mapper0 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        Mapper0 {
+            prg_rom,
+            prg_ram: vec![0; 0x2000],
+            chr: if chr_is_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_is_ram,
+            mirroring,
+        }
+    }
+
+    // raw program images, e.g. for tests
+    pub fn from_prg_rom(prg_rom: Vec<u8>) -> Self {
+        Mapper0::new(prg_rom, vec![], Mirroring::Horizontal)
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let mut addr = (addr - 0x8000) as usize;
+        if self.prg_rom.len() == 0x4000 {
+            addr %= 0x4000;
+        }
+        addr
+    }
+}
+
+impl Mapper for Mapper0 {
+    fn read(&self, addr: u16) -> u8 {
+        if addr < 0x8000 {
+            return self.prg_ram[(addr - 0x6000) as usize];
+        }
+
+        self.prg_rom[self.prg_rom_offset(addr)]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        // NROM has no registers, writes to ROM space are ignored
+        if addr < 0x8000 {
+            self.prg_ram[(addr - 0x6000) as usize] = val;
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        self.chr[(addr & 0x1FFF) as usize]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        if self.chr_is_ram {
+            self.chr[(addr & 0x1FFF) as usize] = val;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mapper0(self.clone())
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn patch_prg_rom(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            let offset = self.prg_rom_offset(addr);
+            self.prg_rom[offset] = val;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_prg_rom_32kb_banks() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0000] = 0x11;
+        rom[0x4000] = 0x22;
+        let mapper = Mapper0::from_prg_rom(rom);
+        assert_eq!(mapper.read(0x8000), 0x11);
+        assert_eq!(mapper.read(0xc000), 0x22);
+    }
+
+    #[test]
+    fn test_prg_rom_16kb_mirrored() {
+        let mut rom = vec![0; 0x4000];
+        rom[0x0010] = 0x33;
+        let mapper = Mapper0::from_prg_rom(rom);
+        assert_eq!(mapper.read(0x8010), 0x33);
+        assert_eq!(mapper.read(0xc010), 0x33);
+    }
+
+    #[test]
+    fn test_patch_prg_rom() {
+        let mut mapper = Mapper0::from_prg_rom(vec![0; 0x4000]);
+        mapper.write(0xc010, 0x44);
+        assert_eq!(mapper.read(0xc010), 0x00);
+        mapper.patch_prg_rom(0xc010, 0x44);
+        assert_eq!(mapper.read(0x8010), 0x44);
+    }
+
+    #[test]
+    fn test_chr_rom_is_read_only() {
+        let mut mapper = Mapper0::new(vec![0; 0x4000], vec![0x44; 0x2000], Mirroring::Vertical);
+        mapper.chr_write(0x0010, 0x55);
+        assert_eq!(mapper.chr_read(0x0010), 0x44);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_chr_ram() {
+        let mut mapper = Mapper0::from_prg_rom(vec![0; 0x4000]);
+        mapper.chr_write(0x1fff, 0x55);
+        assert_eq!(mapper.chr_read(0x1fff), 0x55);
+    }
+}
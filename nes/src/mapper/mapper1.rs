@@ -0,0 +1,281 @@
+use crate::cartridge::Mirroring;
+use crate::mapper::{Mapper, MapperState};
+use alloc::vec;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+/*
+ * MMC1 (SxROM)
+ * Registers are loaded serially: each write to $8000-$FFFF shifts bit 0 into a
+ * 5-bit shift register, the fifth write copies it into the register selected by
+ * address bits 13-14. A write with bit 7 set resets the shift register.
+ *   $8000-$9FFF control   (mirroring, PRG banking mode, CHR banking mode)
+ *   $A000-$BFFF CHR bank 0
+ *   $C000-$DFFF CHR bank 1
+ *   $E000-$FFFF PRG bank  (bit 4 disables PRG-RAM)
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mapper1 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    shift_register: u8,
+    shift_count: u8,
+
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+}
+
+impl Mapper1 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        Mapper1 {
+            prg_rom,
+            prg_ram: vec![0; 0x2000],
+            chr: if chr_is_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_is_ram,
+            shift_register: 0,
+            shift_count: 0,
+            // power on with the last PRG bank fixed at $C000 so the vectors are reachable
+            control: 0x0C,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let last_bank = self.prg_rom.len() / PRG_BANK_SIZE - 1;
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let offset = (addr as usize - 0x8000) % PRG_BANK_SIZE;
+
+        let bank = match ((self.control >> 2) & 0b11, addr < 0xC000) {
+            // 32KB mode, the low bit of the bank number is ignored
+            (0 | 1, true) => bank & !1,
+            (0 | 1, false) => bank | 1,
+            // first bank fixed at $8000, switch $C000
+            (2, true) => 0,
+            (2, false) => bank,
+            // switch $8000, last bank fixed at $C000
+            (_, true) => bank,
+            (_, false) => last_bank,
+        };
+
+        (bank * PRG_BANK_SIZE + offset) % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = (addr & 0x1FFF) as usize;
+        let offset = addr % CHR_BANK_SIZE;
+
+        let bank = if self.control & 0x10 == 0 {
+            // 8KB mode, the low bit of the bank number is ignored
+            (self.chr_bank_0 & !1) as usize + addr / CHR_BANK_SIZE
+        } else if addr < CHR_BANK_SIZE {
+            self.chr_bank_0 as usize
+        } else {
+            self.chr_bank_1 as usize
+        };
+
+        (bank * CHR_BANK_SIZE + offset) % self.chr.len()
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = val,
+            0xA000..=0xBFFF => self.chr_bank_0 = val,
+            0xC000..=0xDFFF => self.chr_bank_1 = val,
+            _ => self.prg_bank = val,
+        }
+    }
+}
+
+impl Mapper for Mapper1 {
+    fn read(&self, addr: u16) -> u8 {
+        if addr < 0x8000 {
+            if !self.prg_ram_enabled() {
+                return 0;
+            }
+            return self.prg_ram[(addr - 0x6000) as usize];
+        }
+        self.prg_rom[self.prg_offset(addr)]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        if addr < 0x8000 {
+            if self.prg_ram_enabled() {
+                self.prg_ram[(addr - 0x6000) as usize] = val;
+            }
+            return;
+        }
+
+        if val & 0x80 != 0 {
+            self.shift_register = 0;
+            self.shift_count = 0;
+            self.control |= 0x0C;
+            return;
+        }
+
+        self.shift_register |= (val & 1) << self.shift_count;
+        self.shift_count += 1;
+
+        if self.shift_count == 5 {
+            self.write_register(addr, self.shift_register);
+            self.shift_register = 0;
+            self.shift_count = 0;
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = val;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mapper1(self.clone())
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // 8 PRG banks and 8 CHR banks, every byte holds its own bank number
+    fn mapper() -> Mapper1 {
+        let mut prg_rom = vec![];
+        for bank in 0..8 {
+            prg_rom.extend(vec![bank as u8; PRG_BANK_SIZE]);
+        }
+        let mut chr_rom = vec![];
+        for bank in 0..8 {
+            chr_rom.extend(vec![bank as u8; CHR_BANK_SIZE]);
+        }
+        Mapper1::new(prg_rom, chr_rom)
+    }
+
+    fn load_register(mapper: &mut Mapper1, addr: u16, val: u8) {
+        for bit in 0..5 {
+            mapper.write(addr, (val >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn test_power_on_fixes_last_bank() {
+        let mapper = mapper();
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0xFFFC), 7);
+    }
+
+    #[test]
+    fn test_init_sequence() {
+        let mut mapper = mapper();
+        // reset, then control: vertical mirroring, fixed last bank, 4KB CHR banks
+        mapper.write(0x8000, 0x80);
+        load_register(&mut mapper, 0x8000, 0b1_11_10);
+        load_register(&mut mapper, 0xE000, 0x03);
+        load_register(&mut mapper, 0xA000, 0x02);
+        load_register(&mut mapper, 0xC000, 0x05);
+
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        assert_eq!(mapper.read(0x8000), 3);
+        assert_eq!(mapper.read(0xC000), 7);
+        assert_eq!(mapper.chr_read(0x0000), 2);
+        assert_eq!(mapper.chr_read(0x1000), 5);
+    }
+
+    #[test]
+    fn test_reset_in_the_middle_of_a_load() {
+        let mut mapper = mapper();
+        mapper.write(0xE000, 1);
+        mapper.write(0xE000, 1);
+        mapper.write(0xE000, 0x80);
+        load_register(&mut mapper, 0xE000, 0x02);
+        assert_eq!(mapper.read(0x8000), 2);
+    }
+
+    #[test]
+    fn test_prg_mode_fix_first_bank() {
+        let mut mapper = mapper();
+        load_register(&mut mapper, 0x8000, 0b0_10_00);
+        load_register(&mut mapper, 0xE000, 0x04);
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0xC000), 4);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn test_prg_mode_32kb() {
+        let mut mapper = mapper();
+        load_register(&mut mapper, 0x8000, 0b0_00_01);
+        load_register(&mut mapper, 0xE000, 0x05);
+        assert_eq!(mapper.read(0x8000), 4);
+        assert_eq!(mapper.read(0xC000), 5);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn test_chr_mode_8kb() {
+        let mut mapper = mapper();
+        load_register(&mut mapper, 0x8000, 0b0_11_11);
+        load_register(&mut mapper, 0xA000, 0x03);
+        assert_eq!(mapper.chr_read(0x0000), 2);
+        assert_eq!(mapper.chr_read(0x1000), 3);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_prg_ram_enable() {
+        let mut mapper = mapper();
+        mapper.write(0x6000, 0x42);
+        assert_eq!(mapper.read(0x6000), 0x42);
+
+        load_register(&mut mapper, 0xE000, 0x10);
+        assert_eq!(mapper.read(0x6000), 0);
+        mapper.write(0x6000, 0x24);
+
+        load_register(&mut mapper, 0xE000, 0x00);
+        assert_eq!(mapper.read(0x6000), 0x42);
+    }
+
+    #[test]
+    fn test_chr_ram() {
+        let mut mapper = Mapper1::new(vec![0; 0x8000], vec![]);
+        mapper.chr_write(0x1234, 0x99);
+        assert_eq!(mapper.chr_read(0x1234), 0x99);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::cartridge::Mirroring;
+use crate::mapper::{Mapper, MapperState};
+use alloc::vec;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/*
+ * UxROM
+ * Any write to $8000-$FFFF selects the 16KB PRG bank at $8000-$BFFF,
+ * $C000-$FFFF is fixed to the last bank. CHR is 8KB of RAM unless the
+ * cartridge carries CHR-ROM, there is no PRG-RAM.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mapper2 {
+    prg_rom: Vec<u8>,
+    bank_select: usize,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Mapper2 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        Mapper2 {
+            prg_rom,
+            bank_select: 0,
+            chr: if chr_is_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_is_ram,
+            mirroring,
+        }
+    }
+}
+
+impl Mapper for Mapper2 {
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xBFFF => {
+                let bank = self.bank_select % (self.prg_rom.len() / PRG_BANK_SIZE);
+                self.prg_rom[bank * PRG_BANK_SIZE + (addr - 0x8000) as usize]
+            }
+            0xC000..=0xFFFF => {
+                let last_bank = self.prg_rom.len() - PRG_BANK_SIZE;
+                self.prg_rom[last_bank + (addr - 0xC000) as usize]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            self.bank_select = val as usize;
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        self.chr[(addr & 0x1FFF) as usize]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        if self.chr_is_ram {
+            self.chr[(addr & 0x1FFF) as usize] = val;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mapper2(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // 8 PRG banks, every byte holds its own bank number
+    fn mapper() -> Mapper2 {
+        let mut prg_rom = vec![];
+        for bank in 0..8 {
+            prg_rom.extend(vec![bank as u8; PRG_BANK_SIZE]);
+        }
+        Mapper2::new(prg_rom, vec![], Mirroring::Vertical)
+    }
+
+    #[test]
+    fn test_power_on_banks() {
+        let mapper = mapper();
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0xBFFF), 0);
+        assert_eq!(mapper.read(0xC000), 7);
+        assert_eq!(mapper.read(0xFFFF), 7);
+    }
+
+    #[test]
+    fn test_bank_switch() {
+        let mut mapper = mapper();
+        mapper.write(0x8000, 3);
+        assert_eq!(mapper.read(0x8000), 3);
+        assert_eq!(mapper.read(0xC000), 7);
+
+        mapper.write(0xFFF0, 5);
+        assert_eq!(mapper.read(0xA000), 5);
+        assert_eq!(mapper.read(0xE000), 7);
+    }
+
+    #[test]
+    fn test_bank_select_wraps() {
+        let mut mapper = mapper();
+        mapper.write(0x8000, 9);
+        assert_eq!(mapper.read(0x8000), 1);
+    }
+
+    #[test]
+    fn test_chr_ram() {
+        let mut mapper = mapper();
+        mapper.chr_write(0x0042, 0x99);
+        assert_eq!(mapper.chr_read(0x0042), 0x99);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+}
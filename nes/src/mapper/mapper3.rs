@@ -0,0 +1,105 @@
+use crate::cartridge::Mirroring;
+use crate::mapper::{Mapper, MapperState};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/*
+ * CNROM
+ * 16KB or 32KB of fixed PRG-ROM (a 16KB ROM is mirrored into $C000-$FFFF),
+ * any write to $8000-$FFFF selects the 8KB CHR bank seen at PPU $0000-$1FFF.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mapper3 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Mapper3 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        Mapper3 {
+            prg_rom,
+            chr_rom,
+            chr_bank: 0,
+            mirroring,
+        }
+    }
+}
+
+impl Mapper for Mapper3 {
+    fn read(&self, addr: u16) -> u8 {
+        if addr < 0x8000 {
+            return 0;
+        }
+        let addr = (addr - 0x8000) as usize % self.prg_rom.len();
+        self.prg_rom[addr]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        if addr >= 0x8000 {
+            self.chr_bank = val as usize;
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        let bank = self.chr_bank % (self.chr_rom.len() / CHR_BANK_SIZE);
+        self.chr_rom[bank * CHR_BANK_SIZE + (addr & 0x1FFF) as usize]
+    }
+
+    fn chr_write(&mut self, _addr: u16, _val: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mapper3(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // 4 CHR banks, every byte holds its own bank number
+    fn mapper() -> Mapper3 {
+        let mut chr_rom = vec![];
+        for bank in 0..4 {
+            chr_rom.extend(vec![bank as u8; CHR_BANK_SIZE]);
+        }
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[0x0010] = 0x42;
+        Mapper3::new(prg_rom, chr_rom, Mirroring::Horizontal)
+    }
+
+    #[test]
+    fn test_prg_rom_mirrored() {
+        let mapper = mapper();
+        assert_eq!(mapper.read(0x8010), 0x42);
+        assert_eq!(mapper.read(0xC010), 0x42);
+    }
+
+    #[test]
+    fn test_chr_bank_switch() {
+        let mut mapper = mapper();
+        assert_eq!(mapper.chr_read(0x0000), 0);
+
+        mapper.write(0x8000, 2);
+        assert_eq!(mapper.chr_read(0x0000), 2);
+        assert_eq!(mapper.chr_read(0x1FFF), 2);
+
+        mapper.write(0xFFFF, 5);
+        assert_eq!(mapper.chr_read(0x1000), 1);
+    }
+
+    #[test]
+    fn test_chr_rom_is_read_only() {
+        let mut mapper = mapper();
+        mapper.chr_write(0x0000, 0x99);
+        assert_eq!(mapper.chr_read(0x0000), 0);
+    }
+}
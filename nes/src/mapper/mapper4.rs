@@ -0,0 +1,285 @@
+use crate::cartridge::Mirroring;
+use crate::mapper::{Mapper, MapperState};
+use alloc::vec;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/*
+ * MMC3 (TxROM)
+ * Register pairs are selected by the address range and whether the address is even or odd:
+ *   $8000 bank select  (register index, PRG mode, CHR A12 inversion)   $8001 bank data
+ *   $A000 mirroring                                                    $A001 PRG-RAM protect
+ *   $C000 IRQ latch                                                    $C001 IRQ reload
+ *   $E000 IRQ disable/acknowledge                                      $E001 IRQ enable
+ * The scanline counter is clocked through tick_irq by the PPU, once per rendered line at dot 260
+ * where the sprite fetches raise A12.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mapper4 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    four_screen: bool,
+
+    bank_select: u8,
+    registers: [u8; 8],
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    pub irq_pending: bool,
+}
+
+impl Mapper4 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        Mapper4 {
+            prg_rom,
+            prg_ram: vec![0; 0x2000],
+            chr: if chr_is_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_is_ram,
+            four_screen: mirroring == Mirroring::FourScreen,
+            bank_select: 0,
+            registers: [0; 8],
+            mirroring,
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let second_last = bank_count - 2;
+        let last = bank_count - 1;
+        let swap_8000 = self.bank_select & 0x40 != 0;
+
+        let bank = match (addr, swap_8000) {
+            (0x8000..=0x9FFF, false) => self.registers[6] as usize,
+            (0x8000..=0x9FFF, true) => second_last,
+            (0xA000..=0xBFFF, _) => self.registers[7] as usize,
+            (0xC000..=0xDFFF, false) => second_last,
+            (0xC000..=0xDFFF, true) => self.registers[6] as usize,
+            _ => last,
+        };
+
+        (bank % bank_count) * PRG_BANK_SIZE + (addr as usize % PRG_BANK_SIZE)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let mut addr = (addr & 0x1FFF) as usize;
+        // A12 inversion swaps the 2KB and 1KB halves
+        if self.bank_select & 0x80 != 0 {
+            addr ^= 0x1000;
+        }
+
+        let bank = match addr / CHR_BANK_SIZE {
+            0 => self.registers[0] & !1,
+            1 => self.registers[0] | 1,
+            2 => self.registers[1] & !1,
+            3 => self.registers[1] | 1,
+            slot => self.registers[slot - 2],
+        } as usize;
+
+        (bank * CHR_BANK_SIZE + addr % CHR_BANK_SIZE) % self.chr.len()
+    }
+}
+
+impl Mapper for Mapper4 {
+    fn read(&self, addr: u16) -> u8 {
+        if addr < 0x8000 {
+            if !self.prg_ram_enabled {
+                return 0;
+            }
+            return self.prg_ram[(addr - 0x6000) as usize];
+        }
+        self.prg_rom[self.prg_offset(addr)]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        let even = addr & 1 == 0;
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram_write_protect => {
+                self.prg_ram[(addr - 0x6000) as usize] = val;
+            }
+            0x8000..=0x9FFF if even => self.bank_select = val,
+            0x8000..=0x9FFF => self.registers[(self.bank_select & 0b111) as usize] = val,
+            // four-screen boards ignore the mirroring register
+            0xA000..=0xBFFF if even && !self.four_screen => {
+                self.mirroring = if val & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            0xA000..=0xBFFF if !even => {
+                self.prg_ram_enabled = val & 0x80 != 0;
+                self.prg_ram_write_protect = val & 0x40 != 0;
+            }
+            0xC000..=0xDFFF if even => self.irq_latch = val,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, val: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = val;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn tick_irq(&mut self) -> bool {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+            return true;
+        }
+        false
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mapper4(self.clone())
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // 16 PRG banks and 16 CHR banks, every byte holds its own bank number
+    fn mapper() -> Mapper4 {
+        let mut prg_rom = vec![];
+        for bank in 0..16 {
+            prg_rom.extend(vec![bank as u8; PRG_BANK_SIZE]);
+        }
+        let mut chr_rom = vec![];
+        for bank in 0..16 {
+            chr_rom.extend(vec![bank as u8; CHR_BANK_SIZE]);
+        }
+        Mapper4::new(prg_rom, chr_rom, Mirroring::Vertical)
+    }
+
+    fn set_bank(mapper: &mut Mapper4, mode: u8, register: u8, bank: u8) {
+        mapper.write(0x8000, mode | register);
+        mapper.write(0x8001, bank);
+    }
+
+    #[test]
+    fn test_prg_mode_0() {
+        let mut mapper = mapper();
+        set_bank(&mut mapper, 0x00, 6, 3);
+        set_bank(&mut mapper, 0x00, 7, 5);
+        assert_eq!(mapper.read(0x8000), 3);
+        assert_eq!(mapper.read(0xA000), 5);
+        assert_eq!(mapper.read(0xC000), 14);
+        assert_eq!(mapper.read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_prg_mode_1() {
+        let mut mapper = mapper();
+        set_bank(&mut mapper, 0x40, 6, 3);
+        set_bank(&mut mapper, 0x40, 7, 5);
+        assert_eq!(mapper.read(0x8000), 14);
+        assert_eq!(mapper.read(0xA000), 5);
+        assert_eq!(mapper.read(0xC000), 3);
+        assert_eq!(mapper.read(0xFFFF), 15);
+    }
+
+    #[test]
+    fn test_chr_banks() {
+        let mut mapper = mapper();
+        for (register, bank) in [(0, 2), (1, 5), (2, 8), (3, 9), (4, 10), (5, 11)] {
+            set_bank(&mut mapper, 0x00, register, bank);
+        }
+        assert_eq!(mapper.chr_read(0x0000), 2);
+        assert_eq!(mapper.chr_read(0x0400), 3);
+        assert_eq!(mapper.chr_read(0x0800), 4);
+        assert_eq!(mapper.chr_read(0x0C00), 5);
+        assert_eq!(mapper.chr_read(0x1000), 8);
+        assert_eq!(mapper.chr_read(0x1C00), 11);
+
+        // A12 inversion
+        mapper.write(0x8000, 0x80);
+        assert_eq!(mapper.chr_read(0x0000), 8);
+        assert_eq!(mapper.chr_read(0x1000), 2);
+        assert_eq!(mapper.chr_read(0x1C00), 5);
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut mapper = mapper();
+        mapper.write(0xA000, 1);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        mapper.write(0xA000, 0);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_prg_ram_protect() {
+        let mut mapper = mapper();
+        mapper.write(0x6000, 0x42);
+        mapper.write(0xA001, 0xC0);
+        mapper.write(0x6000, 0x24);
+        assert_eq!(mapper.read(0x6000), 0x42);
+        mapper.write(0xA001, 0x00);
+        assert_eq!(mapper.read(0x6000), 0);
+    }
+
+    #[test]
+    fn test_irq_counter() {
+        let mut mapper = mapper();
+        mapper.write(0xC000, 3);
+        mapper.write(0xC001, 0);
+        mapper.write(0xE001, 0);
+
+        let raised: Vec<bool> = (0..8).map(|_| mapper.tick_irq()).collect();
+        // reload on the first scanline, then 2, 1, 0, reload, 2, 1, 0
+        assert_eq!(raised, vec![false, false, false, true, false, false, false, true]);
+        assert_eq!(mapper.irq_pending, true);
+
+        mapper.write(0xE000, 0);
+        assert_eq!(mapper.irq_pending, false);
+        assert_eq!((0..4).any(|_| mapper.tick_irq()), false);
+    }
+}
@@ -0,0 +1,194 @@
+pub mod mapper0;
+pub mod mapper1;
+pub mod mapper2;
+pub mod mapper3;
+pub mod mapper4;
+
+use alloc::boxed::Box;
+pub use mapper0::Mapper0;
+pub use mapper1::Mapper1;
+pub use mapper2::Mapper2;
+pub use mapper3::Mapper3;
+pub use mapper4::Mapper4;
+
+use crate::cartridge::{Cartridge, Mirroring, RomError, CHR_ROM_PAGE_SIZE, PRG_ROM_PAGE_SIZE};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/*
+ * Cartridge hardware seen by the CPU at $6000-$FFFF (PRG-RAM and PRG-ROM)
+ * and by the PPU at $0000-$1FFF
+ */
+pub trait Mapper: Send {
+    fn read(&self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+    fn chr_read(&self, addr: u16) -> u8;
+    fn chr_write(&mut self, addr: u16, val: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    // clocked by the PPU once per rendered line (dot 260), returns true when the cartridge raises an IRQ
+    fn tick_irq(&mut self) -> bool {
+        false
+    }
+
+    // a copy of the board with its banking registers and RAM, for save states
+    fn save_state(&self) -> MapperState;
+
+    // loader/debugger access that overwrites PRG-ROM as seen at addr, boards with bank switching ignore it
+    fn patch_prg_rom(&mut self, _addr: u16, _val: u8) {}
+
+    // PRG-RAM at $6000-$7FFF, battery-backed on some carts; empty on boards without any
+    fn prg_ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+}
+
+// the boards a save state can hold
+#[derive(Serialize, Deserialize)]
+pub enum MapperState {
+    Mapper0(Mapper0),
+    Mapper1(Mapper1),
+    Mapper2(Mapper2),
+    Mapper3(Mapper3),
+    Mapper4(Mapper4),
+}
+
+impl MapperState {
+    pub fn into_mapper(self) -> Box<dyn Mapper> {
+        match self {
+            MapperState::Mapper0(mapper) => Box::new(mapper),
+            MapperState::Mapper1(mapper) => Box::new(mapper),
+            MapperState::Mapper2(mapper) => Box::new(mapper),
+            MapperState::Mapper3(mapper) => Box::new(mapper),
+            MapperState::Mapper4(mapper) => Box::new(mapper),
+        }
+    }
+}
+
+// #[serde(with = "mapper::serde_mapper")] for Box<dyn Mapper> fields
+pub mod serde_mapper {
+    use super::*;
+
+    #[allow(clippy::borrowed_box)]
+    pub fn serialize<S: Serializer>(mapper: &Box<dyn Mapper>, serializer: S) -> Result<S::Ok, S::Error> {
+        mapper.save_state().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Box<dyn Mapper>, D::Error> {
+        Ok(MapperState::deserialize(deserializer)?.into_mapper())
+    }
+}
+
+/*
+ * The mappers index ROM by whole banks: PRG-ROM must be a non-empty run of 16KB pages,
+ * NROM takes one or two, and CHR-ROM a run of 8KB pages, empty for CHR-RAM except on CNROM
+ */
+fn check_sizes(mapper: u16, prg_rom_size: usize, chr_rom_size: usize) -> Result<(), RomError> {
+    let prg_valid = match mapper {
+        0 => prg_rom_size == PRG_ROM_PAGE_SIZE || prg_rom_size == 2 * PRG_ROM_PAGE_SIZE,
+        _ => prg_rom_size != 0 && prg_rom_size.is_multiple_of(PRG_ROM_PAGE_SIZE),
+    };
+    let chr_valid = match mapper {
+        3 => chr_rom_size != 0 && chr_rom_size.is_multiple_of(CHR_ROM_PAGE_SIZE),
+        _ => chr_rom_size.is_multiple_of(CHR_ROM_PAGE_SIZE),
+    };
+    if prg_valid && chr_valid {
+        Ok(())
+    } else {
+        Err(RomError::InvalidSize)
+    }
+}
+
+pub fn from_cartridge(cartridge: Cartridge) -> Result<Box<dyn Mapper>, RomError> {
+    let mirroring = cartridge.header.mirroring;
+    if cartridge.header.mapper <= 4 {
+        check_sizes(cartridge.header.mapper, cartridge.prg_rom.len(), cartridge.chr_rom.len())?;
+    }
+    match cartridge.header.mapper {
+        0 => Ok(Box::new(Mapper0::new(cartridge.prg_rom, cartridge.chr_rom, mirroring))),
+        1 => Ok(Box::new(Mapper1::new(cartridge.prg_rom, cartridge.chr_rom))),
+        2 => Ok(Box::new(Mapper2::new(cartridge.prg_rom, cartridge.chr_rom, mirroring))),
+        3 => Ok(Box::new(Mapper3::new(cartridge.prg_rom, cartridge.chr_rom, mirroring))),
+        4 => Ok(Box::new(Mapper4::new(cartridge.prg_rom, cartridge.chr_rom, mirroring))),
+        mapper => Err(RomError::UnsupportedMapper(mapper)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::cartridge::{ConsoleType, RomHeader, RomVersion, Timing};
+
+    fn cartridge(mapper: u16) -> Cartridge {
+        Cartridge {
+            header: RomHeader {
+                version: RomVersion::NES10,
+                mapper,
+                submapper: 0,
+                prg_rom_size: 0x4000,
+                chr_rom_size: 0x2000,
+                prg_ram_size: 0x2000,
+                prg_nvram_size: 0,
+                chr_ram_size: 0,
+                chr_nvram_size: 0,
+                mirroring: Mirroring::Vertical,
+                battery: false,
+                trainer: false,
+                timing: Timing::Ntsc,
+                console_type: ConsoleType::Nes,
+            },
+            prg_rom: vec![0x11; 0x4000],
+            chr_rom: vec![0x22; 0x2000],
+        }
+    }
+
+    #[test]
+    fn test_from_cartridge() {
+        let mapper = from_cartridge(cartridge(0)).unwrap();
+        assert_eq!(mapper.read(0xc000), 0x11);
+        assert_eq!(mapper.chr_read(0x0000), 0x22);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_from_cartridge_invalid_sizes() {
+        let sizes = [
+            (0, 0, 0x2000),
+            (0, 0x2000, 0x2000),
+            (0, 0xC000, 0x2000),
+            (1, 0, 0x2000),
+            (1, 0x6000, 0x2000),
+            (2, 0, 0),
+            (2, 0x4000, 0x1000),
+            (3, 0x4000, 0),
+            (4, 0x3000, 0x2000),
+        ];
+        for (mapper, prg_rom_size, chr_rom_size) in sizes {
+            let mut cartridge = cartridge(mapper);
+            cartridge.prg_rom = vec![0; prg_rom_size];
+            cartridge.chr_rom = vec![0; chr_rom_size];
+            assert_eq!(from_cartridge(cartridge).err(), Some(RomError::InvalidSize), "mapper {}", mapper);
+        }
+    }
+
+    #[test]
+    fn test_from_cartridge_chr_ram() {
+        let mut cartridge = cartridge(2);
+        cartridge.prg_rom = vec![0x11; 0x20000];
+        cartridge.chr_rom = vec![];
+        let mut mapper = from_cartridge(cartridge).unwrap();
+        mapper.chr_write(0x0000, 0x33);
+        assert_eq!(mapper.chr_read(0x0000), 0x33);
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        assert_eq!(from_cartridge(cartridge(0xff)).err(), Some(RomError::UnsupportedMapper(0xff)));
+        // NES 2.0 mapper numbers past 255 do not wrap onto the iNES ones
+        assert_eq!(from_cartridge(cartridge(0x101)).err(), Some(RomError::UnsupportedMapper(0x101)));
+    }
+}
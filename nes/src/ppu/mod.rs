@@ -0,0 +1,846 @@
+pub mod palette;
+mod render;
+
+pub use render::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use crate::cartridge::Mirroring;
+use crate::mapper::Mapper;
+use crate::region::Region;
+use alloc::boxed::Box;
+use serde::{Deserialize, Serialize};
+
+/*
+ * Picture Processing Unit
+ * v, t, x and w are the internal "loopy" registers:
+ *   v - current VRAM address (15 bits)
+ *   t - temporary VRAM address, the top left of the screen
+ *   x - fine X scroll (3 bits)
+ *   w - first/second write toggle shared by PPUSCROLL and PPUADDR
+ * t and v are laid out as yyy NN YYYYY XXXXX (fine Y, nametable, coarse Y, coarse X).
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Ppu {
+    pub v: u16,
+    pub t: u16,
+    pub x: u8,
+    pub w: bool,
+
+    pub ctrl: u8,
+    pub mask: u8,
+
+    #[serde(with = "serde_bytes")]
+    pub vram: [u8; 2048],
+    pub palette_table: [u8; 32],
+    // PPUDATA reads outside the palette return the byte fetched by the previous read
+    read_buffer: u8,
+
+    // 64 sprites, 4 bytes each: Y, tile index, attributes, X
+    #[serde(with = "serde_bytes")]
+    pub oam_data: [u8; 256],
+    pub oam_addr: u8,
+    // the sprites on the line being rendered
+    pub secondary_oam: [u8; 32],
+    sprite_count: usize,
+    sprite_zero_on_line: bool,
+    pub sprite_overflow: bool,
+    pub sprite0_hit: bool,
+
+    // redrawn every frame so save states leave it out
+    #[serde(skip, default = "blank_frame")]
+    frame_buffer: FrameBuffer,
+
+    // position of the next dot, scanlines 0-239 are visible, see Region for where VBlank and the pre-render line are
+    pub dot: u16,
+    pub scanline: i16,
+    // frames completed since power on
+    pub frame: u64,
+
+    pub vblank: bool,
+    // set for the dot on which VBlank starts, a PPUSTATUS read in that window suppresses the flag and the NMI
+    vblank_race: bool,
+    nmi_suppressed: bool,
+    // raised by the mapper's scanline counter, not yet picked up by the bus
+    mapper_irq: bool,
+
+    region: Region,
+}
+
+const DOTS_PER_SCANLINE: u16 = 341;
+
+// raised once per frame when VBlank starts with NMI enabled in PPUCTRL
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct NmiSignal;
+
+// PPUCTRL bits
+const CTRL_NAMETABLE: u8 = 0b0000_0011;
+const CTRL_VRAM_INCREMENT: u8 = 0b0000_0100;
+const CTRL_SPRITE_PATTERN: u8 = 0b0000_1000;
+const CTRL_BACKGROUND_PATTERN: u8 = 0b0001_0000;
+const CTRL_SPRITE_SIZE: u8 = 0b0010_0000;
+const CTRL_NMI_ENABLE: u8 = 0b1000_0000;
+
+// PPUMASK bits
+const MASK_GREYSCALE: u8 = 0b0000_0001;
+const MASK_SHOW_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const MASK_SHOW_SPRITES_LEFT: u8 = 0b0000_0100;
+const MASK_SHOW_BACKGROUND: u8 = 0b0000_1000;
+const MASK_SHOW_SPRITES: u8 = 0b0001_0000;
+
+// PPUSTATUS bits
+const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+const STATUS_VBLANK: u8 = 0b1000_0000;
+
+// sprite attribute bits
+const SPRITE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const SPRITE_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const SPRITE_FLIP_VERTICAL: u8 = 0b1000_0000;
+
+fn blank_frame() -> FrameBuffer {
+    FrameBuffer {
+        pixels: Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT * 3]),
+    }
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Ppu::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu::new_with_region(Region::NTSC)
+    }
+
+    pub fn new_with_region(region: Region) -> Self {
+        Ppu {
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            ctrl: 0,
+            mask: 0,
+            vram: [0; 2048],
+            palette_table: [0; 32],
+            read_buffer: 0,
+            oam_data: [0; 256],
+            oam_addr: 0,
+            secondary_oam: [0xFF; 32],
+            sprite_count: 0,
+            sprite_zero_on_line: false,
+            sprite_overflow: false,
+            sprite0_hit: false,
+            frame_buffer: blank_frame(),
+            dot: 0,
+            scanline: 0,
+            frame: 0,
+            vblank: false,
+            vblank_race: false,
+            nmi_suppressed: false,
+            mapper_irq: false,
+            region,
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    // $2000
+    /*
+     * Turning NMI on while the VBlank flag is still set raises an NMI right away,
+     * inside the race window poll_nmi raises it on the next dot instead
+     */
+    pub fn write_ctrl(&mut self, val: u8) -> Option<NmiSignal> {
+        let was_enabled = self.nmi_enabled();
+        self.ctrl = val;
+        self.t = (self.t & !0x0C00) | (((val & CTRL_NAMETABLE) as u16) << 10);
+        (!was_enabled && self.nmi_enabled() && self.vblank && !self.vblank_race).then_some(NmiSignal)
+    }
+
+    pub fn nmi_enabled(&self) -> bool {
+        self.ctrl & CTRL_NMI_ENABLE != 0
+    }
+
+    // $2001
+    pub fn write_mask(&mut self, val: u8) {
+        self.mask = val;
+    }
+
+    // RGB color of a palette RAM value, honouring the greyscale bit of PPUMASK
+    pub fn color(&self, index: u8) -> (u8, u8, u8) {
+        let index = if self.mask & MASK_GREYSCALE != 0 { index & 0x30 } else { index };
+        palette::palette_index_to_rgb(index)
+    }
+
+    // $2002
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        if self.sprite_overflow {
+            status |= STATUS_SPRITE_OVERFLOW;
+        }
+        if self.sprite0_hit {
+            status |= STATUS_SPRITE_ZERO_HIT;
+        }
+        if self.vblank && !self.vblank_race {
+            status |= STATUS_VBLANK;
+        }
+        if self.vblank_race {
+            self.nmi_suppressed = true;
+        }
+
+        self.vblank = false;
+        self.w = false;
+        status
+    }
+
+    /*
+     * Called by the rendering loop when VBlank starts (dot 1 of the region's VBlank scanline)
+     */
+    pub fn set_vblank(&mut self) {
+        self.vblank = true;
+        self.vblank_race = true;
+    }
+
+    /*
+     * Closes the VBlank race window on the dot after set_vblank,
+     * returns true if an NMI has to be raised for this frame
+     */
+    pub fn poll_nmi(&mut self) -> bool {
+        let nmi = self.vblank_race && !self.nmi_suppressed && self.nmi_enabled();
+        self.vblank_race = false;
+        self.nmi_suppressed = false;
+        nmi
+    }
+
+    /*
+     * Advances the PPU by one dot. A visible scanline is drawn in one go once its 256th
+     * pixel is reached. Returns the NMI on the dot after VBlank starts.
+     * While rendering, the sprite fetches raise A12 once per line around dot 260, which
+     * clocks the mapper's scanline counter; see take_mapper_irq.
+     */
+    pub fn tick(&mut self, mapper: &mut dyn Mapper) -> Option<NmiSignal> {
+        self.dot += 1;
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline = (self.scanline + 1) % self.region.scanlines_per_frame();
+            if self.scanline == 0 {
+                self.frame += 1;
+            }
+        }
+
+        let vblank_scanline = self.region.vblank_scanline();
+        let pre_render_scanline = self.region.pre_render_scanline();
+        match (self.scanline, self.dot) {
+            (0..=239, 256) => self.render_scanline(mapper, self.scanline as u16),
+            (scanline, 260) if self.rendering_enabled() && (scanline < 240 || scanline == pre_render_scanline) => {
+                self.mapper_irq |= mapper.tick_irq();
+            }
+            (scanline, 1) if scanline == vblank_scanline => self.set_vblank(),
+            (scanline, 2) if scanline == vblank_scanline => return self.poll_nmi().then_some(NmiSignal),
+            (scanline, 1) if scanline == pre_render_scanline => {
+                self.vblank = false;
+                self.sprite0_hit = false;
+                self.sprite_overflow = false;
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // true once after the mapper raised an IRQ
+    pub fn take_mapper_irq(&mut self) -> bool {
+        core::mem::take(&mut self.mapper_irq)
+    }
+
+    // the last picture drawn, rows still being rendered hold the previous frame
+    pub fn frame_buffer(&self) -> &FrameBuffer {
+        &self.frame_buffer
+    }
+
+    /*
+     * Runs the PPU on its own for one frame (89,342 dots on NTSC, there is no skipped
+     * dot on odd frames) and returns the picture.
+     * Nothing is attached to take the NMI, a front end driving the CPU reads
+     * frame_buffer after each frame instead.
+     */
+    pub fn render_frame(&mut self, mapper: &mut dyn Mapper) -> &FrameBuffer {
+        let dots_per_frame = DOTS_PER_SCANLINE as u32 * self.region.scanlines_per_frame() as u32;
+        for _ in 0..dots_per_frame {
+            self.tick(mapper);
+        }
+        &self.frame_buffer
+    }
+
+    // $2007
+    pub fn read_data(&mut self, mapper: &dyn Mapper) -> u8 {
+        let addr = self.v & 0x3FFF;
+        self.increment_vram_addr();
+
+        if addr >= 0x3F00 {
+            // palette reads are not buffered, the buffer picks up the nametable byte underneath
+            self.read_buffer = self.read_vram(mapper, addr - 0x1000);
+            return self.read_vram(mapper, addr);
+        }
+
+        let data = self.read_buffer;
+        self.read_buffer = self.read_vram(mapper, addr);
+        data
+    }
+
+    // $2007
+    pub fn write_data(&mut self, mapper: &mut dyn Mapper, val: u8) {
+        let addr = self.v & 0x3FFF;
+        self.increment_vram_addr();
+        self.write_vram(mapper, addr, val);
+    }
+
+    fn increment_vram_addr(&mut self) {
+        let step = if self.ctrl & CTRL_VRAM_INCREMENT != 0 { 32 } else { 1 };
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
+    }
+
+    fn read_vram(&self, mapper: &dyn Mapper, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => mapper.chr_read(addr),
+            0x2000..=0x3EFF => self.vram[mirror_vram_addr(addr, mapper.mirroring()) as usize],
+            _ => self.palette_table[mirror_palette_addr(addr)],
+        }
+    }
+
+    fn write_vram(&mut self, mapper: &mut dyn Mapper, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => mapper.chr_write(addr, val),
+            0x2000..=0x3EFF => self.vram[mirror_vram_addr(addr, mapper.mirroring()) as usize] = val,
+            _ => self.palette_table[mirror_palette_addr(addr)] = val,
+        }
+    }
+
+    // $2003
+    pub fn write_oam_addr(&mut self, val: u8) {
+        self.oam_addr = val;
+    }
+
+    // $2004
+    pub fn read_oam_data(&self) -> u8 {
+        self.oam_data[self.oam_addr as usize]
+    }
+
+    // $2004
+    pub fn write_oam_data(&mut self, val: u8) {
+        self.oam_data[self.oam_addr as usize] = val;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    // $2005
+    pub fn write_scroll(&mut self, val: u8) {
+        if !self.w {
+            self.t = (self.t & !0x001F) | (val >> 3) as u16;
+            self.x = val & 0b111;
+        } else {
+            self.t = (self.t & !0x73E0) | (((val & 0b111) as u16) << 12) | (((val & 0xF8) as u16) << 2);
+        }
+        self.w = !self.w;
+    }
+
+    // $2006
+    pub fn write_addr(&mut self, val: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00FF) | (((val & 0x3F) as u16) << 8);
+        } else {
+            self.t = (self.t & 0xFF00) | val as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+}
+
+/*
+ * Maps a nametable address ($2000-$3EFF) to an offset into the 2KB of VRAM
+ *   Horizontal: [ A ] [ a ]    Vertical: [ A ] [ B ]
+ *               [ B ] [ b ]              [ a ] [ b ]
+ * Four-screen boards add 2KB of VRAM on the cartridge which is not emulated,
+ * they fall back to vertical mirroring.
+ */
+pub fn mirror_vram_addr(addr: u16, mirroring: Mirroring) -> u16 {
+    // $3000-$3EFF mirrors $2000-$2EFF
+    let index = (addr - 0x2000) & 0x0FFF;
+    let nametable = index / 0x400;
+    let offset = index % 0x400;
+
+    let bank = match (mirroring, nametable) {
+        (Mirroring::Horizontal, 0 | 1) => 0,
+        (Mirroring::Horizontal, _) => 1,
+        (Mirroring::Vertical | Mirroring::FourScreen, _) => nametable % 2,
+        (Mirroring::SingleScreenLower, _) => 0,
+        (Mirroring::SingleScreenUpper, _) => 1,
+    };
+
+    bank * 0x400 + offset
+}
+
+/*
+ * Maps a palette address ($3F00-$3FFF) to an index into the 32 bytes of palette RAM,
+ * $3F10/$3F14/$3F18/$3F1C are the backdrop slots of the background palettes
+ */
+fn mirror_palette_addr(addr: u16) -> usize {
+    let index = (addr & 0x1F) as usize;
+    if index >= 0x10 && index.is_multiple_of(4) {
+        index - 0x10
+    } else {
+        index
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mapper::Mapper0;
+
+    fn mapper() -> Mapper0 {
+        let mut chr_rom = vec![0; 0x2000];
+        chr_rom[0x0123] = 0x66;
+        Mapper0::new(vec![0; 0x4000], chr_rom, Mirroring::Horizontal)
+    }
+
+    fn set_addr(ppu: &mut Ppu, addr: u16) {
+        ppu.write_addr((addr >> 8) as u8);
+        ppu.write_addr(addr as u8);
+    }
+
+    #[test]
+    fn test_ctrl_sets_nametable_bits() {
+        let mut ppu = Ppu::new();
+        ppu.t = 0x7FFF;
+        ppu.write_ctrl(0b1000_0001);
+        assert_eq!(ppu.t, 0x77FF);
+        assert_eq!(ppu.nmi_enabled(), true);
+
+        ppu.write_ctrl(0b0000_0010);
+        assert_eq!(ppu.t, 0x7BFF);
+        assert_eq!(ppu.nmi_enabled(), false);
+    }
+
+    #[test]
+    fn test_scroll_two_writes() {
+        let mut ppu = Ppu::new();
+        // x = 125 = 01111 101, y = 94 = 01011 110
+        ppu.write_scroll(125);
+        assert_eq!(ppu.t, 0b000_00_00000_01111);
+        assert_eq!(ppu.x, 0b101);
+        assert_eq!(ppu.w, true);
+
+        ppu.write_scroll(94);
+        assert_eq!(ppu.t, 0b110_00_01011_01111);
+        assert_eq!(ppu.w, false);
+    }
+
+    #[test]
+    fn test_addr_two_writes() {
+        let mut ppu = Ppu::new();
+        ppu.write_addr(0x3F);
+        assert_eq!(ppu.t, 0x3F00);
+        assert_eq!(ppu.v, 0x0000);
+
+        ppu.write_addr(0x10);
+        assert_eq!(ppu.t, 0x3F10);
+        assert_eq!(ppu.v, 0x3F10);
+        assert_eq!(ppu.w, false);
+    }
+
+    #[test]
+    fn test_addr_clears_bit_14() {
+        let mut ppu = Ppu::new();
+        ppu.t = 0x4000;
+        ppu.write_addr(0xFF);
+        ppu.write_addr(0x00);
+        assert_eq!(ppu.v, 0x3F00);
+    }
+
+    #[test]
+    fn test_status_clears_vblank() {
+        let mut ppu = Ppu::new();
+        ppu.set_vblank();
+        ppu.poll_nmi();
+        assert_eq!(ppu.read_status() & STATUS_VBLANK, STATUS_VBLANK);
+        assert_eq!(ppu.read_status() & STATUS_VBLANK, 0);
+        assert_eq!(ppu.vblank, false);
+    }
+
+    #[test]
+    fn test_status_resets_latch() {
+        let mut ppu = Ppu::new();
+        ppu.write_addr(0x21);
+        assert_eq!(ppu.w, true);
+        ppu.read_status();
+        assert_eq!(ppu.w, false);
+
+        ppu.write_addr(0x23);
+        ppu.write_addr(0x45);
+        assert_eq!(ppu.v, 0x2345);
+    }
+
+    #[test]
+    fn test_vblank_nmi() {
+        let mut ppu = Ppu::new();
+        ppu.write_ctrl(CTRL_NMI_ENABLE);
+        ppu.set_vblank();
+        assert_eq!(ppu.poll_nmi(), true);
+        assert_eq!(ppu.poll_nmi(), false);
+    }
+
+    #[test]
+    fn test_nmi_enabled_during_vblank() {
+        let mut ppu = Ppu::new();
+        ppu.set_vblank();
+        assert_eq!(ppu.poll_nmi(), false);
+        assert_eq!(ppu.write_ctrl(CTRL_NMI_ENABLE), Some(NmiSignal));
+        // only the 0 to 1 transition raises it
+        assert_eq!(ppu.write_ctrl(CTRL_NMI_ENABLE), None);
+        ppu.write_ctrl(0);
+
+        // not once the flag is read
+        ppu.read_status();
+        assert_eq!(ppu.write_ctrl(CTRL_NMI_ENABLE), None);
+    }
+
+    #[test]
+    fn test_nmi_enabled_in_vblank_race_is_raised_once() {
+        let mut ppu = Ppu::new();
+        ppu.set_vblank();
+        assert_eq!(ppu.write_ctrl(CTRL_NMI_ENABLE), None);
+        assert_eq!(ppu.poll_nmi(), true);
+    }
+
+    #[test]
+    fn test_status_read_on_vblank_dot_suppresses_nmi() {
+        let mut ppu = Ppu::new();
+        ppu.write_ctrl(CTRL_NMI_ENABLE);
+        ppu.set_vblank();
+        assert_eq!(ppu.read_status() & STATUS_VBLANK, 0);
+        assert_eq!(ppu.poll_nmi(), false);
+        assert_eq!(ppu.vblank, false);
+    }
+
+    #[test]
+    fn test_scroll_and_addr_share_latch() {
+        // the mid-frame scroll split: $2006, $2005, $2005, $2006
+        let mut ppu = Ppu::new();
+        ppu.write_addr(0x04);
+        ppu.write_scroll(0x3E);
+        ppu.write_scroll(0x7D);
+        ppu.write_addr(0xEF);
+        assert_eq!(ppu.t, 0x64EF);
+        assert_eq!(ppu.v, 0x64EF);
+        assert_eq!(ppu.x, 0b101);
+    }
+
+    #[test]
+    fn test_data_write_and_buffered_read() {
+        let mut ppu = Ppu::new();
+        let mut mapper = mapper();
+        set_addr(&mut ppu, 0x2305);
+        for val in [0x11, 0x22, 0x33] {
+            ppu.write_data(&mut mapper, val);
+        }
+        assert_eq!(ppu.v, 0x2308);
+
+        set_addr(&mut ppu, 0x2305);
+        // the first read returns the stale buffer
+        ppu.read_data(&mapper);
+        assert_eq!(ppu.read_data(&mapper), 0x11);
+        assert_eq!(ppu.read_data(&mapper), 0x22);
+        assert_eq!(ppu.read_data(&mapper), 0x33);
+    }
+
+    #[test]
+    fn test_data_increment_32() {
+        let mut ppu = Ppu::new();
+        let mut mapper = mapper();
+        ppu.write_ctrl(CTRL_VRAM_INCREMENT);
+        set_addr(&mut ppu, 0x2000);
+        ppu.write_data(&mut mapper, 0x11);
+        ppu.write_data(&mut mapper, 0x22);
+        assert_eq!(ppu.v, 0x2040);
+        assert_eq!(ppu.vram[0x0000], 0x11);
+        assert_eq!(ppu.vram[0x0020], 0x22);
+    }
+
+    #[test]
+    fn test_data_reads_chr() {
+        let mut ppu = Ppu::new();
+        let mapper = mapper();
+        set_addr(&mut ppu, 0x0123);
+        ppu.read_data(&mapper);
+        assert_eq!(ppu.read_data(&mapper), 0x66);
+    }
+
+    #[test]
+    fn test_palette_read_is_not_buffered() {
+        let mut ppu = Ppu::new();
+        let mut mapper = mapper();
+        set_addr(&mut ppu, 0x2F01);
+        ppu.write_data(&mut mapper, 0x77);
+        set_addr(&mut ppu, 0x3F01);
+        ppu.write_data(&mut mapper, 0x2C);
+
+        set_addr(&mut ppu, 0x3F01);
+        assert_eq!(ppu.read_data(&mapper), 0x2C);
+        // the buffer holds the nametable byte below the palette
+        set_addr(&mut ppu, 0x2000);
+        assert_eq!(ppu.read_data(&mapper), 0x77);
+    }
+
+    #[test]
+    fn test_mirror_vram_addr_horizontal() {
+        assert_eq!(mirror_vram_addr(0x2000, Mirroring::Horizontal), 0x0000);
+        assert_eq!(mirror_vram_addr(0x2405, Mirroring::Horizontal), 0x0005);
+        assert_eq!(mirror_vram_addr(0x2805, Mirroring::Horizontal), 0x0405);
+        assert_eq!(mirror_vram_addr(0x2FFF, Mirroring::Horizontal), 0x07FF);
+        assert_eq!(mirror_vram_addr(0x3405, Mirroring::Horizontal), 0x0005);
+    }
+
+    #[test]
+    fn test_mirror_vram_addr_vertical() {
+        assert_eq!(mirror_vram_addr(0x2005, Mirroring::Vertical), 0x0005);
+        assert_eq!(mirror_vram_addr(0x2405, Mirroring::Vertical), 0x0405);
+        assert_eq!(mirror_vram_addr(0x2805, Mirroring::Vertical), 0x0005);
+        assert_eq!(mirror_vram_addr(0x2C05, Mirroring::Vertical), 0x0405);
+    }
+
+    #[test]
+    fn test_mirror_vram_addr_single_screen() {
+        assert_eq!(mirror_vram_addr(0x2C05, Mirroring::SingleScreenLower), 0x0005);
+        assert_eq!(mirror_vram_addr(0x2005, Mirroring::SingleScreenUpper), 0x0405);
+    }
+
+    #[test]
+    fn test_horizontal_mirroring_through_data() {
+        let mut ppu = Ppu::new();
+        let mut mapper = Mapper0::new(vec![0; 0x4000], vec![], Mirroring::Horizontal);
+        set_addr(&mut ppu, 0x2410);
+        ppu.write_data(&mut mapper, 0x55);
+        set_addr(&mut ppu, 0x2C10);
+        ppu.write_data(&mut mapper, 0x66);
+
+        set_addr(&mut ppu, 0x2010);
+        ppu.read_data(&mapper);
+        assert_eq!(ppu.read_data(&mapper), 0x55);
+        set_addr(&mut ppu, 0x2810);
+        ppu.read_data(&mapper);
+        assert_eq!(ppu.read_data(&mapper), 0x66);
+    }
+
+    #[test]
+    fn test_vertical_mirroring_through_data() {
+        let mut ppu = Ppu::new();
+        let mut mapper = Mapper0::new(vec![0; 0x4000], vec![], Mirroring::Vertical);
+        set_addr(&mut ppu, 0x2810);
+        ppu.write_data(&mut mapper, 0x55);
+        set_addr(&mut ppu, 0x2410);
+        ppu.write_data(&mut mapper, 0x66);
+
+        set_addr(&mut ppu, 0x2010);
+        ppu.read_data(&mapper);
+        assert_eq!(ppu.read_data(&mapper), 0x55);
+        set_addr(&mut ppu, 0x2C10);
+        ppu.read_data(&mapper);
+        assert_eq!(ppu.read_data(&mapper), 0x66);
+    }
+
+    #[test]
+    fn test_oam_write_and_read_back() {
+        let mut ppu = Ppu::new();
+        ppu.write_oam_addr(0x00);
+        for i in 0..=255u8 {
+            ppu.write_oam_data(i ^ 0xA5);
+        }
+        // 256 writes wrap the address back to where it started
+        assert_eq!(ppu.oam_addr, 0x00);
+
+        for i in 0..=255u8 {
+            ppu.write_oam_addr(i);
+            assert_eq!(ppu.read_oam_data(), i ^ 0xA5);
+        }
+    }
+
+    #[test]
+    fn test_oam_addr_wraps() {
+        let mut ppu = Ppu::new();
+        ppu.write_oam_addr(0xFF);
+        ppu.write_oam_data(0x11);
+        ppu.write_oam_data(0x22);
+        assert_eq!(ppu.oam_addr, 0x01);
+        assert_eq!(ppu.oam_data[0xFF], 0x11);
+        assert_eq!(ppu.oam_data[0x00], 0x22);
+    }
+
+    #[test]
+    fn test_oam_read_does_not_increment() {
+        let mut ppu = Ppu::new();
+        ppu.write_oam_addr(0x10);
+        ppu.write_oam_data(0x33);
+        ppu.write_oam_addr(0x10);
+        assert_eq!(ppu.read_oam_data(), 0x33);
+        assert_eq!(ppu.read_oam_data(), 0x33);
+        assert_eq!(ppu.oam_addr, 0x10);
+    }
+
+    #[test]
+    fn test_palette_backdrop_mirrors() {
+        let mut ppu = Ppu::new();
+        let mut mapper = mapper();
+        for (sprite, background) in [(0x3F10, 0x3F00), (0x3F14, 0x3F04), (0x3F18, 0x3F08), (0x3F1C, 0x3F0C)] {
+            set_addr(&mut ppu, sprite);
+            ppu.write_data(&mut mapper, sprite as u8);
+            set_addr(&mut ppu, background);
+            assert_eq!(ppu.read_data(&mapper), sprite as u8);
+        }
+
+        // and the other way around
+        set_addr(&mut ppu, 0x3F04);
+        ppu.write_data(&mut mapper, 0x2A);
+        set_addr(&mut ppu, 0x3F14);
+        assert_eq!(ppu.read_data(&mapper), 0x2A);
+    }
+
+    #[test]
+    fn test_palette_sprite_colors_are_not_mirrored() {
+        let mut ppu = Ppu::new();
+        let mut mapper = mapper();
+        set_addr(&mut ppu, 0x3F11);
+        ppu.write_data(&mut mapper, 0x16);
+        set_addr(&mut ppu, 0x3F01);
+        ppu.write_data(&mut mapper, 0x27);
+
+        set_addr(&mut ppu, 0x3F11);
+        assert_eq!(ppu.read_data(&mapper), 0x16);
+        // $3F20-$3FFF repeats the 32 bytes
+        set_addr(&mut ppu, 0x3FE1);
+        assert_eq!(ppu.read_data(&mapper), 0x27);
+    }
+
+    #[test]
+    fn test_greyscale_color() {
+        let mut ppu = Ppu::new();
+        assert_eq!(ppu.color(0x16), palette::palette_index_to_rgb(0x16));
+        ppu.write_mask(MASK_GREYSCALE);
+        assert_eq!(ppu.color(0x16), palette::palette_index_to_rgb(0x10));
+        assert_eq!(ppu.color(0x2A), palette::palette_index_to_rgb(0x20));
+    }
+
+    fn tick_until(ppu: &mut Ppu, mapper: &mut Mapper0, scanline: i16, dot: u16) -> usize {
+        let mut ticks = 0;
+        while (ppu.scanline, ppu.dot) != (scanline, dot) {
+            ppu.tick(mapper);
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn test_vblank_timing() {
+        let mut mapper = mapper();
+        let mut ppu = Ppu::new();
+        ppu.write_ctrl(0x80);
+
+        assert_eq!(tick_until(&mut ppu, &mut mapper, 241, 0), 241 * 341);
+        assert_eq!(ppu.vblank, false);
+        assert_eq!(ppu.tick(&mut mapper), None);
+        assert_eq!(ppu.vblank, true);
+        assert_eq!(ppu.tick(&mut mapper), Some(NmiSignal));
+
+        assert_eq!(tick_until(&mut ppu, &mut mapper, 261, 0), 20 * 341 - 2);
+        assert_eq!(ppu.vblank, true);
+        ppu.tick(&mut mapper);
+        assert_eq!(ppu.vblank, false);
+
+        assert_eq!(tick_until(&mut ppu, &mut mapper, 0, 0), 340);
+    }
+
+    #[test]
+    fn test_frame_length() {
+        let mut mapper = mapper();
+        let mut ppu = Ppu::new();
+        ppu.write_ctrl(0x80);
+
+        let mut nmis = vec![];
+        for dot in 0..2 * 341 * 262 {
+            if ppu.tick(&mut mapper).is_some() {
+                nmis.push(dot + 1);
+            }
+        }
+        assert_eq!(nmis, vec![241 * 341 + 2, 262 * 341 + 241 * 341 + 2]);
+        assert_eq!((ppu.scanline, ppu.dot), (0, 0));
+        assert_eq!(ppu.frame, 2);
+    }
+
+    #[test]
+    fn test_pal_frame_length() {
+        let mut mapper = mapper();
+        let mut ppu = Ppu::new_with_region(Region::PAL);
+        ppu.write_ctrl(0x80);
+
+        let mut scanlines = 0;
+        let mut nmis = vec![];
+        for dot in 0..341 * 312 {
+            if ppu.tick(&mut mapper).is_some() {
+                nmis.push(dot + 1);
+            }
+            if ppu.dot == 0 {
+                scanlines += 1;
+            }
+        }
+        assert_eq!(scanlines, 312);
+        assert_eq!((ppu.scanline, ppu.dot, ppu.frame), (0, 0, 1));
+        assert_eq!(nmis, vec![241 * 341 + 2]);
+    }
+
+    #[test]
+    fn test_pal_vblank_timing() {
+        let mut mapper = mapper();
+        let mut ppu = Ppu::new_with_region(Region::PAL);
+
+        tick_until(&mut ppu, &mut mapper, 241, 1);
+        assert_eq!(ppu.vblank, true);
+
+        // 70 lines of VBlank up to the pre-render line
+        tick_until(&mut ppu, &mut mapper, 311, 0);
+        assert_eq!(ppu.vblank, true);
+        ppu.tick(&mut mapper);
+        assert_eq!(ppu.vblank, false);
+    }
+
+    #[test]
+    fn test_dendy_vblank_starts_late() {
+        let mut mapper = mapper();
+        let mut ppu = Ppu::new_with_region(Region::Dendy);
+
+        tick_until(&mut ppu, &mut mapper, 241, 1);
+        assert_eq!(ppu.vblank, false);
+        tick_until(&mut ppu, &mut mapper, 291, 1);
+        assert_eq!(ppu.vblank, true);
+    }
+
+    #[test]
+    fn test_no_nmi_when_disabled() {
+        let mut mapper = mapper();
+        let mut ppu = Ppu::new();
+        assert_eq!((0..341 * 262).any(|_| ppu.tick(&mut mapper).is_some()), false);
+    }
+
+    #[test]
+    fn test_pre_render_line_clears_sprite_flags() {
+        let mut mapper = mapper();
+        let mut ppu = Ppu::new();
+        tick_until(&mut ppu, &mut mapper, 250, 0);
+        ppu.sprite0_hit = true;
+        ppu.sprite_overflow = true;
+
+        tick_until(&mut ppu, &mut mapper, 261, 1);
+        assert_eq!(ppu.sprite0_hit, false);
+        assert_eq!(ppu.sprite_overflow, false);
+    }
+}
@@ -0,0 +1,526 @@
+use crate::mapper::Mapper;
+use crate::ppu::*;
+use alloc::boxed::Box;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+// the picture for the front end, RGB row by row, 3 bytes per pixel
+pub struct FrameBuffer {
+    pub pixels: Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT * 3]>,
+}
+
+// brightness a light gun reacts to, white and the light greys of the palette
+const LIGHT_THRESHOLD: u8 = 0xC0;
+// lines the CRT phosphor stays lit for after the beam passes
+const LIGHT_SCANLINES: i16 = 25;
+
+impl FrameBuffer {
+    // 0-255, Rec. 601 weights
+    pub fn luminance(&self, x: usize, y: usize) -> u8 {
+        let offset = (y * SCREEN_WIDTH + x) * 3;
+        let [r, g, b] = [0, 1, 2].map(|i| self.pixels[offset + i] as u32);
+        ((r * 299 + g * 587 + b * 114) / 1000) as u8
+    }
+}
+
+impl Ppu {
+    /*
+     * Renders one visible scanline into the frame buffer.
+     * Does the loopy register updates of the surrounding dots as well: the vertical
+     * scroll bits are reloaded from t before the first line, the horizontal ones before
+     * every line, and v moves down one pixel row once the line is done.
+     */
+    pub fn render_scanline(&mut self, mapper: &dyn Mapper, scanline: u16) {
+        if !self.rendering_enabled() {
+            let backdrop = self.color(self.palette_table[0]);
+            for x in 0..SCREEN_WIDTH {
+                self.set_pixel(x, scanline as usize, backdrop);
+            }
+            return;
+        }
+
+        if scanline == 0 {
+            self.copy_vertical_bits();
+        }
+        self.copy_horizontal_bits();
+
+        let background = self.render_background(mapper, scanline as usize);
+        self.evaluate_sprites(scanline);
+        self.render_sprites_on_scanline(mapper, scanline as usize, &background);
+
+        self.increment_y();
+    }
+
+    /*
+     * Draws the background of a line, returns which pixels are opaque
+     */
+    fn render_background(&mut self, mapper: &dyn Mapper, y: usize) -> [bool; SCREEN_WIDTH] {
+        let mut opaque = [false; SCREEN_WIDTH];
+        let show_background = self.mask & MASK_SHOW_BACKGROUND != 0;
+        let fine_x = self.x as usize;
+        let pattern_table = if self.ctrl & CTRL_BACKGROUND_PATTERN != 0 { 0x1000 } else { 0 };
+
+        // 33 tiles cover the line when it is scrolled by a fine X offset
+        for tile in 0..33 {
+            let v = self.v;
+            let tile_index = self.read_vram(mapper, 0x2000 | (v & 0x0FFF)) as u16;
+            let attr_addr = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+            let attr_shift = ((v >> 4) & 4) | (v & 2);
+            let palette = (self.read_vram(mapper, attr_addr) >> attr_shift) & 0b11;
+
+            let fine_y = (v >> 12) & 0b111;
+            let lo = mapper.chr_read(pattern_table + tile_index * 16 + fine_y);
+            let hi = mapper.chr_read(pattern_table + tile_index * 16 + fine_y + 8);
+
+            for bit in 0..8 {
+                let column = tile * 8 + bit;
+                if column < fine_x || column - fine_x >= SCREEN_WIDTH {
+                    continue;
+                }
+                let x = column - fine_x;
+
+                let mut value = ((hi >> (7 - bit)) & 1) << 1 | ((lo >> (7 - bit)) & 1);
+                if !show_background || (x < 8 && self.mask & MASK_SHOW_BACKGROUND_LEFT == 0) {
+                    value = 0;
+                }
+                opaque[x] = value != 0;
+
+                let color = if value == 0 {
+                    self.palette_table[0]
+                } else {
+                    self.palette_table[(palette * 4 + value) as usize]
+                };
+                self.set_pixel(x, y, self.color(color));
+            }
+
+            self.increment_coarse_x();
+        }
+
+        opaque
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl & CTRL_SPRITE_SIZE != 0 { 16 } else { 8 }
+    }
+
+    /*
+     * Copies the first 8 sprites that cover the scanline into secondary OAM.
+     * A sprite's Y byte is one less than the first line it is drawn on.
+     */
+    fn evaluate_sprites(&mut self, scanline: u16) {
+        self.secondary_oam = [0xFF; 32];
+        self.sprite_count = 0;
+        self.sprite_zero_on_line = false;
+
+        let height = self.sprite_height();
+        for sprite in 0..64 {
+            let top = self.oam_data[sprite * 4] as u16 + 1;
+            if scanline < top || scanline >= top + height {
+                continue;
+            }
+            if self.sprite_count == 8 {
+                self.sprite_overflow = true;
+                break;
+            }
+
+            if sprite == 0 {
+                self.sprite_zero_on_line = true;
+            }
+            let slot = self.sprite_count * 4;
+            self.secondary_oam[slot..slot + 4].copy_from_slice(&self.oam_data[sprite * 4..sprite * 4 + 4]);
+            self.sprite_count += 1;
+        }
+    }
+
+    /*
+     * Draws the sprites found by evaluate_sprites. The first sprite in OAM order with an
+     * opaque pixel owns it; with its priority bit set it only shows where the background
+     * is transparent.
+     */
+    fn render_sprites_on_scanline(&mut self, mapper: &dyn Mapper, y: usize, background: &[bool; SCREEN_WIDTH]) {
+        if self.mask & MASK_SHOW_SPRITES == 0 {
+            return;
+        }
+
+        let height = self.sprite_height();
+        let mut drawn = [false; SCREEN_WIDTH];
+
+        for slot in 0..self.sprite_count {
+            let sprite = &self.secondary_oam[slot * 4..slot * 4 + 4];
+            let (sprite_y, tile, attributes, sprite_x) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+
+            let mut row = y as u16 - (sprite_y as u16 + 1);
+            if attributes & SPRITE_FLIP_VERTICAL != 0 {
+                row = height - 1 - row;
+            }
+
+            let tile_addr = if height == 16 {
+                let table = (tile as u16 & 1) * 0x1000;
+                let tile = (tile as u16 & 0xFE) + row / 8;
+                table + tile * 16 + row % 8
+            } else {
+                let table = if self.ctrl & CTRL_SPRITE_PATTERN != 0 { 0x1000 } else { 0 };
+                table + tile as u16 * 16 + row
+            };
+            let lo = mapper.chr_read(tile_addr);
+            let hi = mapper.chr_read(tile_addr + 8);
+
+            let palette = (attributes & 0b11) as usize;
+            let behind_background = attributes & SPRITE_BEHIND_BACKGROUND != 0;
+
+            for bit in 0..8 {
+                let x = sprite_x as usize + bit;
+                if x >= SCREEN_WIDTH || drawn[x] {
+                    continue;
+                }
+                if x < 8 && self.mask & MASK_SHOW_SPRITES_LEFT == 0 {
+                    continue;
+                }
+
+                let shift = if attributes & SPRITE_FLIP_HORIZONTAL != 0 { bit } else { 7 - bit };
+                let value = ((hi >> shift) & 1) << 1 | ((lo >> shift) & 1);
+                if value == 0 {
+                    continue;
+                }
+                drawn[x] = true;
+
+                // sprite 0 hit never happens on the last column
+                if slot == 0 && self.sprite_zero_on_line && background[x] && x != 255 {
+                    self.sprite0_hit = true;
+                }
+                if behind_background && background[x] {
+                    continue;
+                }
+                let color = self.palette_table[0x10 + palette * 4 + value as usize];
+                self.set_pixel(x, y, self.color(color));
+            }
+        }
+    }
+
+    /*
+     * Whether a light gun aimed at (x, y) sees light: the pixel has to be bright and
+     * drawn by the beam less than LIGHT_SCANLINES lines ago. Lines are drawn in one go
+     * on their 256th dot.
+     */
+    pub fn light_at(&self, x: u8, y: u8) -> bool {
+        let lines_since = self.scanline - y as i16;
+        let drawn = lines_since > 0 || (lines_since == 0 && self.dot >= 256);
+        let bright = self.frame_buffer.luminance(x as usize, y as usize) >= LIGHT_THRESHOLD;
+        drawn && lines_since < LIGHT_SCANLINES && bright
+    }
+
+    pub(super) fn rendering_enabled(&self) -> bool {
+        self.mask & (MASK_SHOW_BACKGROUND | MASK_SHOW_SPRITES) != 0
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, (r, g, b): (u8, u8, u8)) {
+        let offset = (y * SCREEN_WIDTH + x) * 3;
+        self.frame_buffer.pixels[offset] = r;
+        self.frame_buffer.pixels[offset + 1] = g;
+        self.frame_buffer.pixels[offset + 2] = b;
+    }
+
+    fn copy_horizontal_bits(&mut self) {
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+
+    fn copy_vertical_bits(&mut self) {
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            // wrap into the horizontally adjacent nametable
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            // wrap into the vertically adjacent nametable
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // rows 30 and 31 hold attributes, wrap without switching nametables
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::cartridge::Mirroring;
+    use crate::mapper::Mapper0;
+
+    // tile 1 is solid color 3, tile 2 has color 1 in its left half
+    fn mapper() -> Mapper0 {
+        let mut chr_rom = vec![0; 0x2000];
+        for row in 0..8 {
+            chr_rom[16 + row] = 0xFF;
+            chr_rom[16 + row + 8] = 0xFF;
+            chr_rom[32 + row] = 0xF0;
+        }
+        Mapper0::new(vec![0; 0x4000], chr_rom, Mirroring::Horizontal)
+    }
+
+    fn ppu(mapper: &mut Mapper0) -> Ppu {
+        let mut ppu = Ppu::new();
+        let writes: [(u16, u8); 6] = [
+            (0x2000, 1),
+            (0x2001, 2),
+            // top left quadrant uses palette 1
+            (0x23C0, 0b01),
+            (0x3F00, 0x0F),
+            (0x3F05, 0x16),
+            (0x3F07, 0x30),
+        ];
+        for (addr, val) in writes {
+            ppu.write_addr((addr >> 8) as u8);
+            ppu.write_addr(addr as u8);
+            ppu.write_data(mapper, val);
+        }
+        // PPUADDR shares t with the scroll, reset it like games do after loading VRAM
+        ppu.write_ctrl(0);
+        ppu.write_scroll(0);
+        ppu.write_scroll(0);
+        ppu.write_mask(MASK_SHOW_BACKGROUND | MASK_SHOW_BACKGROUND_LEFT);
+        ppu
+    }
+
+    fn render_frame(ppu: &mut Ppu, mapper: &Mapper0) {
+        for scanline in 0..SCREEN_HEIGHT as u16 {
+            ppu.render_scanline(mapper, scanline);
+        }
+    }
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> (u8, u8, u8) {
+        let offset = (y * SCREEN_WIDTH + x) * 3;
+        let pixels = &ppu.frame_buffer().pixels;
+        (pixels[offset], pixels[offset + 1], pixels[offset + 2])
+    }
+
+    #[test]
+    fn test_background_tiles() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 0, 0), palette::palette_index_to_rgb(0x30));
+        assert_eq!(pixel(&ppu, 7, 7), palette::palette_index_to_rgb(0x30));
+        assert_eq!(pixel(&ppu, 8, 0), palette::palette_index_to_rgb(0x16));
+        assert_eq!(pixel(&ppu, 12, 0), palette::palette_index_to_rgb(0x0F));
+        assert_eq!(pixel(&ppu, 0, 8), palette::palette_index_to_rgb(0x0F));
+        assert_eq!(pixel(&ppu, 255, 239), palette::palette_index_to_rgb(0x0F));
+    }
+
+    #[test]
+    fn test_render_frame() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        let pixels = &ppu.render_frame(&mut mapper).pixels;
+
+        let rgb = |x: usize, y: usize| {
+            let offset = (y * SCREEN_WIDTH + x) * 3;
+            (pixels[offset], pixels[offset + 1], pixels[offset + 2])
+        };
+        assert_eq!(rgb(0, 0), palette::palette_index_to_rgb(0x30));
+        assert_eq!(rgb(8, 0), palette::palette_index_to_rgb(0x16));
+        assert_eq!(rgb(12, 0), palette::palette_index_to_rgb(0x0F));
+        assert_eq!(rgb(255, 239), palette::palette_index_to_rgb(0x0F));
+
+        // a whole frame, the PPU is back where it started
+        assert_eq!((ppu.scanline, ppu.dot, ppu.frame), (0, 0, 1));
+    }
+
+    #[test]
+    fn test_fine_x_scroll() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        ppu.write_scroll(4);
+        ppu.write_scroll(0);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 0, 0), palette::palette_index_to_rgb(0x30));
+        assert_eq!(pixel(&ppu, 4, 0), palette::palette_index_to_rgb(0x16));
+        assert_eq!(pixel(&ppu, 8, 0), palette::palette_index_to_rgb(0x0F));
+    }
+
+    #[test]
+    fn test_fine_y_scroll() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        ppu.write_scroll(0);
+        ppu.write_scroll(4);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 0, 3), palette::palette_index_to_rgb(0x30));
+        assert_eq!(pixel(&ppu, 0, 4), palette::palette_index_to_rgb(0x0F));
+    }
+
+    #[test]
+    fn test_left_column_clipping() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        ppu.write_mask(MASK_SHOW_BACKGROUND);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 0, 0), palette::palette_index_to_rgb(0x0F));
+        assert_eq!(pixel(&ppu, 8, 0), palette::palette_index_to_rgb(0x16));
+    }
+
+    fn sprites(ppu: &mut Ppu, sprites: &[[u8; 4]]) {
+        ppu.oam_data = [0xFF; 256];
+        for (i, sprite) in sprites.iter().enumerate() {
+            ppu.oam_data[i * 4..i * 4 + 4].copy_from_slice(sprite);
+        }
+        ppu.palette_table[0x11] = 0x21;
+        ppu.palette_table[0x13] = 0x27;
+        ppu.palette_table[0x17] = 0x2A;
+        ppu.write_mask(MASK_SHOW_BACKGROUND | MASK_SHOW_BACKGROUND_LEFT | MASK_SHOW_SPRITES);
+    }
+
+    #[test]
+    fn test_sprite_position() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        sprites(&mut ppu, &[[9, 1, 0b00, 16]]);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 16, 10), palette::palette_index_to_rgb(0x27));
+        assert_eq!(pixel(&ppu, 23, 17), palette::palette_index_to_rgb(0x27));
+        assert_eq!(pixel(&ppu, 16, 9), palette::palette_index_to_rgb(0x0F));
+        assert_eq!(pixel(&ppu, 24, 10), palette::palette_index_to_rgb(0x0F));
+        assert_eq!(pixel(&ppu, 16, 18), palette::palette_index_to_rgb(0x0F));
+    }
+
+    #[test]
+    fn test_sprite_transparency_and_flip() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        // tile 2 is opaque in its left half only
+        sprites(&mut ppu, &[[19, 2, 0b00, 16], [19, 2, SPRITE_FLIP_HORIZONTAL, 32]]);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 16, 20), palette::palette_index_to_rgb(0x21));
+        assert_eq!(pixel(&ppu, 20, 20), palette::palette_index_to_rgb(0x0F));
+        assert_eq!(pixel(&ppu, 32, 20), palette::palette_index_to_rgb(0x0F));
+        assert_eq!(pixel(&ppu, 36, 20), palette::palette_index_to_rgb(0x21));
+    }
+
+    #[test]
+    fn test_sprite_vertical_flip() {
+        // tile 3 has a single opaque row at the top
+        let mut chr_rom = vec![0; 0x2000];
+        chr_rom[48] = 0xFF;
+        let mapper = Mapper0::new(vec![0; 0x4000], chr_rom, Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        sprites(&mut ppu, &[[19, 3, 0b00, 16], [19, 3, SPRITE_FLIP_VERTICAL, 32]]);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 16, 20), palette::palette_index_to_rgb(0x21));
+        assert_eq!(pixel(&ppu, 16, 27), palette::palette_index_to_rgb(0x00));
+        assert_eq!(pixel(&ppu, 32, 20), palette::palette_index_to_rgb(0x00));
+        assert_eq!(pixel(&ppu, 32, 27), palette::palette_index_to_rgb(0x21));
+    }
+
+    #[test]
+    fn test_sprite_priority() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        // the background tile 1 covers (0, 0)-(7, 7), tile 2 is opaque at (8, 0)-(11, 7)
+        sprites(&mut ppu, &[[0, 1, SPRITE_BEHIND_BACKGROUND, 0], [0, 1, 0b00, 8]]);
+        render_frame(&mut ppu, &mapper);
+
+        // behind an opaque background pixel
+        assert_eq!(pixel(&ppu, 0, 1), palette::palette_index_to_rgb(0x30));
+        // in front of the background
+        assert_eq!(pixel(&ppu, 8, 1), palette::palette_index_to_rgb(0x27));
+        assert_eq!(pixel(&ppu, 12, 1), palette::palette_index_to_rgb(0x27));
+    }
+
+    #[test]
+    fn test_lower_sprite_index_wins() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        // a transparent-priority sprite 0 still hides sprite 1 where sprite 0 is opaque
+        sprites(&mut ppu, &[[0, 1, SPRITE_BEHIND_BACKGROUND, 0], [0, 1, 0b01, 0]]);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 0, 1), palette::palette_index_to_rgb(0x30));
+    }
+
+    #[test]
+    fn test_eight_sprites_per_line() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        let line: Vec<[u8; 4]> = (0..9).map(|i| [99, 1, 0b00, i * 16 + 16]).collect();
+        sprites(&mut ppu, &line);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 16 * 8, 100), palette::palette_index_to_rgb(0x27));
+        assert_eq!(pixel(&ppu, 16 * 9, 100), palette::palette_index_to_rgb(0x0F));
+        assert_eq!(ppu.sprite_overflow, true);
+    }
+
+    #[test]
+    fn test_sprite0_hit() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        // sprite 0 starts on line 4 and overlaps the opaque background tile at (0, 0)
+        sprites(&mut ppu, &[[3, 1, 0b00, 4]]);
+
+        for scanline in 0..4 {
+            ppu.render_scanline(&mapper, scanline);
+        }
+        assert_eq!(ppu.sprite0_hit, false);
+
+        ppu.render_scanline(&mapper, 4);
+        assert_eq!(ppu.sprite0_hit, true);
+        assert_eq!(ppu.read_status() & 0b0100_0000, 0b0100_0000);
+    }
+
+    #[test]
+    fn test_sprite0_hit_needs_opaque_background() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        // sprite 0 over the transparent background, sprite 1 over the opaque tile
+        sprites(&mut ppu, &[[49, 1, 0b00, 100], [3, 1, 0b00, 4]]);
+        render_frame(&mut ppu, &mapper);
+        assert_eq!(ppu.sprite0_hit, false);
+    }
+
+    #[test]
+    fn test_sprite0_hit_behind_background() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        sprites(&mut ppu, &[[3, 1, SPRITE_BEHIND_BACKGROUND, 4]]);
+        render_frame(&mut ppu, &mapper);
+        assert_eq!(ppu.sprite0_hit, true);
+    }
+
+    #[test]
+    fn test_rendering_disabled_shows_backdrop() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        ppu.write_mask(0);
+        render_frame(&mut ppu, &mapper);
+
+        assert_eq!(pixel(&ppu, 0, 0), palette::palette_index_to_rgb(0x0F));
+    }
+}
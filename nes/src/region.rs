@@ -0,0 +1,63 @@
+use crate::cartridge::Timing;
+use serde::{Deserialize, Serialize};
+
+/*
+ * TV system the console is built for
+ *   NTSC  - Famicom and NTSC NES, 60 Hz, 262 scanlines, 3 PPU dots per CPU cycle
+ *   PAL   - PAL NES, 50 Hz, 312 scanlines, 3.2 dots per cycle
+ *   Dendy - PAL famiclones, 50 Hz, 312 scanlines, 3 dots per cycle, VBlank starts 50 lines late
+ * VBlank lasts up to the pre-render line, the last of the frame: 20 lines on NTSC and Dendy, 70 on PAL.
+ */
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Region {
+    NTSC,
+    PAL,
+    Dendy,
+}
+
+impl Region {
+    // multi-region cartridges run as NTSC
+    pub fn from_timing(timing: Timing) -> Self {
+        match timing {
+            Timing::Ntsc | Timing::MultiRegion => Region::NTSC,
+            Timing::Pal => Region::PAL,
+            Timing::Dendy => Region::Dendy,
+        }
+    }
+
+    pub fn scanlines_per_frame(self) -> i16 {
+        match self {
+            Region::NTSC => 262,
+            Region::PAL | Region::Dendy => 312,
+        }
+    }
+
+    pub fn vblank_scanline(self) -> i16 {
+        match self {
+            Region::NTSC | Region::PAL => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    // the last line of the frame
+    pub fn pre_render_scanline(self) -> i16 {
+        self.scanlines_per_frame() - 1
+    }
+
+    // PPU dots per 5 CPU cycles, PAL runs 3.2 dots per cycle
+    pub fn ppu_dots_per_5_cycles(self) -> u64 {
+        match self {
+            Region::NTSC | Region::Dendy => 15,
+            Region::PAL => 16,
+        }
+    }
+
+    pub fn cpu_clock_hz(self) -> f64 {
+        match self {
+            Region::NTSC => 1_789_773.0,
+            Region::PAL => 1_662_607.0,
+            Region::Dendy => 1_773_448.0,
+        }
+    }
+}
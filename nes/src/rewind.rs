@@ -0,0 +1,161 @@
+use crate::cpu::CPU;
+use crate::save_state::{load_state, save_state};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/*
+ * Rewind history: a save state every interval frames, the oldest is dropped
+ * once capacity states are held. rewind() hands them back newest first.
+ * An interval of 0 is taken as 1, a state every frame.
+ */
+pub struct RewindBuffer {
+    states: VecDeque<Vec<u8>>,
+    capacity: usize,
+    interval: u32,
+    frames_since_capture: u32,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, interval: u32) -> Self {
+        RewindBuffer {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+            interval: interval.max(1),
+            frames_since_capture: 0,
+        }
+    }
+
+    // called by the front end once per frame, captures every interval frames
+    pub fn frame(&mut self, cpu: &CPU) {
+        if self.frames_since_capture == 0 {
+            self.capture(cpu);
+        }
+        self.frames_since_capture = (self.frames_since_capture + 1) % self.interval;
+    }
+
+    pub fn capture(&mut self, cpu: &CPU) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(save_state(cpu));
+    }
+
+    pub fn rewind(&mut self) -> Option<CPU> {
+        let state = self.states.pop_back()?;
+        self.frames_since_capture = 0;
+        Some(load_state(&state).expect("rewind states are written by save_state"))
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::bus::{Bus, Mem};
+
+    // counts up a 16-bit value at $10 and mixes it into $12 forever
+    // e6 10 d0 02 e6 11 a5 10 45 11 85 12 4c 00 06
+    fn cpu() -> CPU {
+        let mut rom = vec![0; 0x8000];
+        rom[0x7ffc] = 0x00;
+        rom[0x7ffd] = 0x06;
+        let mut cpu = CPU::new(Bus::new_with_rom(rom));
+        cpu.power_on();
+        cpu.load(vec![
+            0xe6, 0x10, 0xd0, 0x02, 0xe6, 0x11, 0xa5, 0x10, 0x45, 0x11, 0x85, 0x12, 0x4c, 0x00,
+            0x06,
+        ]);
+        cpu
+    }
+
+    fn run_frame(cpu: &mut CPU) {
+        let frame = cpu.bus.ppu.frame;
+        while cpu.bus.ppu.frame == frame {
+            cpu.step().unwrap();
+        }
+    }
+
+    fn state(cpu: &mut CPU) -> (u16, u8, u64, u8, u8, u8) {
+        (
+            cpu.program_counter,
+            cpu.register_a,
+            cpu.elapsed_cycles(),
+            cpu.mem_read(0x10),
+            cpu.mem_read(0x11),
+            cpu.mem_read(0x12),
+        )
+    }
+
+    #[test]
+    fn test_rewind_and_replay() {
+        let mut cpu = cpu();
+        let mut rewind = RewindBuffer::new(100, 1);
+        for _ in 0..60 {
+            rewind.frame(&cpu);
+            run_frame(&mut cpu);
+        }
+        let original = state(&mut cpu);
+
+        // the 10th state back is the one from the start of frame 51
+        let mut cpu = (0..10).filter_map(|_| rewind.rewind()).last().unwrap();
+        assert_eq!(cpu.bus.ppu.frame, 50);
+        for _ in 0..10 {
+            run_frame(&mut cpu);
+        }
+
+        assert_eq!(state(&mut cpu), original);
+    }
+
+    #[test]
+    fn test_capacity_drops_oldest() {
+        let cpu = cpu();
+        let mut rewind = RewindBuffer::new(3, 1);
+        for frame in 0..5 {
+            let mut cpu = load_state(&save_state(&cpu)).unwrap();
+            cpu.register_x = frame;
+            rewind.capture(&cpu);
+        }
+        assert_eq!(rewind.len(), 3);
+
+        let frames: Vec<u8> = std::iter::from_fn(|| rewind.rewind())
+            .map(|cpu| cpu.register_x)
+            .collect();
+        assert_eq!(frames, vec![4, 3, 2]);
+        assert_eq!(rewind.is_empty(), true);
+    }
+
+    #[test]
+    fn test_capture_interval() {
+        let mut cpu = cpu();
+        let mut rewind = RewindBuffer::new(100, 4);
+        for _ in 0..10 {
+            rewind.frame(&cpu);
+            run_frame(&mut cpu);
+        }
+        // frames 0, 4 and 8
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.rewind().unwrap().bus.ppu.frame, 8);
+    }
+
+    #[test]
+    fn test_zero_interval_captures_every_frame() {
+        let mut cpu = cpu();
+        let mut rewind = RewindBuffer::new(100, 0);
+        for _ in 0..3 {
+            rewind.frame(&cpu);
+            run_frame(&mut cpu);
+        }
+        assert_eq!(rewind.len(), 3);
+    }
+}
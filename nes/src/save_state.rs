@@ -0,0 +1,112 @@
+use crate::cpu::CPU;
+use alloc::vec::Vec;
+
+const SAVE_STATE_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x53];
+// bumped whenever a serialized struct changes
+const SAVE_STATE_VERSION: u8 = 5;
+
+#[derive(Debug, PartialEq)]
+pub enum SaveStateError {
+    // the data does not start with "NESS"
+    InvalidTag,
+    // written by a different version of the emulator
+    UnsupportedVersion(u8),
+    // the state could not be decoded
+    Corrupt,
+}
+
+/*
+ * Save state layout: "NESS", version byte, then the CPU with its bus, PPU, APU and
+ * cartridge encoded with bincode. Breakpoints, watchpoints and profiling counts
+ * are not saved.
+ */
+pub fn save_state(cpu: &CPU) -> Vec<u8> {
+    let mut data = SAVE_STATE_TAG.to_vec();
+    data.push(SAVE_STATE_VERSION);
+    bincode::serialize_into(&mut data, cpu).expect("serializing to memory does not fail");
+    data
+}
+
+pub fn load_state(data: &[u8]) -> Result<CPU, SaveStateError> {
+    if data.len() < 5 || data[0..4] != SAVE_STATE_TAG {
+        return Err(SaveStateError::InvalidTag);
+    }
+    if data[4] != SAVE_STATE_VERSION {
+        return Err(SaveStateError::UnsupportedVersion(data[4]));
+    }
+    bincode::deserialize(&data[5..]).map_err(|_| SaveStateError::Corrupt)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::bus::{Bus, Mem};
+
+    // fills $0201-$02FF with 1..FF
+    // a2 00 e8 8a 9d 00 02 e0 ff d0 f7 00
+    const PROGRAM: [u8; 12] = [0xa2, 0x00, 0xe8, 0x8a, 0x9d, 0x00, 0x02, 0xe0, 0xff, 0xd0, 0xf7, 0x00];
+
+    fn cpu() -> CPU {
+        let mut rom = vec![0; 0x8000];
+        rom[0x7ffc] = 0x00;
+        rom[0x7ffd] = 0x06;
+        let mut cpu = CPU::new(Bus::new_with_rom(rom));
+        cpu.load(PROGRAM.to_vec());
+        cpu
+    }
+
+    // registers, cycles and the memory the program writes
+    fn outcome(cpu: &mut CPU) -> (u8, u8, u8, u64, Vec<u8>) {
+        let memory = (0x0200..0x0300).map(|addr| cpu.mem_read(addr)).collect();
+        (cpu.register_a, cpu.register_x, cpu.status, cpu.elapsed_cycles(), memory)
+    }
+
+    #[test]
+    fn test_round_trip_continues_identically() {
+        let mut reference = cpu();
+        reference.run().unwrap();
+
+        let mut cpu = cpu();
+        while cpu.elapsed_cycles() < 1000 {
+            cpu.step().unwrap();
+        }
+        let state = save_state(&cpu);
+
+        cpu.register_x = 0x77;
+        cpu.mem_write(0x0201, 0xee);
+        cpu.program_counter = 0x0000;
+
+        let mut cpu = load_state(&state).unwrap();
+        cpu.run().unwrap();
+
+        assert_eq!(outcome(&mut cpu), outcome(&mut reference));
+    }
+
+    #[test]
+    fn test_state_includes_ppu_and_cartridge() {
+        let mut cpu = cpu();
+        cpu.mem_write(0x6000, 0x42);
+        cpu.bus.ppu.write_ctrl(0x80);
+        cpu.bus.ppu.oam_data[0x10] = 0x99;
+        cpu.bus.apu.write_register(0x4000, 0xC0);
+
+        let mut cpu = load_state(&save_state(&cpu)).unwrap();
+
+        assert_eq!(cpu.mem_read(0x6000), 0x42);
+        assert_eq!(cpu.mem_read(0xfffd), 0x06);
+        assert_eq!(cpu.bus.ppu.ctrl, 0x80);
+        assert_eq!(cpu.bus.ppu.oam_data[0x10], 0x99);
+        assert_eq!(cpu.bus.apu.pulse1.duty, 3);
+    }
+
+    #[test]
+    fn test_invalid_states() {
+        assert_eq!(load_state(b"NES\x1a\x01").err(), Some(SaveStateError::InvalidTag));
+        assert_eq!(load_state(b"NESS\x07").err(), Some(SaveStateError::UnsupportedVersion(7)));
+
+        let mut state = save_state(&cpu());
+        state.truncate(state.len() / 2);
+        assert_eq!(load_state(&state).err(), Some(SaveStateError::Corrupt));
+    }
+}
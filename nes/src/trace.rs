@@ -0,0 +1,177 @@
+use crate::bus::Mem;
+use crate::cpu::CPU;
+use crate::disasm;
+#[cfg(feature = "debug")]
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/*
+ * CPU state before an instruction executes, printed in the nestest log layout:
+ * C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7
+ */
+#[derive(Debug, PartialEq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    pub register_a: u8,
+    pub register_x: u8,
+    pub register_y: u8,
+    pub status: u8,
+    pub stack_pointer: u8,
+    pub cycle: u64,
+}
+
+pub fn trace<M: Mem>(cpu: &mut CPU<M>) -> TraceEntry {
+    let pc = cpu.program_counter;
+    let (mnemonic, len) = disasm::disassemble_instruction(cpu, pc);
+    let bytes = (0..len as u16).map(|i| cpu.peek(pc.wrapping_add(i))).collect();
+
+    TraceEntry {
+        pc,
+        bytes,
+        mnemonic,
+        register_a: cpu.register_a,
+        register_x: cpu.register_x,
+        register_y: cpu.register_y,
+        status: cpu.status,
+        stack_pointer: cpu.stack_pointer,
+        cycle: cpu.elapsed_cycles(),
+    }
+}
+
+// the last capacity instructions that ran, for looking back after a crash
+#[cfg(feature = "debug")]
+pub struct ExecutionHistory {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+#[cfg(feature = "debug")]
+impl ExecutionHistory {
+    pub fn new(capacity: usize) -> Self {
+        ExecutionHistory {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    // the last n entries, oldest first. Takes &mut self to line the ring buffer up in one slice
+    pub fn last(&mut self, n: usize) -> &[TraceEntry] {
+        let entries = self.entries.make_contiguous();
+        &entries[entries.len() - n.min(entries.len())..]
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        // unofficial opcodes are marked by the * in front of the mnemonic
+        let mnemonic = if self.mnemonic.starts_with('*') {
+            self.mnemonic.clone()
+        } else {
+            format!(" {}", self.mnemonic)
+        };
+        write!(
+            f,
+            "{:04X}  {:<9}{:<32} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.pc,
+            bytes.join(" "),
+            mnemonic,
+            self.register_a,
+            self.register_x,
+            self.register_y,
+            self.status,
+            self.stack_pointer,
+            self.cycle
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::bus::Bus;
+
+    fn test_bus() -> Bus {
+        let mut rom = vec![0; 0x8000];
+        rom[0x7ffc] = 0x00;
+        rom[0x7ffd] = 0x06;
+        Bus::new_with_rom(rom)
+    }
+
+    #[test]
+    fn test_trace_format() {
+        let entry = TraceEntry {
+            pc: 0xC000,
+            bytes: vec![0x4C, 0xF5, 0xC5],
+            mnemonic: String::from("JMP $C5F5"),
+            register_a: 0,
+            register_x: 0,
+            register_y: 0,
+            status: 0x24,
+            stack_pointer: 0xFD,
+            cycle: 7,
+        };
+        assert_eq!(
+            entry.to_string(),
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7"
+        );
+    }
+
+    #[test]
+    fn test_trace_format_unofficial() {
+        let entry = TraceEntry {
+            pc: 0xDCB4,
+            bytes: vec![0xA7, 0x33],
+            mnemonic: String::from("*LAX $33"),
+            register_a: 0x5A,
+            register_x: 0xFF,
+            register_y: 0x01,
+            status: 0x65,
+            stack_pointer: 0xFB,
+            cycle: 14600,
+        };
+        assert_eq!(
+            entry.to_string(),
+            "DCB4  A7 33    *LAX $33                         A:5A X:FF Y:01 P:65 SP:FB CYC:14600"
+        );
+    }
+
+    #[test]
+    fn test_run_with_trace() {
+        // a2 01 ca 88 00
+        let mut cpu = CPU::new(test_bus());
+        cpu.mem_write(0x0600, 0xa2);
+        cpu.mem_write(0x0601, 0x01);
+        cpu.mem_write(0x0602, 0xca);
+        cpu.mem_write(0x0603, 0x88);
+        cpu.power_on();
+        cpu.register_a = 1;
+        cpu.register_y = 3;
+
+        let mut lines = vec![];
+        cpu.run_with_trace(|entry| lines.push(entry.to_string())).unwrap();
+
+        assert_eq!(lines, vec![
+            "0600  A2 01     LDX #$01                        A:01 X:00 Y:03 P:34 SP:FD CYC:0",
+            "0602  CA        DEX                             A:01 X:01 Y:03 P:34 SP:FD CYC:2",
+            "0603  88        DEY                             A:01 X:00 Y:03 P:36 SP:FD CYC:4",
+            "0604  00        BRK                             A:01 X:00 Y:02 P:34 SP:FD CYC:6",
+        ]);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::bus::Bus;
+use crate::cartridge::Cartridge;
+use crate::cpu::{CpuError, CPU};
+use crate::mapper;
+use crate::region::Region;
+use alloc::format;
+use alloc::vec::Vec;
+use wasm_bindgen::prelude::*;
+
+/*
+ * JavaScript API, built with wasm-pack (see the wasm feature in Cargo.toml).
+ * The /// comments below end up as JSDoc in the generated bindings.
+ */
+
+/// An NES running one cartridge.
+#[wasm_bindgen]
+pub struct WasmNes {
+    cpu: CPU,
+}
+
+#[wasm_bindgen]
+impl WasmNes {
+    /// Loads an iNES image and powers the console on. Throws when the ROM is invalid or uses an unsupported mapper.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom_data: &[u8]) -> Result<WasmNes, JsValue> {
+        let cartridge = Cartridge::from_bytes(rom_data)
+            .map_err(|err| JsValue::from_str(&format!("{:?}", err)))?;
+        let region = Region::from_timing(cartridge.header.timing);
+        let mapper = mapper::from_cartridge(cartridge)
+            .map_err(|err| JsValue::from_str(&format!("{:?}", err)))?;
+
+        let mut cpu = CPU::new(Bus::new_with_region(mapper, region));
+        cpu.power_on();
+        Ok(WasmNes { cpu })
+    }
+
+    /// Runs until the PPU finishes the next frame and returns it as 256x240 RGB pixels, 3 bytes each, row by row.
+    #[wasm_bindgen(js_name = tickFrame)]
+    pub fn tick_frame(&mut self) -> Vec<u8> {
+        let frame = self.cpu.bus.ppu.frame;
+        while self.cpu.bus.ppu.frame == frame {
+            match self.cpu.step() {
+                // BRK has already jumped through the IRQ vector, the program carries on from there
+                Ok(_) | Err(CpuError::Break) => {}
+                // a halted CPU keeps showing the last frame
+                Err(_) => break,
+            }
+        }
+        self.cpu.bus.ppu.frame_buffer().pixels.to_vec()
+    }
+
+    /// Sets the buttons held on controller 1, one bit each: A, B, Select, Start, Up, Down, Left, Right from bit 0.
+    #[wasm_bindgen(js_name = setController)]
+    pub fn set_controller(&mut self, buttons: u8) {
+        self.cpu.bus.controller1.update_buttons(buttons);
+    }
+
+    /// Returns the audio samples produced since the last call, mono floats in [0, 1] at 44.1kHz.
+    #[wasm_bindgen(js_name = getAudioSamples)]
+    pub fn get_audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.bus.apu.drain_samples()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    // NROM, 16KB of PRG-ROM that loops at $8000 forever
+    // 4c 00 80
+    fn rom() -> Vec<u8> {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0];
+        data.resize(16, 0);
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..3].copy_from_slice(&[0x4c, 0x00, 0x80]);
+        prg_rom[0x3ffc] = 0x00;
+        prg_rom[0x3ffd] = 0x80;
+        data.extend(prg_rom);
+        data.extend(vec![0; 0x2000]);
+        data
+    }
+
+    #[test]
+    fn test_tick_frame() {
+        let mut nes = WasmNes::new(&rom()).unwrap();
+        nes.set_controller(0b0000_1001);
+
+        let frame = nes.tick_frame();
+        assert_eq!(frame.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        assert_eq!(nes.cpu.bus.ppu.frame, 1);
+        assert_eq!(nes.get_audio_samples().is_empty(), false);
+        assert_eq!(nes.get_audio_samples().is_empty(), true);
+    }
+}
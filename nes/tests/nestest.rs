@@ -0,0 +1,105 @@
+use nes::bus::Bus;
+use nes::cartridge::Cartridge;
+use nes::cpu::CpuBuilder;
+use nes::mapper;
+use nes::trace::{trace, TraceEntry};
+
+/*
+ * Runs the automated part of nestest ($C000 up to the final RTS at $C66E) and
+ * compares every instruction against the reference log. Both are fixtures next to
+ * this file, tests/nestest.nes and tests/nestest.log.
+ *
+ * The PPU column and the memory values the log prints after the operands are not
+ * compared.
+ */
+const START: u16 = 0xC000;
+const END: u16 = 0xC66E;
+
+#[derive(Debug, PartialEq)]
+struct LogLine {
+    pc: u16,
+    bytes: Vec<u8>,
+    mnemonic: String,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    sp: u8,
+    cycle: u64,
+}
+
+impl LogLine {
+    fn parse(line: &str) -> LogLine {
+        let hex = |s: &str| u8::from_str_radix(s, 16).unwrap();
+        let registers: Vec<&str> = line[48..].split_whitespace().collect();
+        let field = |name: &str| {
+            let token = registers.iter().find(|token| token.starts_with(name)).unwrap();
+            &token[name.len()..]
+        };
+
+        LogLine {
+            pc: u16::from_str_radix(&line[0..4], 16).unwrap(),
+            bytes: line[6..15].split_whitespace().map(hex).collect(),
+            mnemonic: line[15..48].split_whitespace().next().unwrap().to_string(),
+            a: hex(field("A:")),
+            x: hex(field("X:")),
+            y: hex(field("Y:")),
+            p: hex(field("P:")),
+            sp: hex(field("SP:")),
+            cycle: field("CYC:").parse().unwrap(),
+        }
+    }
+
+    fn from_entry(entry: &TraceEntry) -> LogLine {
+        LogLine {
+            pc: entry.pc,
+            bytes: entry.bytes.clone(),
+            // the operand notation is left out, the log resolves it differently
+            mnemonic: entry.mnemonic.split_whitespace().next().unwrap().to_string(),
+            a: entry.register_a,
+            x: entry.register_x,
+            y: entry.register_y,
+            p: entry.status,
+            sp: entry.stack_pointer,
+            cycle: entry.cycle,
+        }
+    }
+}
+
+#[test]
+fn test_nestest_automated() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests");
+    let rom = std::fs::read(format!("{}/nestest.nes", dir)).expect("tests/nestest.nes fixture");
+    let log = std::fs::read_to_string(format!("{}/nestest.log", dir)).expect("tests/nestest.log fixture");
+
+    let cartridge = Cartridge::from_bytes(&rom).unwrap();
+    let bus = Bus::new(mapper::from_cartridge(cartridge).unwrap());
+    // automated mode starts at $C000 with the state after the reset sequence
+    let mut cpu = CpuBuilder::new(bus)
+        .with_pc(START)
+        .with_status(0x24)
+        .with_sp(0xFD)
+        .with_cycle_count(7)
+        .build();
+
+    for (number, line) in log.lines().enumerate() {
+        let entry = trace(&mut cpu);
+        let expected = LogLine::parse(line);
+        assert_eq!(
+            LogLine::from_entry(&entry),
+            expected,
+            "nestest.log diverges at line {}\nexpected: {}\nactual:   {}",
+            number + 1,
+            line,
+            entry
+        );
+
+        if entry.pc == END {
+            return;
+        }
+        if let Err(err) = cpu.step() {
+            panic!("CPU stopped at line {}: {:?}\n{}", number + 1, err, entry);
+        }
+    }
+    panic!("nestest.log ended before ${:04X}", END);
+}